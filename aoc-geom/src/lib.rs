@@ -0,0 +1,136 @@
+use std::ops::{Add, AddAssign, Index, IndexMut, Neg, Sub};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Vec3 {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl Vec3 {
+    pub fn new(x: i64, y: i64, z: i64) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+    pub fn zero() -> Vec3 {
+        Vec3::default()
+    }
+    // The sign of each component.
+    pub fn signum(self) -> Vec3 {
+        Vec3::new(self.x.signum(), self.y.signum(), self.z.signum())
+    }
+    // The distance from the origin.
+    pub fn manhattan(self) -> i64 {
+        self.x.abs() + self.y.abs() + self.z.abs()
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl Index<Axis> for Vec3 {
+    type Output = i64;
+    fn index(&self, axis: Axis) -> &i64 {
+        match axis {
+            Axis::X => &self.x,
+            Axis::Y => &self.y,
+            Axis::Z => &self.z,
+        }
+    }
+}
+
+impl IndexMut<Axis> for Vec3 {
+    fn index_mut(&mut self, axis: Axis) -> &mut i64 {
+        match axis {
+            Axis::X => &mut self.x,
+            Axis::Y => &mut self.y,
+            Axis::Z => &mut self.z,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // small enough that sums and differences don't overflow
+    fn vec3() -> impl Strategy<Value = Vec3> {
+        let c = -(1i64 << 40)..(1i64 << 40);
+        (c.clone(), c.clone(), c).prop_map(|(x, y, z)| Vec3::new(x, y, z))
+    }
+
+    proptest! {
+        #[test]
+        fn subtraction_undoes_addition(a in vec3(), b in vec3()) {
+            prop_assert_eq!(a + b - b, a);
+            prop_assert_eq!(a - b + b, a);
+            prop_assert_eq!(a + b, b + a);
+            prop_assert_eq!(a - b, a + -b);
+            prop_assert_eq!(-(-a), a);
+            let mut c = a;
+            c += b;
+            prop_assert_eq!(c, a + b);
+        }
+
+        #[test]
+        fn signum_components(a in vec3()) {
+            let s = a.signum();
+            for &axis in &Axis::ALL {
+                prop_assert!((-1..=1).contains(&s[axis]));
+                prop_assert_eq!(s[axis] * a[axis], a[axis].abs());
+            }
+        }
+
+        #[test]
+        fn manhattan_distances(a in vec3(), b in vec3()) {
+            prop_assert!(a.manhattan() >= 0);
+            prop_assert_eq!((-a).manhattan(), a.manhattan());
+            prop_assert!((a + b).manhattan() <= a.manhattan() + b.manhattan());
+        }
+    }
+
+    #[test]
+    fn indexing_by_axis() {
+        let mut v = Vec3::new(1, -2, 3);
+        assert_eq!([v[Axis::X], v[Axis::Y], v[Axis::Z]], [1, -2, 3]);
+        v[Axis::Y] = 7;
+        assert_eq!(v, Vec3::new(1, 7, 3));
+        assert_eq!(Vec3::zero().signum(), Vec3::zero());
+        assert_eq!(Vec3::new(-5, 0, 9).signum(), Vec3::new(-1, 0, 1));
+    }
+}
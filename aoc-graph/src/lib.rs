@@ -0,0 +1,454 @@
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TreeError {
+    #[error("there is no {root} in the tree")]
+    MissingRoot { root: String },
+    #[error("{root} is the root, but it has the parent {parent}")]
+    RootHasParent { root: String, parent: String },
+    #[error("{node} has more than one parent: {}", parents.join(", "))]
+    DuplicateParent { node: String, parents: Vec<String> },
+    #[error("the edges form a cycle through {witness}")]
+    CycleDetected { witness: String },
+    #[error("{root} has no parent and is not connected to the root")]
+    Disconnected { root: String },
+    #[error("there is no {node} in the tree")]
+    UnknownNode { node: String },
+    #[error("{node} is the root, so it has no parent")]
+    NoParent { node: String },
+}
+
+// Nodes are numbered in the order their names first appear.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+// A rooted tree with named nodes. Everything that queries can need is computed
+// once when it is built, indexed by node id.
+pub struct Tree {
+    names: Vec<String>,
+    ids: HashMap<String, NodeId>,
+    root: NodeId,
+    parents: Vec<Option<NodeId>>,
+    children: Vec<Vec<NodeId>>,
+    depths: Vec<usize>,
+    bfs: Vec<NodeId>,
+    // the position of each node in a depth first walk, which puts every subtree
+    // in the `subtree_size` positions starting from its root
+    preorder: Vec<usize>,
+    subtree_sizes: Vec<usize>,
+}
+
+impl Tree {
+    // Builds the tree of the (parent, child) `edges`. Every node but `root` must have
+    // exactly one parent, and all of them must be under `root`.
+    pub fn build<I: IntoIterator<Item = (String, String)>>(root: &str, edges: I) -> Result<Tree, TreeError> {
+        let mut names = Vec::new();
+        let mut ids = HashMap::new();
+        let mut intern = |name: String| {
+            *ids.entry(name.clone()).or_insert_with(|| {
+                names.push(name);
+                NodeId(names.len() as u32 - 1)
+            })
+        };
+        let edges: Vec<(NodeId, NodeId)> = edges.into_iter().map(|(p, c)| (intern(p), intern(c))).collect();
+        let mut parents: Vec<Option<NodeId>> = vec![None; names.len()];
+        let mut children = vec![Vec::new(); names.len()];
+        for &(parent, child) in &edges {
+            if let Some(first) = parents[child.index()].replace(parent) {
+                let parents = vec![names[first.index()].clone(), names[parent.index()].clone()];
+                return Err(TreeError::DuplicateParent { node: names[child.index()].clone(), parents });
+            }
+            children[parent.index()].push(child);
+        }
+        let root = match ids.get(root) {
+            Some(&id) => id,
+            None => return Err(TreeError::MissingRoot { root: root.to_owned() }),
+        };
+        if let Some(parent) = parents[root.index()] {
+            let parent = names[parent.index()].clone();
+            return Err(TreeError::RootHasParent { root: names[root.index()].clone(), parent });
+        }
+        let mut depths = vec![0; names.len()];
+        let mut bfs = vec![root];
+        let mut next = 0;
+        while let Some(&node) = bfs.get(next) {
+            for &child in &children[node.index()] {
+                depths[child.index()] = depths[node.index()] + 1;
+                bfs.push(child);
+            }
+            next += 1;
+        }
+        if bfs.len() < names.len() {
+            // going up from a node the root cannot reach ends either in a loop or at another root
+            let mut reached = vec![false; names.len()];
+            for &node in &bfs {
+                reached[node.index()] = true;
+            }
+            let mut node = NodeId(reached.iter().position(|&r| !r).unwrap_or(0) as u32);
+            let mut seen = HashSet::new();
+            while seen.insert(node) {
+                match parents[node.index()] {
+                    Some(parent) => node = parent,
+                    None => return Err(TreeError::Disconnected { root: names[node.index()].clone() }),
+                }
+            }
+            return Err(TreeError::CycleDetected { witness: names[node.index()].clone() });
+        }
+        let mut preorder = vec![0; names.len()];
+        let mut walk = Vec::with_capacity(names.len());
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            preorder[node.index()] = walk.len();
+            walk.push(node);
+            stack.extend(children[node.index()].iter().rev());
+        }
+        let mut subtree_sizes = vec![1; names.len()];
+        for &node in walk.iter().rev() {
+            if let Some(parent) = parents[node.index()] {
+                subtree_sizes[parent.index()] += subtree_sizes[node.index()];
+            }
+        }
+        Ok(Tree { names, ids, root, parents, children, depths, bfs, preorder, subtree_sizes })
+    }
+    pub fn id(&self, name: &str) -> Option<NodeId> {
+        self.ids.get(name).copied()
+    }
+    pub fn name(&self, node: NodeId) -> &str {
+        &self.names[node.index()]
+    }
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.parents[node.index()]
+    }
+    pub fn children(&self, node: NodeId) -> &[NodeId] {
+        &self.children[node.index()]
+    }
+    // The number of edges between `node` and the root.
+    pub fn depth(&self, node: NodeId) -> usize {
+        self.depths[node.index()]
+    }
+    // The number of nodes under `node`, itself included.
+    pub fn subtree_size(&self, node: NodeId) -> usize {
+        self.subtree_sizes[node.index()]
+    }
+    // Whether `a` is above `b`. A node is not its own ancestor.
+    pub fn is_ancestor(&self, a: NodeId, b: NodeId) -> bool {
+        let (a, b) = (a.index(), b.index());
+        self.preorder[a] < self.preorder[b] && self.preorder[b] < self.preorder[a] + self.subtree_sizes[a]
+    }
+    // From the parent of `node` up to the root.
+    pub fn ancestors(&self, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(node), move |&n| self.parent(n))
+    }
+    // The deepest node that is either `a` or above it, and either `b` or above it.
+    pub fn common_ancestor(&self, a: NodeId, b: NodeId) -> NodeId {
+        std::iter::once(a)
+            .chain(self.ancestors(a))
+            .find(|&n| n == b || self.is_ancestor(n, b))
+            .unwrap_or(self.root)
+    }
+    // The nodes from `a` up to their common ancestor and down to `b`, both ends included.
+    pub fn path(&self, a: NodeId, b: NodeId) -> Vec<NodeId> {
+        let common = self.common_ancestor(a, b);
+        let up_from = |n| std::iter::once(n).chain(self.ancestors(n)).take_while(move |&n| n != common);
+        let mut path: Vec<NodeId> = up_from(a).collect();
+        path.push(common);
+        let down: Vec<NodeId> = up_from(b).collect();
+        path.extend(down.into_iter().rev());
+        path
+    }
+    // The parent of the node called `name`.
+    fn parent_of(&self, name: &str) -> Result<NodeId, TreeError> {
+        let node = self.id(name).ok_or_else(|| TreeError::UnknownNode { node: name.to_owned() })?;
+        self.parent(node).ok_or_else(|| TreeError::NoParent { node: name.to_owned() })
+    }
+    // The nodes on the way from the parent of `a` to the parent of `b`, through
+    // the closest node above both.
+    pub fn transfer_path(&self, a: &str, b: &str) -> Result<Vec<String>, TreeError> {
+        let path = self.path(self.parent_of(a)?, self.parent_of(b)?);
+        Ok(path.into_iter().map(|n| self.name(n).to_owned()).collect())
+    }
+    // Every step of the transfer path is one move.
+    pub fn transfer_distance(&self, a: &str, b: &str) -> Result<usize, TreeError> {
+        Ok(self.transfer_path(a, b)?.len() - 1)
+    }
+    // Every node, level by level from the root.
+    pub fn nodes_bfs(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.bfs.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(map: &str) -> Vec<(String, String)> {
+        map.split_whitespace()
+            .map(|orbit| {
+                let mut it = orbit.split(')');
+                (it.next().unwrap().to_owned(), it.next().unwrap().to_owned())
+            })
+            .collect()
+    }
+
+    fn chain(len: usize) -> Vec<(String, String)> {
+        (0..len).map(|i| (i.to_string(), (i + 1).to_string())).collect()
+    }
+
+    #[test]
+    fn deep_chain() {
+        let len = 200_000;
+        let tree = Tree::build("0", chain(len)).unwrap();
+        let checksum: usize = tree.nodes_bfs().map(|n| tree.depth(n)).sum();
+        assert_eq!(checksum, len * (len + 1) / 2);
+        let bottom = tree.id(&len.to_string()).unwrap();
+        assert_eq!(tree.depth(bottom), len);
+        assert_eq!(tree.ancestors(bottom).count(), len);
+        assert_eq!(tree.subtree_size(tree.root()), len + 1);
+        let middle = tree.id("100000").unwrap();
+        assert_eq!(tree.path(bottom, middle).len(), len / 2 + 1);
+        assert!(tree.is_ancestor(tree.root(), bottom));
+    }
+
+    #[test]
+    fn missing_root() {
+        let error = Tree::build("COM", edges("A)B")).err().unwrap();
+        assert_eq!(error.to_string(), "there is no COM in the tree");
+    }
+
+    const EXAMPLE: &str = "COM)B B)C C)D D)E E)F B)G G)H D)I E)J J)K K)L";
+
+    fn invalid(root: &str, map: &str) -> TreeError {
+        match Tree::build(root, edges(map)) {
+            Ok(_) => panic!("{} was accepted", map),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn example_is_valid() {
+        let tree = Tree::build("COM", edges(EXAMPLE)).unwrap();
+        assert_eq!(tree.nodes_bfs().map(|n| tree.depth(n)).sum::<usize>(), 42);
+    }
+
+    #[test]
+    fn malformed_maps() {
+        match invalid("COM", "COM)B B)C D)C") {
+            TreeError::DuplicateParent { node, parents } => {
+                assert_eq!(node, "C");
+                assert_eq!(parents, ["B", "D"]);
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+        match invalid("COM", "COM)B X)Y Y)Z Z)X") {
+            TreeError::CycleDetected { witness } => assert!(["X", "Y", "Z"].contains(&witness.as_str())),
+            error => panic!("unexpected error: {}", error),
+        }
+        match invalid("COM", "COM)B B)COM") {
+            TreeError::RootHasParent { root, parent } => assert_eq!((root.as_str(), parent.as_str()), ("COM", "B")),
+            error => panic!("unexpected error: {}", error),
+        }
+        match invalid("COM", "COM)B X)Y") {
+            TreeError::Disconnected { root } => assert_eq!(root, "X"),
+            error => panic!("unexpected error: {}", error),
+        }
+        match invalid("COM", "A)B B)C") {
+            TreeError::MissingRoot { root } => assert_eq!(root, "COM"),
+            error => panic!("unexpected error: {}", error),
+        }
+        match invalid("COM", "") {
+            TreeError::MissingRoot { root } => assert_eq!(root, "COM"),
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn malformed_map_messages() {
+        assert_eq!(invalid("COM", "COM)B B)C D)C").to_string(), "C has more than one parent: B, D");
+        assert_eq!(invalid("COM", "COM)B X)Y Y)X").to_string(), "the edges form a cycle through X");
+        assert_eq!(invalid("COM", "COM)B B)COM").to_string(), "COM is the root, but it has the parent B");
+        assert_eq!(invalid("COM", "COM)B X)Y").to_string(), "X has no parent and is not connected to the root");
+    }
+
+    fn transfers() -> Tree {
+        Tree::build("COM", edges(&format!("{} K)YOU I)SAN", EXAMPLE))).unwrap()
+    }
+
+    #[test]
+    fn transfer_distance() {
+        let tree = transfers();
+        assert_eq!(tree.transfer_distance("YOU", "SAN").unwrap(), 4);
+        assert_eq!(tree.transfer_distance("SAN", "YOU").unwrap(), 4);
+        assert_eq!(tree.transfer_distance("YOU", "YOU").unwrap(), 0);
+        // E orbits D, which is above K, the body L orbits
+        assert_eq!(tree.transfer_distance("L", "E").unwrap(), 3);
+        assert_eq!(tree.transfer_distance("E", "L").unwrap(), 3);
+        assert_eq!(tree.transfer_distance("B", "H").unwrap(), 2);
+    }
+
+    #[test]
+    fn transfer_distance_errors() {
+        let tree = transfers();
+        let error = tree.transfer_distance("YOU", "NOBODY").err().unwrap();
+        assert_eq!(error.to_string(), "there is no NOBODY in the tree");
+        let error = tree.transfer_distance("NOBODY", "SAN").err().unwrap();
+        assert_eq!(error.to_string(), "there is no NOBODY in the tree");
+        let error = tree.transfer_distance("COM", "SAN").err().unwrap();
+        assert_eq!(error.to_string(), "COM is the root, so it has no parent");
+    }
+
+    //       R
+    //      / \
+    //     A   B
+    //    / \   \
+    //   C   D   F
+    //       |
+    //       E
+    fn handcrafted() -> Tree {
+        Tree::build("R", edges("R)A R)B A)C A)D D)E B)F")).unwrap()
+    }
+
+    fn names(tree: &Tree, nodes: impl IntoIterator<Item = NodeId>) -> Vec<&str> {
+        nodes.into_iter().map(|n| tree.name(n)).collect()
+    }
+
+    fn queries(tree: &Tree, names: &str) -> Vec<NodeId> {
+        names.chars().map(|c| tree.id(&c.to_string()).unwrap()).collect()
+    }
+
+    #[test]
+    fn ids_and_names() {
+        let tree = handcrafted();
+        assert_eq!(tree.name(tree.root()), "R");
+        for name in ["R", "A", "B", "C", "D", "E", "F"].iter() {
+            assert_eq!(tree.name(tree.id(name).unwrap()), *name);
+        }
+        assert_eq!(tree.id("G"), None);
+    }
+
+    #[test]
+    fn structure() {
+        let tree = handcrafted();
+        let node = |name: &str| tree.id(name).unwrap();
+        assert_eq!(names(&tree, tree.children(node("R")).iter().copied()), ["A", "B"]);
+        assert_eq!(names(&tree, tree.children(node("A")).iter().copied()), ["C", "D"]);
+        assert!(tree.children(node("E")).is_empty());
+        assert_eq!(tree.parent(node("R")), None);
+        assert_eq!(tree.parent(node("E")), Some(node("D")));
+        assert_eq!(names(&tree, tree.ancestors(node("E"))), ["D", "A", "R"]);
+        assert_eq!(tree.ancestors(node("R")).count(), 0);
+    }
+
+    #[test]
+    fn depths_and_sizes() {
+        let tree = handcrafted();
+        let depths: Vec<usize> = queries(&tree, "RABCDEF").into_iter().map(|n| tree.depth(n)).collect();
+        assert_eq!(depths, [0, 1, 1, 2, 2, 3, 2]);
+        let sizes: Vec<usize> = queries(&tree, "RABCDEF").into_iter().map(|n| tree.subtree_size(n)).collect();
+        assert_eq!(sizes, [7, 4, 2, 1, 2, 1, 1]);
+    }
+
+    #[test]
+    fn bfs_order() {
+        let tree = handcrafted();
+        assert_eq!(names(&tree, tree.nodes_bfs()), ["R", "A", "B", "C", "D", "F", "E"]);
+        let depths: Vec<usize> = tree.nodes_bfs().map(|n| tree.depth(n)).collect();
+        assert!(depths.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn ancestry() {
+        let tree = handcrafted();
+        let all = queries(&tree, "RABCDEF");
+        for &a in &all {
+            for &b in &all {
+                let above = tree.ancestors(b).any(|n| n == a);
+                assert_eq!(tree.is_ancestor(a, b), above, "{} above {}", tree.name(a), tree.name(b));
+            }
+        }
+        let pair = |names: &str| {
+            let nodes = queries(&tree, names);
+            (nodes[0], nodes[1])
+        };
+        let (a, e) = pair("AE");
+        assert!(tree.is_ancestor(a, e));
+        assert!(!tree.is_ancestor(e, a));
+        assert!(!tree.is_ancestor(a, a));
+        let (b, e) = pair("BE");
+        assert!(!tree.is_ancestor(b, e));
+    }
+
+    #[test]
+    fn common_ancestors_and_paths() {
+        let tree = handcrafted();
+        let common = |names: &str| {
+            let nodes = queries(&tree, names);
+            tree.name(tree.common_ancestor(nodes[0], nodes[1]))
+        };
+        assert_eq!(common("CE"), "A");
+        assert_eq!(common("EF"), "R");
+        assert_eq!(common("AE"), "A");
+        assert_eq!(common("EA"), "A");
+        assert_eq!(common("EE"), "E");
+        let path = |names: &str| {
+            let nodes = queries(&tree, names);
+            self::names(&tree, tree.path(nodes[0], nodes[1])).concat()
+        };
+        assert_eq!(path("CE"), "CADE");
+        assert_eq!(path("EC"), "EDAC");
+        assert_eq!(path("FC"), "FBRAC");
+        assert_eq!(path("RE"), "RADE");
+        assert_eq!(path("EE"), "E");
+    }
+
+    #[test]
+    fn transfer_path() {
+        let tree = transfers();
+        assert_eq!(tree.transfer_path("YOU", "SAN").unwrap().join(" -> "), "K -> J -> E -> D -> I");
+        assert_eq!(tree.transfer_path("SAN", "YOU").unwrap().join(" -> "), "I -> D -> E -> J -> K");
+        assert_eq!(tree.transfer_path("L", "E").unwrap(), ["K", "J", "E", "D"]);
+    }
+
+    #[test]
+    fn transfer_around_the_same_body() {
+        let tree = Tree::build("COM", edges("COM)B B)YOU B)SAN")).unwrap();
+        assert_eq!(tree.transfer_path("YOU", "SAN").unwrap(), ["B"]);
+        assert_eq!(tree.transfer_distance("YOU", "SAN").unwrap(), 0);
+        let tree = transfers();
+        assert_eq!(tree.transfer_path("C", "G").unwrap(), ["B"]);
+    }
+
+    #[test]
+    fn transfer_path_errors() {
+        let tree = transfers();
+        let error = tree.transfer_path("YOU", "NOBODY").err().unwrap();
+        assert_eq!(error.to_string(), "there is no NOBODY in the tree");
+        let error = tree.transfer_path("SAN", "COM").err().unwrap();
+        assert_eq!(error.to_string(), "COM is the root, so it has no parent");
+    }
+
+    #[test]
+    fn distances_match_paths() {
+        let tree = transfers();
+        let bodies: Vec<&str> = tree.nodes_bfs().filter(|&n| n != tree.root()).map(|n| tree.name(n)).collect();
+        for a in &bodies {
+            for b in &bodies {
+                let path = tree.transfer_path(a, b).unwrap();
+                assert_eq!(tree.transfer_distance(a, b).unwrap(), path.len() - 1);
+                assert!(path.windows(2).all(|w| {
+                    let (x, y) = (tree.id(&w[0]).unwrap(), tree.id(&w[1]).unwrap());
+                    tree.parent(x) == Some(y) || tree.parent(y) == Some(x)
+                }));
+            }
+        }
+    }
+}
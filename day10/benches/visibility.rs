@@ -0,0 +1,34 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use day10::asteroids::{visible_counts, visible_counts_hashed, Pos};
+
+// A side x side field with about half of the cells taken, scattered the same way every run.
+fn dense_field(side: i32) -> Vec<Pos> {
+    let mut state = 0x2545_f491_u32;
+    let mut asteroids = Vec::new();
+    for y in 0..side {
+        for x in 0..side {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            if state & 1 == 0 {
+                asteroids.push(Pos::new(x, y));
+            }
+        }
+    }
+    asteroids
+}
+
+fn counts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("visible counts");
+    group.sample_size(10);
+    for &side in &[6, 10, 20, 60] {
+        let field = dense_field(side);
+        group.bench_with_input(BenchmarkId::new("hashed", field.len()), &field, |b, field| b.iter(|| visible_counts_hashed(field)));
+        group.bench_with_input(BenchmarkId::new("sorted", field.len()), &field, |b, field| b.iter(|| visible_counts(field)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, counts);
+criterion_main!(benches);
@@ -0,0 +1,319 @@
+use anyhow::{anyhow, Result};
+use num_rational::Rational32;
+use std::collections::HashSet;
+use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct Pos {
+    pub x: i32,
+    pub y: i32,
+}
+impl Pos {
+    pub fn new(x: i32, y: i32) -> Pos {
+        Pos {
+            x,
+            y,
+        }
+    }
+    pub fn slope_to(self, other: Pos) -> (i32, i32) {
+        let dx = other.x - self.x;
+        let dy = other.y - self.y;
+        if dx == 0 {
+            (0, dy.signum())
+        } else {
+            let r = Rational32::new(dy.abs(), dx.abs());
+            (*r.denom()*dx.signum(), *r.numer()*dy.signum())
+        }
+    }
+    pub fn distance2_to(self, other: Pos) -> i32 {
+        let y = other.y - self.y;
+        let x = other.x - self.x;
+        y*y + x*x
+    }
+}
+
+// A direction from the station, as the reduced step between grid points. Angles
+// are ordered clockwise starting from straight up (y grows downwards).
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct Angle {
+    dx: i32,
+    dy: i32,
+}
+impl Angle {
+    pub fn new((dx, dy): (i32, i32)) -> Angle {
+        Angle { dx, dy }
+    }
+    // 0 from up (included) to down (excluded), 1 for the rest of the turn
+    fn half(self) -> u8 {
+        if self.dx > 0 || (self.dx == 0 && self.dy < 0) { 0 } else { 1 }
+    }
+}
+impl Ord for Angle {
+    fn cmp(&self, other: &Angle) -> Ordering {
+        // within a half turn, `other` is clockwise from `self` when the cross product is positive
+        let cross = self.dx*other.dy - self.dy*other.dx;
+        self.half().cmp(&other.half()).then(0.cmp(&cross))
+    }
+}
+impl PartialOrd for Angle {
+    fn partial_cmp(&self, other: &Angle) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub struct Field {
+    pub width: i32,
+    pub height: i32,
+    pub asteroids: Vec<Pos>,
+}
+
+pub fn parse_map<R: BufRead>(reader: R) -> Result<Field> {
+    let mut rows = Vec::new();
+    for line in reader.lines() {
+        rows.push(line?.trim_end_matches('\r').to_owned());
+    }
+    while rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+    let width = rows.first().map_or(0, |row| row.chars().count());
+    if width == 0 {
+        return Err(anyhow!("the map is empty"));
+    }
+    let mut asteroids = Vec::new();
+    for (y, row) in rows.iter().enumerate() {
+        let mut x = 0;
+        for c in row.chars() {
+            match c {
+                '#' => asteroids.push(Pos::new(x as i32, y as i32)),
+                '.' => {},
+                _ => return Err(anyhow!("line {}, column {}: unexpected `{}`, expected `.` or `#`", y + 1, x + 1, c)),
+            }
+            x += 1;
+        }
+        if x != width {
+            return Err(anyhow!("line {} is {} wide, the first line is {}", y + 1, x, width));
+        }
+    }
+    Ok(Field { width: width as i32, height: rows.len() as i32, asteroids })
+}
+
+pub fn parse(path: &str) -> Result<Field> {
+    let file = File::open(path).map_err(|e| anyhow!("cannot open {}: {}", path, e))?;
+    parse_map(BufReader::new(file)).map_err(|e| anyhow!("{}: {}", path, e))
+}
+
+// How many other asteroids each one sees, counting the distinct directions to them.
+pub fn visible_counts_hashed(positions: &[Pos]) -> Vec<usize> {
+    positions.iter()
+        .map(|&p| {
+            let slopes: HashSet<_> = positions.iter()
+                .filter(|&&target| target != p)
+                .map(|&target| p.slope_to(target))
+                .collect();
+            slopes.len()
+        })
+        .collect()
+}
+
+// Clockwise from up like Angle, but as a number: dy/(|dx|+|dy|) grows monotonically
+// with the angle on each side of the vertical. Collinear steps divide the same fraction,
+// which rounds to the same float, and distinct fractions stay distinct while the
+// steps are below 2^20. The key is never negative, so its bits sort like the float.
+fn angle_key(dx: i32, dy: i32) -> u64 {
+    let p = f64::from(dy)/f64::from(dx.abs() + dy.abs());
+    let key = if dx > 0 || (dx == 0 && dy < 0) { 1.0 + p } else { 3.0 - p };
+    key.to_bits()
+}
+
+// The same counts without reducing or hashing the slopes: sorting the angle keys puts
+// collinear asteroids next to each other.
+pub fn visible_counts(positions: &[Pos]) -> Vec<usize> {
+    let mut keys = Vec::with_capacity(positions.len());
+    positions.iter()
+        .map(|&p| {
+            keys.clear();
+            keys.extend(positions.iter()
+                .filter(|&&target| target != p)
+                .map(|&target| angle_key(target.x - p.x, target.y - p.y)));
+            keys.sort_unstable();
+            keys.dedup();
+            keys.len()
+        })
+        .collect()
+}
+
+// Sorting float keys is three to five times faster than hashing reduced slopes from
+// about this many asteroids on (benches/visibility.rs); below it both are instant.
+const SORT_THRESHOLD: usize = 16;
+
+// angle_key is exact while every step is below this, which any real map is
+const KEY_LIMIT: i32 = 1 << 20;
+
+// The asteroid that sees the most others, with how many it sees.
+pub fn find_best_station(positions: &[Pos]) -> Option<(Pos, usize)> {
+    let in_range = |c: i32| (0..KEY_LIMIT).contains(&c);
+    let exact = || positions.iter().all(|p| in_range(p.x) && in_range(p.y));
+    let counts = if positions.len() >= SORT_THRESHOLD && exact() {
+        visible_counts(positions)
+    } else {
+        visible_counts_hashed(positions)
+    };
+    let mut best: Option<(Pos, usize)> = None;
+    for (&p, &seen) in positions.iter().zip(&counts) {
+        if best.is_none_or(|(_, max)| max < seen) {
+            best = Some((p, seen));
+        }
+    }
+    best
+}
+
+// Every asteroid in the order the laser hits them, starting up and turning clockwise.
+// Only the nearest one in each direction is hit on every turn.
+pub fn vaporization_order(station: Pos, asteroids: &[Pos]) -> Vec<Pos> {
+    let p = station;
+    let mut slopes: HashMap<_, Vec<_>> = HashMap::new();
+    for &target in asteroids {
+        if target == p {
+            continue;
+        }
+        let angle = Angle::new(p.slope_to(target));
+        slopes.entry(angle).or_default().push(target);
+    }
+    let mut sorted: Vec<_> = slopes.into_iter().collect();
+    sorted.sort_by_key(|&(angle, _)| angle);
+    for (_, v) in &mut sorted {
+        v.sort_by_key(|target| -p.distance2_to(*target));
+    }
+    let mut order = Vec::new();
+    while !sorted.is_empty() {
+        for (_, v) in &mut sorted {
+            if let Some(target) = v.pop() {
+                order.push(target);
+            }
+        }
+        sorted.retain(|(_, v)| !v.is_empty());
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angles_turn_clockwise_from_up() {
+        // y grows downwards
+        let turn = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+        let angles: Vec<Angle> = turn.iter().map(|&step| Angle::new(step)).collect();
+        for pair in angles.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} should come before {:?}", pair[0], pair[1]);
+        }
+        let mut shuffled = angles.clone();
+        shuffled.reverse();
+        shuffled.swap(1, 5);
+        shuffled.sort();
+        assert_eq!(shuffled, angles);
+        // in between the eight directions too
+        assert!(Angle::new((1, -3)) < Angle::new((1, -2)));
+        assert!(Angle::new((-3, 1)) < Angle::new((-3, -1)));
+        assert!(Angle::new((-1, -3)) > Angle::new((-3, -1)));
+    }
+
+    #[test]
+    fn collinear_asteroids_share_a_key() {
+        let station = Pos::new(3, 3);
+        for &(near, far) in &[((4, 1), (5, -1)), ((3, 4), (3, 9)), ((1, 2), (-3, 0)), ((6, 3), (4, 3))] {
+            let (near, far) = (Pos::new(near.0, near.1), Pos::new(far.0, far.1));
+            assert_eq!(Angle::new(station.slope_to(near)), Angle::new(station.slope_to(far)));
+            assert_eq!(angle_key(near.x - 3, near.y - 3), angle_key(far.x - 3, far.y - 3));
+        }
+        assert_ne!(Angle::new(station.slope_to(Pos::new(4, 1))), Angle::new(station.slope_to(Pos::new(5, 0))));
+    }
+
+    #[test]
+    fn keys_order_like_angles() {
+        let steps: Vec<(i32, i32)> = (-4..=4).flat_map(|dx| (-4..=4).map(move |dy| (dx, dy)))
+            .filter(|&step| step != (0, 0))
+            .collect();
+        for &a in &steps {
+            for &b in &steps {
+                let by_angle = Angle::new(Pos::new(0, 0).slope_to(Pos::new(a.0, a.1)))
+                    .cmp(&Angle::new(Pos::new(0, 0).slope_to(Pos::new(b.0, b.1))));
+                assert_eq!(angle_key(a.0, a.1).cmp(&angle_key(b.0, b.1)), by_angle, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    const LARGE: &str = include_str!("../tests/data/large.txt");
+
+    fn agree(positions: &[Pos]) {
+        assert_eq!(visible_counts(positions), visible_counts_hashed(positions));
+    }
+
+    #[test]
+    fn both_counts_agree() {
+        for map in &[".#..#\n.....\n#####\n....#\n...##\n", LARGE] {
+            let positions = parse_map(map.as_bytes()).unwrap().asteroids;
+            agree(&positions);
+        }
+        let counts = visible_counts(&parse_map(LARGE.as_bytes()).unwrap().asteroids);
+        assert_eq!(counts.iter().max(), Some(&210));
+        // steps in every direction, some far apart
+        let scattered: Vec<Pos> = (0..40).map(|i| Pos::new((i * 7919) % 1000, (i * 104_729) % 997)).collect();
+        agree(&scattered);
+        agree(&[Pos::new(0, 0), Pos::new(KEY_LIMIT - 1, KEY_LIMIT - 1), Pos::new(KEY_LIMIT - 2, KEY_LIMIT - 1), Pos::new(1, 1)]);
+    }
+
+    #[test]
+    fn either_path_finds_the_station() {
+        let small = parse_map(".#..#\n.....\n#####\n....#\n...##\n".as_bytes()).unwrap().asteroids;
+        assert!(small.len() < SORT_THRESHOLD);
+        assert_eq!(find_best_station(&small), Some((Pos::new(3, 4), 8)));
+        let large = parse_map(LARGE.as_bytes()).unwrap().asteroids;
+        assert!(large.len() >= SORT_THRESHOLD);
+        assert_eq!(find_best_station(&large), Some((Pos::new(11, 13), 210)));
+        // coordinates too far apart for exact keys
+        let mut far = large.clone();
+        far.push(Pos::new(-(1 << 25), 7));
+        let best = *visible_counts_hashed(&far).iter().max().unwrap();
+        assert_eq!(find_best_station(&far), Some((far[far.len() - 1], best)));
+        assert_eq!(find_best_station(&[]), None);
+    }
+
+    fn invalid(map: &str) -> String {
+        parse_map(map.as_bytes()).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn parsing_a_field() {
+        let field = parse_map(".#.\r\n#..\r\n\n\n".as_bytes()).unwrap();
+        assert_eq!((field.width, field.height), (3, 2));
+        assert_eq!(field.asteroids, [Pos::new(1, 0), Pos::new(0, 1)]);
+        let empty_space = parse_map("...\n...\n".as_bytes()).unwrap();
+        assert!(empty_space.asteroids.is_empty());
+    }
+
+    #[test]
+    fn ragged_rows() {
+        assert_eq!(invalid("#..\n#.\n...\n"), "line 2 is 2 wide, the first line is 3");
+        assert_eq!(invalid("#..\n...\n....\n"), "line 3 is 4 wide, the first line is 3");
+        assert_eq!(invalid("#..\n\n...\n"), "line 2 is 0 wide, the first line is 3");
+    }
+
+    #[test]
+    fn invalid_characters() {
+        assert_eq!(invalid("#..\n.X.\n"), "line 2, column 2: unexpected `X`, expected `.` or `#`");
+        assert_eq!(invalid("# .\n"), "line 1, column 2: unexpected ` `, expected `.` or `#`");
+    }
+
+    #[test]
+    fn empty_maps() {
+        assert_eq!(invalid(""), "the map is empty");
+        assert_eq!(invalid("\n\n"), "the map is empty");
+        let error = parse("no such map").err().unwrap().to_string();
+        assert!(error.starts_with("cannot open no such map: "), "{}", error);
+    }
+}
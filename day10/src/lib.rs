@@ -0,0 +1,2 @@
+pub mod asteroids;
+pub mod watch;
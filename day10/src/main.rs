@@ -1,114 +1,147 @@
 use anyhow::{anyhow, Result};
-use num_rational::Rational32;
-use std::collections::HashSet;
-use std::collections::HashMap;
+use std::time::Duration;
+use day10::asteroids::{self, Pos};
+use day10::watch;
 
-#[derive(Clone, Copy, Eq, PartialEq, Debug)]
-struct Pos {
-    x: i32,
-    y: i32,
+fn part1(positions: &[Pos]) -> Result<(Pos, usize)> {
+    asteroids::find_best_station(positions).ok_or_else(|| anyhow!("there are no asteroids in the map"))
 }
-impl Pos {
-    fn new(x: i32, y: i32) -> Pos {
-        Pos {
-            x,
-            y,
-        }
-    }
-    fn slope_to(self, other: Pos) -> (i32, i32) {
-        let dx = other.x - self.x;
-        let dy = other.y - self.y;
-        if dx == 0 {
-            (0, dy.signum())
-        } else {
-            let r = Rational32::new(dy.abs(), dx.abs());
-            (*r.denom()*dx.signum(), *r.numer()*dy.signum())
-        }
-    }
-    fn distance2_to(self, other: Pos) -> i32 {
-        let y = other.y - self.y;
-        let x = other.x - self.x;
-        y*y + x*x
-    }
+
+fn part2(order: &[Pos], nth: usize) -> Result<impl std::fmt::Display> {
+    let target = order.get(nth - 1)
+        .ok_or_else(|| anyhow!("asteroid {} is never vaporized, there are only {}", nth, order.len()))?;
+    Ok(target.x*100+target.y)
 }
 
-fn parse() -> Result<Vec<Pos>> {
-    Ok(std::fs::read_to_string("input")?
-        .trim()
-        .split('\n')
-        .enumerate()
-        .flat_map(|(y,l)| l.chars().enumerate().map(move |(x,v)| (x,y,v)))
-        .filter(|(_,_,v)| *v == '#')
-        .map(|(x,y,_)| Pos::new(x as i32,y as i32))
-        .collect())
+struct Options {
+    path: String,
+    nth: usize,
+    list: bool,
+    watch: bool,
+    delay: u64,
 }
 
-fn part1(positions: Vec<Pos>) -> Result<impl std::fmt::Display> {
-    let mut max = 0;
-    let mut max_pos = Pos::new(0,0);
-    for &p in &positions {
-        let mut slopes = HashSet::new();
-        for &target in &positions {
-            if target == p {
-                continue;
-            }
-            let slope = p.slope_to(target);
-            slopes.insert(slope);
+fn parse_args() -> Result<Options> {
+    let mut path = None;
+    let mut options = Options {
+        path: String::new(),
+        nth: 200,
+        list: false,
+        watch: false,
+        delay: 50,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--nth" => match value()?.parse()? {
+                0 => return Err(anyhow!("--nth starts from 1")),
+                nth => options.nth = nth,
+            },
+            "--list" => options.list = true,
+            "--watch" => options.watch = true,
+            "--delay" => options.delay = value()?.parse()?,
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown argument `{}`", flag)),
+            _ if path.is_none() => path = Some(arg.clone()),
+            _ => return Err(anyhow!("unexpected argument `{}`, only one map can be given", arg)),
         }
-        if max < slopes.len() {
-            max = slopes.len();
-            max_pos = p;
+    }
+    options.path = path.unwrap_or_else(|| "input".to_owned());
+    Ok(options)
+}
+
+fn main() -> Result<()> {
+    let options = parse_args()?;
+    let field = asteroids::parse(&options.path)?;
+    let v = &field.asteroids;
+    let (station, seen) = part1(v)?;
+    println!("part 1: pos: {},{} - {} asteroids", station.x, station.y, seen);
+    let order = asteroids::vaporization_order(station, v);
+    if options.watch {
+        watch::sweep(&field, station, &order, Duration::from_millis(options.delay));
+    }
+    if options.list {
+        for (i, p) in order.iter().enumerate() {
+            println!("{}: {},{}", i + 1, p.x, p.y);
         }
-        max = std::cmp::max(max, slopes.len());
     }
-    Ok(format!("pos: {},{} - {} asteroids", max_pos.x, max_pos.y, max))
+    let p2 = part2(&order, options.nth)?;
+    println!("part 2: {}", p2);
+    Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use day10::asteroids::{parse_map, vaporization_order};
 
-fn part2(positions: Vec<Pos>) -> Result<impl std::fmt::Display> {
-    let p = Pos::new(14, 17);
-    let mut slopes: HashMap<_, Vec<_>> = HashMap::new();
-    for &target in &positions {
-        if target == p {
-            continue;
-        }
-        let slope = p.slope_to(target);
-        let entry = slopes.entry(slope).or_default();
-        entry.push(target);
+    const LARGE: &str = include_str!("../tests/data/large.txt");
+
+    fn asteroids(map: &str) -> Vec<Pos> {
+        parse_map(map.as_bytes()).unwrap().asteroids
     }
-    let mut sorted: Vec<_> = slopes.into_iter().collect();
-    sorted.sort_by_key(|(slope, _)| {
-        if slope.0 < 0 {
-            (3, Rational32::new(slope.1, slope.0))
-        } else if slope.0 > 0 {
-            (1, Rational32::new(slope.1, slope.0))
-        } else if slope.1 > 0 {
-            (2, Rational32::new(0, 1))
-        } else {
-            (0, Rational32::new(0, 1))
-        }
-    });
-    for (_, v) in &mut sorted {
-        v.sort_by_key(|target| -p.distance2_to(*target));
+
+    #[test]
+    fn the_large_example() {
+        let asteroids = asteroids(LARGE);
+        let (station, seen) = part1(&asteroids).unwrap();
+        assert_eq!((station, seen), (Pos::new(11, 13), 210));
+        let order = vaporization_order(station, &asteroids);
+        assert_eq!(order.len(), 299);
+        let nth = |n: usize| (order[n - 1].x, order[n - 1].y);
+        assert_eq!([nth(1), nth(2), nth(3), nth(10), nth(20)], [(11, 12), (12, 1), (12, 2), (12, 8), (16, 0)]);
+        assert_eq!([nth(50), nth(100), nth(199), nth(200), nth(201), nth(299)], [(16, 9), (10, 16), (9, 6), (8, 2), (10, 9), (11, 1)]);
+        assert_eq!(part2(&order, 200).unwrap().to_string(), "802");
     }
-    let mut count = 0;
-    'main: loop {
-        for (_, v) in &mut sorted {
-            if let Some(target) = v.pop() {
-                count+=1;
-                if count == 200 {
-                    return Ok(target.x*100+target.y);
-                }
-            }
-        }
+
+    const SMALL: &str = "\
+.#..#
+.....
+#####
+....#
+...##
+";
+
+    const SWEEP: &str = "\
+.#....#####...#..
+##...##.#####..##
+##...#...#.#####.
+..#.....#...###..
+..#.#.....#....##
+";
+
+    fn points(order: &[Pos]) -> Vec<(i32, i32)> {
+        order.iter().map(|p| (p.x, p.y)).collect()
     }
-}
 
-fn main() -> Result<()> {
-    let v = parse()?;
-    let p1 = part1(v.clone())?;
-    println!("part 1: {}", p1);
-    let p2 = part2(v)?;
-    println!("part 2: {}", p2);
-    Ok(())
+    #[test]
+    fn the_small_example() {
+        let asteroids = asteroids(SMALL);
+        let (station, seen) = part1(&asteroids).unwrap();
+        assert_eq!((station, seen), (Pos::new(3, 4), 8));
+        // the one behind (2, 2) waits for the second turn
+        let order = vaporization_order(station, &asteroids);
+        assert_eq!(points(&order), [(3, 2), (4, 0), (4, 2), (4, 3), (4, 4), (0, 2), (1, 2), (2, 2), (1, 0)]);
+        assert_eq!(part2(&order, 9).unwrap().to_string(), "100");
+        let error = part2(&order, 10).err().unwrap();
+        assert_eq!(error.to_string(), "asteroid 10 is never vaporized, there are only 9");
+    }
+
+    #[test]
+    fn the_documented_sweep() {
+        let order = vaporization_order(Pos::new(8, 3), &asteroids(SWEEP));
+        assert_eq!(points(&order), [
+            (8, 1), (9, 0), (9, 1), (10, 0), (9, 2), (11, 1), (12, 1), (11, 2), (15, 1),
+            (12, 2), (13, 2), (14, 2), (15, 2), (12, 3), (16, 4), (15, 4), (10, 4), (4, 4),
+            (2, 4), (2, 3), (0, 2), (1, 2), (0, 1), (1, 1), (5, 2), (1, 0), (5, 1),
+            (6, 1), (6, 0), (7, 0), (8, 0), (10, 1), (14, 0), (16, 1), (13, 3), (14, 3),
+        ]);
+    }
+
+    #[test]
+    fn a_lone_station_vaporizes_nothing() {
+        let order = vaporization_order(Pos::new(0, 0), &[Pos::new(0, 0)]);
+        assert!(order.is_empty());
+        assert_eq!(part2(&order, 1).err().unwrap().to_string(), "asteroid 1 is never vaporized, there are only 0");
+    }
 }
@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::time::Duration;
+
+use crate::asteroids::{Field, Pos};
+
+// The cells on the way from `station` through `target` to the edge of the grid.
+pub fn laser(station: Pos, target: Pos, (width, height): (i32, i32)) -> Vec<Pos> {
+    let (dx, dy) = station.slope_to(target);
+    let mut cells = Vec::new();
+    let mut p = Pos::new(station.x + dx, station.y + dy);
+    while p.x >= 0 && p.x < width && p.y >= 0 && p.y < height {
+        cells.push(p);
+        p = Pos::new(p.x + dx, p.y + dy);
+    }
+    cells
+}
+
+// One line per row: `X` for the station, `#` for the asteroids left, `*` for the
+// laser where it does not hit one, and `.` elsewhere.
+pub fn grid(size: (i32, i32), station: Pos, asteroids: &HashSet<Pos>, laser: &[Pos]) -> Vec<String> {
+    let beam: HashSet<Pos> = laser.iter().copied().collect();
+    (0..size.1)
+        .map(|y| {
+            (0..size.0)
+                .map(|x| {
+                    let p = Pos::new(x, y);
+                    if p == station {
+                        'X'
+                    } else if asteroids.contains(&p) {
+                        '#'
+                    } else if beam.contains(&p) {
+                        '*'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Removes the asteroids in `order` one per frame, with the laser pointing at the last one.
+pub fn sweep(field: &Field, station: Pos, order: &[Pos], delay: Duration) {
+    let size = (field.width, field.height);
+    let mut left: HashSet<Pos> = field.asteroids.iter().copied().filter(|&p| p != station).collect();
+    let mut stdout = std::io::stdout();
+    for (i, &target) in order.iter().enumerate() {
+        left.remove(&target);
+        let mut screen = "\x1b[H\x1b[2J".to_owned();
+        for line in grid(size, station, &left, &laser(station, target, size)) {
+            screen.push_str(&line);
+            screen.push('\n');
+        }
+        screen.push_str(&format!("vaporized {}: {},{}\n", i + 1, target.x, target.y));
+        // a closed terminal only loses the animation
+        let _ = stdout.write_all(screen.as_bytes()).and_then(|()| stdout.flush());
+        std::thread::sleep(delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asteroids::{parse_map, vaporization_order};
+
+    const SWEEP: &str = "\
+.#....#####...#..
+##...##.#####..##
+##...#...#.#####.
+..#.....#...###..
+..#.#.....#....##
+";
+
+    // The grid after the first `n` asteroids are vaporized.
+    fn frame(n: usize) -> Vec<String> {
+        let field = parse_map(SWEEP.as_bytes()).unwrap();
+        let station = Pos::new(8, 3);
+        let order = vaporization_order(station, &field.asteroids);
+        let mut left: HashSet<Pos> = field.asteroids.iter().copied().filter(|&p| p != station).collect();
+        for p in &order[..n] {
+            left.remove(p);
+        }
+        grid((field.width, field.height), station, &left, &laser(station, order[n - 1], (field.width, field.height)))
+    }
+
+    #[test]
+    fn the_first_shot() {
+        assert_eq!(frame(1), [
+            ".#....#####...#..",
+            "##...##.*####..##",
+            "##...#..*#.#####.",
+            "..#.....X...###..",
+            "..#.#.....#....##",
+        ]);
+    }
+
+    #[test]
+    fn the_ninth_shot() {
+        // the puzzle's first illustration, once its nine asteroids are gone
+        assert_eq!(frame(9), [
+            ".#....###.....#..",
+            "##...##...#....*#",
+            "##...#......####.",
+            "..#.....X...###..",
+            "..#.#.....#....##",
+        ]);
+    }
+
+    #[test]
+    fn the_last_shot() {
+        assert_eq!(frame(36), [
+            ".................",
+            ".................",
+            ".................",
+            "........X********",
+            ".................",
+        ]);
+    }
+
+    #[test]
+    fn lasers_reach_the_edge() {
+        let size = (10, 6);
+        let station = Pos::new(2, 4);
+        let cells = |target: Pos| laser(station, target, size).into_iter().map(|p| (p.x, p.y)).collect::<Vec<_>>();
+        assert_eq!(cells(Pos::new(4, 2)), [(3, 3), (4, 2), (5, 1), (6, 0)]);
+        assert_eq!(cells(Pos::new(2, 5)), [(2, 5)]);
+        assert_eq!(cells(Pos::new(0, 3)), [(0, 3)]);
+        assert_eq!(cells(Pos::new(8, 1)), [(4, 3), (6, 2), (8, 1)]);
+    }
+}
@@ -1,91 +1,321 @@
 use anyhow::Result;
 use anyhow::anyhow;
-use std::collections::HashMap;
+use anyhow::Context;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use intcode::Vm;
+
+mod png;
+mod robot;
+mod watch;
+
+use robot::{Direction, Robot};
+
+// the color of each panel, with y growing upwards
+type Panels = HashMap<(i64, i64), i64>;
 
 fn parse() -> Result<Vec<i64>> {
-    std::fs::read_to_string("input")?
-        .trim()
-        .split(',')
-        .map(|s| s.parse().map_err(std::convert::From::from))
-        .collect()
-}
-
-const DIRS: [(i64, i64);4] = [
-    (0,1),
-    (1,0),
-    (0,-1),
-    (-1,0),
-];
-
-fn paint_area(vm: &mut intcode::Vm, area: &mut HashMap<(i64, i64), i64>) -> Result<()>{
-    let mut cur_pos = (0, 0);
-    let mut cur_dir = 0;
-    while vm.is_running() {
-        let v = area.entry(cur_pos).or_insert(0);
-        vm.add_inputs(&[*v]);
-        let color = if let Some(c) = vm.run_until_output()? {
-            c
-        } else {
-            break;
+    Ok(intcode::parse_program(&std::fs::read_to_string("input")?)?)
+}
+
+// What drives the robot: the intcode program, or a script standing in for it.
+trait Brain {
+    // The color to paint and the turn to take, given the color of the panel under the
+    // robot. None once the program halted.
+    fn decide(&mut self, color: i64) -> Result<Option<(i64, i64)>>;
+}
+
+impl Brain for Vm {
+    fn decide(&mut self, color: i64) -> Result<Option<(i64, i64)>> {
+        if !self.is_running() {
+            return Ok(None);
+        }
+        self.add_inputs(&[color]);
+        let paint = match self.run_until_output()? {
+            Some(c) => c,
+            None => return Ok(None),
         };
-        area.insert(cur_pos, color);
-        let new_dir = vm.run_until_output()?.ok_or_else(||anyhow!("missing dir"))?;
-        match new_dir {
-            0 => {
-                cur_dir = (cur_dir + DIRS.len() - 1) % DIRS.len();
-            },
-            1 => {
-                cur_dir = (cur_dir + DIRS.len() + 1) % DIRS.len();
-            },
-            _ => {
-                return Err(anyhow!("invalid direction received"));
-            },
+        let turn = self.run_until_output()?
+            .ok_or_else(|| anyhow!("the program halted after painting {} without a turn", paint))?;
+        Ok(Some((paint, turn)))
+    }
+}
+
+struct PaintResult {
+    // the color of every panel painted, or set before starting
+    panels: Panels,
+    // how many panels were painted at least once
+    painted: usize,
+    // every position of the robot, from the start
+    path: Vec<(i64, i64)>,
+}
+
+// `on_move` sees the hull and the robot before every move and once the program halts.
+fn run_painter(
+    brain: &mut dyn Brain,
+    start_pos: (i64, i64),
+    start_color: i64,
+    on_move: &mut dyn FnMut(&Panels, &Robot),
+) -> Result<PaintResult> {
+    let mut robot = Robot::new(start_pos, Direction::Up);
+    let mut panels = HashMap::new();
+    panels.insert(start_pos, start_color);
+    let mut painted = HashSet::new();
+    let mut path = vec![start_pos];
+    on_move(&panels, &robot);
+    loop {
+        let (moves, at) = (path.len() - 1, robot.pos);
+        let context = || format!("the robot stopped after {} moves at {:?}", moves, at);
+        let decision = brain.decide(panels.get(&robot.pos).copied().unwrap_or(0)).with_context(context)?;
+        let (color, turn) = match decision {
+            Some(decision) => decision,
+            None => break,
+        };
+        if color != 0 && color != 1 {
+            return Err(anyhow!("invalid color {}, expected 0 (black) or 1 (white)", color)).with_context(context);
         }
-        let dir = DIRS[cur_dir];
-        cur_pos = (cur_pos.0+dir.0, cur_pos.1+dir.1);
+        panels.insert(robot.pos, color);
+        painted.insert(robot.pos);
+        robot.dir = robot.dir.turn(turn).with_context(context)?;
+        robot.advance();
+        path.push(robot.pos);
+        on_move(&panels, &robot);
     }
-    Ok(())
+    Ok(PaintResult { panels, painted: painted.len(), path })
 }
-fn part1(v: Vec<i64>) -> Result<impl std::fmt::Display> {
-    let mut vm = intcode::Vm::new(v);
-    let mut area = HashMap::new();
-    paint_area(&mut vm, &mut area)?;
-    Ok(area.len())
+
+fn run_program(v: Vec<i64>, options: &Options, default_color: i64) -> Result<PaintResult> {
+    let start_color = options.start_color.unwrap_or(default_color);
+    let result = run_painter(&mut Vm::new(v), options.start_pos, start_color, &mut |_, _| {})?;
+    if options.verbose {
+        // the path always has the start
+        let (x, y) = result.path[result.path.len() - 1];
+        println!("the robot moved {} times, ending at ({}, {})", result.path.len() - 1, x, y);
+    }
+    Ok(result)
+}
+
+fn part1(v: Vec<i64>, options: &Options) -> Result<impl std::fmt::Display> {
+    Ok(run_program(v, options, 0)?.painted)
+}
+
+// The painted panels, top row first, with true for white.
+struct Canvas {
+    rows: Vec<Vec<bool>>,
+}
+
+impl Canvas {
+    fn width(&self) -> usize {
+        self.rows.first().map_or(0, |row| row.len())
+    }
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+    fn pixel(&self, x: usize, y: usize) -> bool {
+        self.rows[y][x]
+    }
+}
+
+impl std::fmt::Display for Canvas {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for row in &self.rows {
+            writeln!(f, "{}", row.iter().map(|&p| if p { '█' } else { ' ' }).collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+fn render(area: &Panels) -> Canvas {
+    let (left, right) = match (area.keys().map(|(x,_)|*x).min(), area.keys().map(|(x,_)|*x).max()) {
+        (Some(left), Some(right)) => (left, right),
+        _ => return Canvas { rows: Vec::new() },
+    };
+    let bottom = area.keys().map(|(_,y)|*y).min().unwrap_or(0);
+    let top = area.keys().map(|(_,y)|*y).max().unwrap_or(0);
+    let mut rows = vec![vec![false; (right-left+1) as usize]; (top-bottom+1) as usize];
+    for (&(x,y),&v) in area {
+        // the robot paints with y going up
+        rows[(top - y) as usize][(x - left) as usize] = v != 0;
+    }
+    Canvas { rows }
 }
 
-fn part2(v: Vec<i64>) -> Result<impl std::fmt::Display> {
-    let mut vm = intcode::Vm::new(v);
-    let mut area = HashMap::new();
-    area.insert((0,0), 1);
-    paint_area(&mut vm, &mut area)?;
-    let left = *area.keys().map(|(x,_)|x).min().unwrap();
-    let bottom = *area.keys().map(|(_,y)|y).min().unwrap();
-    let right = *area.keys().map(|(x,_)|x).max().unwrap();
-    let top = *area.keys().map(|(_,y)|y).max().unwrap();
-    let mut canvas = Vec::new();
-    canvas.resize_with((top-bottom+1) as usize, Vec::new);
-    for row in &mut canvas {
-        row.resize((right-left+1) as usize,' ');
+// Prints the hull and the letters painted on it.
+fn show(panels: &Panels, options: &Options) -> Result<String> {
+    let canvas = render(panels);
+    print!("{}", canvas);
+    if let Some(path) = &options.png {
+        png::write(path, &canvas, options.scale)?;
     }
-    for ((x,y),v) in area {
-        let x = (x - left) as usize;
-        let y = (y - bottom) as usize;
-        canvas[y][x] = if v == 0 { ' ' } else { '█' };
+    let reading = ocr::read(&canvas.rows);
+    for art in &reading.unknown {
+        println!("unknown letter:\n{}", art);
     }
+    Ok(reading.text)
+}
+
+fn part2(v: Vec<i64>, options: &Options) -> Result<impl std::fmt::Display> {
+    show(&run_program(v, options, 1)?.panels, options)
+}
 
-    let mut res = "\n".to_owned();
-    for row in canvas.into_iter().rev() {
-        res.extend(row);
-        res.push('\n');
+// Paints the registration identifier, drawing every move.
+fn watch(v: Vec<i64>, options: &Options) -> Result<()> {
+    let mut watcher = watch::Watcher::new(options.start_pos, Duration::from_millis(options.delay));
+    let start_color = options.start_color.unwrap_or(1);
+    let mut draw = |panels: &Panels, robot: &Robot| watcher.draw(panels, robot);
+    let result = run_painter(&mut Vm::new(v), options.start_pos, start_color, &mut draw)?;
+    // the whole hull replaces the view
+    print!("\x1b[H\x1b[2J");
+    let text = show(&result.panels, options)?;
+    println!("{}", text);
+    Ok(())
+}
+
+struct Options {
+    png: Option<String>,
+    scale: usize,
+    start_color: Option<i64>,
+    start_pos: (i64, i64),
+    watch: bool,
+    delay: u64,
+    verbose: bool,
+}
+
+fn parse_args() -> Result<Options> {
+    let mut options = Options {
+        png: None,
+        scale: 8,
+        start_color: None,
+        start_pos: (0, 0),
+        watch: false,
+        delay: 20,
+        verbose: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--png" => options.png = Some(value()?),
+            "--scale" => options.scale = value()?.parse::<usize>()?.max(1),
+            "--watch" => options.watch = true,
+            "--delay" => options.delay = value()?.parse()?,
+            "--verbose" => options.verbose = true,
+            "--start-color" => match value()?.as_str() {
+                "0" => options.start_color = Some(0),
+                "1" => options.start_color = Some(1),
+                color => return Err(anyhow!("invalid start color `{}`, expected 0 or 1", color)),
+            },
+            "--start-pos" => {
+                let pos = value()?;
+                let (x, y) = pos.split_once(',').ok_or_else(|| anyhow!("invalid start position `{}`, expected x,y", pos))?;
+                options.start_pos = (x.trim().parse()?, y.trim().parse()?);
+            },
+            _ => return Err(anyhow!("unknown argument `{}`", arg)),
+        }
     }
-    Ok(res)
+    Ok(options)
 }
 
 fn main() -> Result<()> {
+    let options = parse_args()?;
     let v = parse()?;
-    let p1 = part1(v.clone())?;
+    if options.watch {
+        return watch(v, &options);
+    }
+    let p1 = part1(v.clone(), &options)?;
     println!("part 1: {}", p1);
-    let p2 = part2(v)?;
+    let p2 = part2(v, &options)?;
     println!("part 2: {}", p2);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // Answers with canned decisions, keeping the colors it was shown.
+    struct Script {
+        decisions: VecDeque<(i64, i64)>,
+        seen: Vec<i64>,
+    }
+
+    impl Script {
+        fn new(decisions: &[(i64, i64)]) -> Script {
+            Script { decisions: decisions.iter().copied().collect(), seen: Vec::new() }
+        }
+    }
+
+    impl Brain for Script {
+        fn decide(&mut self, color: i64) -> Result<Option<(i64, i64)>> {
+            self.seen.push(color);
+            Ok(self.decisions.pop_front())
+        }
+    }
+
+    #[test]
+    fn painting_the_example() {
+        let mut script = Script::new(&[(1, 0), (0, 0), (1, 0), (1, 0), (0, 1), (1, 0), (1, 0)]);
+        let mut moves = 0;
+        let result = run_painter(&mut script, (0, 0), 0, &mut |_, _| moves += 1).unwrap();
+        assert_eq!(result.painted, 6);
+        assert_eq!(moves, 8);
+        let expected: Panels = vec![
+            ((0, 0), 0), ((-1, 0), 0), ((-1, -1), 1), ((0, -1), 1), ((1, 0), 1), ((1, 1), 1),
+        ].into_iter().collect();
+        assert_eq!(result.panels, expected);
+        assert_eq!(result.path, [(0, 0), (-1, 0), (-1, -1), (0, -1), (0, 0), (1, 0), (1, 1), (0, 1)]);
+        // the robot came back to the first panel after painting it white
+        assert_eq!(script.seen, [0, 0, 0, 0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn starting_elsewhere_on_white() {
+        let mut script = Script::new(&[(0, 1), (1, 1)]);
+        let result = run_painter(&mut script, (5, -3), 1, &mut |_, _| {}).unwrap();
+        assert_eq!(script.seen, [1, 0, 0]);
+        assert_eq!(result.painted, 2);
+        let expected: Panels = vec![((5, -3), 0), ((6, -3), 1)].into_iter().collect();
+        assert_eq!(result.panels, expected);
+        assert_eq!(result.path, [(5, -3), (6, -3), (6, -4)]);
+    }
+
+    #[test]
+    fn an_unpainted_start_still_has_its_color() {
+        let result = run_painter(&mut Script::new(&[]), (0, 0), 1, &mut |_, _| {}).unwrap();
+        assert_eq!(result.painted, 0);
+        assert_eq!(render(&result.panels).to_string(), "█\n");
+    }
+
+    fn failure(program: &str) -> String {
+        let mut vm = Vm::new(intcode::parse_program(program).unwrap());
+        let error = run_painter(&mut vm, (0, 0), 0, &mut |_, _| {}).err().unwrap();
+        format!("{:#}", error)
+    }
+
+    #[test]
+    fn protocol_errors() {
+        assert_eq!(failure("3,100,3,100,99"), "the robot stopped after 0 moves at (0, 0): The VM is waiting for input at pc 2 (relative base 0), but none is available");
+        assert_eq!(failure("3,100,104,1,99"), "the robot stopped after 0 moves at (0, 0): the program halted after painting 1 without a turn");
+        assert_eq!(failure("3,100,104,2,104,0,99"), "the robot stopped after 0 moves at (0, 0): invalid color 2, expected 0 (black) or 1 (white)");
+        assert_eq!(failure("3,100,104,1,104,5,99"), "the robot stopped after 0 moves at (0, 0): invalid turn command 5, expected 0 (left) or 1 (right)");
+    }
+
+    #[test]
+    fn errors_tell_where_the_robot_stopped() {
+        let paint_then = |rest: &str| format!("3,100,104,1,104,1,3,100,104,1,104,0,{}", rest);
+        assert_eq!(failure(&paint_then("3,100,104,-1,104,0,99")), "the robot stopped after 2 moves at (1, 1): invalid color -1, expected 0 (black) or 1 (white)");
+        assert_eq!(failure(&paint_then("3,100,104,0,99")), "the robot stopped after 2 moves at (1, 1): the program halted after painting 0 without a turn");
+    }
+
+    #[test]
+    fn rendering_a_tiny_area() {
+        let area: Panels = vec![((-1, 0), 1), ((0, 0), 0), ((1, -1), 1)].into_iter().collect();
+        let canvas = render(&area);
+        assert_eq!((canvas.width(), canvas.height()), (3, 2));
+        assert_eq!(canvas.rows, [[true, false, false], [false, false, true]]);
+        assert_eq!(canvas.to_string(), "█  \n  █\n");
+        let empty = render(&Panels::new());
+        assert_eq!((empty.width(), empty.height()), (0, 0));
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use anyhow::anyhow;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufWriter;
+
+use crate::Canvas;
+
+// Writes `canvas` in black and white, each panel a `scale`x`scale` square.
+pub fn write(path: &str, canvas: &Canvas, scale: usize) -> Result<()> {
+    if canvas.width() == 0 {
+        return Err(anyhow!("nothing was painted, there is no image to write"));
+    }
+    let too_large = || anyhow!("a {}x{} hull scaled {} times is too large for a png", canvas.width(), canvas.height(), scale);
+    let side = |panels: usize| panels.checked_mul(scale).filter(|&side| u32::try_from(side).is_ok());
+    let (width, height) = match (side(canvas.width()), side(canvas.height())) {
+        (Some(width), Some(height)) => (width, height),
+        _ => return Err(too_large()),
+    };
+    let mut pixels = Vec::with_capacity(width.checked_mul(height).ok_or_else(too_large)?);
+    for y in 0..height {
+        pixels.extend((0..width).map(|x| if canvas.pixel(x / scale, y / scale) { 255 } else { 0 }));
+    }
+    let file = File::create(path).map_err(|e| anyhow!("cannot write {}: {}", path, e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("day11-{}-{}.png", name, std::process::id()));
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn scaled_pixels() {
+        let path = temp_path("scaled");
+        let canvas = Canvas { rows: vec![vec![true, false, false], vec![false, false, true]] };
+        write(&path, &canvas, 4).unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((info.width, info.height), (12, 8));
+        assert_eq!((info.color_type, info.bit_depth), (png::ColorType::Grayscale, png::BitDepth::Eight));
+        let pixel = |x: usize, y: usize| pixels[x + 12 * y];
+        assert_eq!(pixel(0, 0), 255);
+        assert_eq!(pixel(3, 3), 255);
+        assert_eq!(pixel(4, 0), 0);
+        assert_eq!(pixel(0, 4), 0);
+        assert_eq!(pixel(8, 4), 255);
+        assert_eq!(pixel(11, 7), 255);
+        assert_eq!(pixel(7, 7), 0);
+    }
+
+    #[test]
+    fn nothing_to_write() {
+        let error = write(&temp_path("empty"), &Canvas { rows: Vec::new() }, 8).unwrap_err();
+        assert_eq!(error.to_string(), "nothing was painted, there is no image to write");
+    }
+
+    #[test]
+    fn oversized_images() {
+        let canvas = Canvas { rows: vec![vec![true; 2]] };
+        let path = temp_path("oversized");
+        for &scale in &[u32::MAX as usize, usize::MAX] {
+            let error = write(&path, &canvas, scale).unwrap_err();
+            assert_eq!(error.to_string(), format!("a 2x1 hull scaled {} times is too large for a png", scale));
+        }
+        assert!(!std::path::Path::new(&path).exists());
+    }
+}
@@ -0,0 +1,108 @@
+use thiserror::Error;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Direction {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid turn command {0}, expected 0 (left) or 1 (right)")]
+pub struct InvalidTurn(pub i64);
+
+impl Direction {
+    pub fn turn_left(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Right => Direction::Up,
+            Direction::Down => Direction::Right,
+            Direction::Left => Direction::Down,
+        }
+    }
+    pub fn turn_right(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+    // The turn an intcode program asks for: 0 is left, 1 is right.
+    pub fn turn(self, command: i64) -> Result<Direction, InvalidTurn> {
+        match command {
+            0 => Ok(self.turn_left()),
+            1 => Ok(self.turn_right()),
+            _ => Err(InvalidTurn(command)),
+        }
+    }
+    // y grows upwards
+    pub fn delta(self) -> (i64, i64) {
+        match self {
+            Direction::Up => (0, 1),
+            Direction::Right => (1, 0),
+            Direction::Down => (0, -1),
+            Direction::Left => (-1, 0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Robot {
+    pub pos: (i64, i64),
+    pub dir: Direction,
+}
+
+impl Robot {
+    pub fn new(pos: (i64, i64), dir: Direction) -> Robot {
+        Robot { pos, dir }
+    }
+    pub fn advance(&mut self) {
+        let (dx, dy) = self.dir.delta();
+        self.pos = (self.pos.0 + dx, self.pos.1 + dy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Direction; 4] = [Direction::Up, Direction::Right, Direction::Down, Direction::Left];
+
+    #[test]
+    fn rotation_cycles() {
+        for (i, &dir) in ALL.iter().enumerate() {
+            assert_eq!(dir.turn_right(), ALL[(i + 1) % 4]);
+            assert_eq!(dir.turn_left(), ALL[(i + 3) % 4]);
+            assert_eq!(dir.turn_left().turn_right(), dir);
+            let right = (0..4).fold(dir, |d, _| d.turn_right());
+            let left = (0..4).fold(dir, |d, _| d.turn_left());
+            assert_eq!((right, left), (dir, dir));
+        }
+    }
+
+    #[test]
+    fn turn_commands() {
+        assert_eq!(Direction::Up.turn(0).unwrap(), Direction::Left);
+        assert_eq!(Direction::Up.turn(1).unwrap(), Direction::Right);
+        let error = Direction::Down.turn(2).unwrap_err();
+        assert_eq!(error.0, 2);
+        assert_eq!(error.to_string(), "invalid turn command 2, expected 0 (left) or 1 (right)");
+        assert!(Direction::Down.turn(-1).is_err());
+    }
+
+    #[test]
+    fn movement_deltas() {
+        let mut robot = Robot::new((3, -2), Direction::Up);
+        let mut positions = Vec::new();
+        for &dir in &ALL {
+            robot.dir = dir;
+            robot.advance();
+            positions.push(robot.pos);
+        }
+        assert_eq!(positions, [(3, -1), (4, -1), (4, -2), (3, -2)]);
+        let deltas: Vec<_> = ALL.iter().map(|d| d.delta()).collect();
+        assert_eq!(deltas, [(0, 1), (1, 0), (0, -1), (-1, 0)]);
+    }
+}
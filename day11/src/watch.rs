@@ -0,0 +1,122 @@
+use std::io::Write;
+use std::time::Duration;
+
+use crate::Panels;
+use crate::robot::{Direction, Robot};
+
+// The part of the hull shown, in panels.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Viewport {
+    pub left: i64,
+    pub bottom: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+// the largest view that fits a terminal, leaving a line for the status
+pub const MAX_VIEW: (i64, i64) = (80, 23);
+
+// Grows `view` to show `pos`. A view that would get larger than `max` is
+// re-centered on `pos` instead.
+pub fn follow(view: Viewport, pos: (i64, i64), max: (i64, i64)) -> Viewport {
+    let fit = |start: i64, len: i64, p: i64, max: i64| {
+        if p >= start && p < start + len {
+            return (start, len);
+        }
+        let (low, high) = (start.min(p), (start + len - 1).max(p));
+        if high - low < max {
+            (low, high - low + 1)
+        } else {
+            (p - max / 2, max)
+        }
+    };
+    let (left, width) = fit(view.left, view.width, pos.0, max.0);
+    let (bottom, height) = fit(view.bottom, view.height, pos.1, max.1);
+    Viewport { left, bottom, width, height }
+}
+
+fn glyph(dir: Direction) -> char {
+    match dir {
+        Direction::Up => '^',
+        Direction::Right => '>',
+        Direction::Down => 'v',
+        Direction::Left => '<',
+    }
+}
+
+pub struct Watcher {
+    view: Viewport,
+    delay: Duration,
+    moves: usize,
+}
+
+impl Watcher {
+    pub fn new(start: (i64, i64), delay: Duration) -> Watcher {
+        Watcher {
+            view: Viewport { left: start.0, bottom: start.1, width: 1, height: 1 },
+            delay,
+            moves: 0,
+        }
+    }
+    pub fn draw(&mut self, panels: &Panels, robot: &Robot) {
+        self.view = follow(self.view, robot.pos, MAX_VIEW);
+        let mut screen = "\x1b[H\x1b[2J".to_owned();
+        // y grows upwards, the screen downwards
+        for y in (self.view.bottom..self.view.bottom + self.view.height).rev() {
+            for x in self.view.left..self.view.left + self.view.width {
+                screen.push(match panels.get(&(x, y)) {
+                    _ if (x, y) == robot.pos => glyph(robot.dir),
+                    Some(1) => '█',
+                    _ => ' ',
+                });
+            }
+            screen.push('\n');
+        }
+        screen.push_str(&format!("moves: {} position: {:?}\n", self.moves, robot.pos));
+        let mut stdout = std::io::stdout();
+        // a closed terminal only loses the animation
+        let _ = stdout.write_all(screen.as_bytes()).and_then(|()| stdout.flush());
+        self.moves += 1;
+        std::thread::sleep(self.delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: Viewport = Viewport { left: 0, bottom: 0, width: 1, height: 1 };
+
+    #[test]
+    fn positions_inside_keep_the_view() {
+        let view = Viewport { left: -2, bottom: -3, width: 5, height: 4 };
+        assert_eq!(follow(view, (-2, -3), MAX_VIEW), view);
+        assert_eq!(follow(view, (2, 0), MAX_VIEW), view);
+    }
+
+    #[test]
+    fn growing_towards_negative_coordinates() {
+        let view = follow(START, (-1, 0), MAX_VIEW);
+        assert_eq!(view, Viewport { left: -1, bottom: 0, width: 2, height: 1 });
+        let view = follow(view, (-1, -4), MAX_VIEW);
+        assert_eq!(view, Viewport { left: -1, bottom: -4, width: 2, height: 5 });
+        let view = follow(view, (-6, -10), MAX_VIEW);
+        assert_eq!(view, Viewport { left: -6, bottom: -10, width: 7, height: 11 });
+    }
+
+    #[test]
+    fn growing_towards_positive_coordinates() {
+        let view = follow(START, (3, 2), MAX_VIEW);
+        assert_eq!(view, Viewport { left: 0, bottom: 0, width: 4, height: 3 });
+    }
+
+    #[test]
+    fn recentering_past_the_largest_view() {
+        let view = Viewport { left: -3, bottom: 0, width: 4, height: 1 };
+        // the width fits, the height would not
+        let view = follow(view, (-4, -5), (5, 5));
+        assert_eq!(view, Viewport { left: -4, bottom: -7, width: 5, height: 5 });
+        let view = follow(view, (-20, -7), (5, 5));
+        assert_eq!(view, Viewport { left: -22, bottom: -7, width: 5, height: 5 });
+    }
+}
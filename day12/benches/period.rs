@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use aoc_geom::Vec3;
+use day12::cycle::{axis_cycle, find_cycle, first_repeat, first_repeat_of, PackedAxis};
+use day12::simulation::Simulation;
+
+// the larger example, which repeats after 4686774924 steps
+fn example() -> Vec<Vec3> {
+    vec![Vec3::new(-8, -10, 0), Vec3::new(5, 5, 10), Vec3::new(2, -7, 3), Vec3::new(9, -8, -3)]
+}
+
+fn searches(c: &mut Criterion) {
+    let positions = example();
+    let mut group = c.benchmark_group("first repeat of the large example");
+    group.bench_function("serial", |b| b.iter(|| {
+        let cycles: Vec<_> = Simulation::new(&positions).into_axes().into_iter().zip("xyz".chars())
+            .map(|(axis, name)| axis_cycle(name, axis, None))
+            .collect();
+        first_repeat(&cycles)
+    }));
+    group.bench_function("parallel", |b| b.iter(|| first_repeat_of(&positions, None)));
+    group.finish();
+}
+
+fn axes(c: &mut Criterion) {
+    let axes = Simulation::new(&example()).into_axes();
+    let mut group = c.benchmark_group("cycles of the large example axes");
+    group.bench_function("packed", |b| b.iter(|| {
+        axes.iter().map(|axis| find_cycle(PackedAxis::pack(axis).unwrap(), |axis| axis.step().unwrap()).length).sum::<u64>()
+    }));
+    group.bench_function("vec", |b| b.iter(|| {
+        axes.iter().map(|axis| find_cycle(axis.clone(), |axis| axis.step()).length).sum::<u64>()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, searches, axes);
+criterion_main!(benches);
@@ -0,0 +1,251 @@
+use std::collections::hash_map::DefaultHasher;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use num::integer::lcm;
+use aoc_geom::Vec3;
+
+use crate::simulation::{AxisState, Simulation};
+
+// The states from `start` on repeat every `length` steps.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cycle {
+    pub start: u64,
+    pub length: u64,
+}
+
+// A state the cycle search can tell apart from most others with a u64.
+pub trait Fingerprint: Clone + Eq {
+    fn fingerprint(&self) -> u64;
+}
+
+impl Fingerprint for AxisState {
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+// The usual four moons, packed for the hot loop of the cycle search. The values stay
+// small in every known input, and a search leaving i16 falls back to AxisState.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PackedAxis {
+    positions: [i16; 4],
+    velocities: [i16; 4],
+}
+
+impl PackedAxis {
+    pub fn pack(axis: &AxisState) -> Option<PackedAxis> {
+        let mut packed = PackedAxis { positions: [0; 4], velocities: [0; 4] };
+        if axis.positions.len() != 4 {
+            return None;
+        }
+        for i in 0..4 {
+            packed.positions[i] = i16::try_from(axis.positions[i]).ok()?;
+            packed.velocities[i] = i16::try_from(axis.velocities[i]).ok()?;
+        }
+        Some(packed)
+    }
+    // None, leaving the state as it was, when a value would overflow.
+    pub fn step(&mut self) -> Option<()> {
+        let [p0, p1, p2, p3] = self.positions;
+        let pull = |from: i16, to: i16| (to > from) as i16 - (to < from) as i16;
+        let (g01, g02, g03) = (pull(p0, p1), pull(p0, p2), pull(p0, p3));
+        let (g12, g13, g23) = (pull(p1, p2), pull(p1, p3), pull(p2, p3));
+        let pulls = [g01 + g02 + g03, g12 + g13 - g01, g23 - g02 - g12, -(g03 + g13 + g23)];
+        let mut next = *self;
+        for (i, &pull) in pulls.iter().enumerate() {
+            next.velocities[i] = self.velocities[i].checked_add(pull)?;
+            next.positions[i] = self.positions[i].checked_add(next.velocities[i])?;
+        }
+        *self = next;
+        Some(())
+    }
+}
+
+impl Fingerprint for PackedAxis {
+    fn fingerprint(&self) -> u64 {
+        let bits = |values: [i16; 4]| values.iter().fold(0u64, |acc, &v| acc << 16 | v as u16 as u64);
+        bits(self.positions) ^ bits(self.velocities).rotate_left(32).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+}
+
+// A state along with its fingerprint, so that most comparisons are a single u64 compare.
+struct Hashed<S> {
+    hash: u64,
+    state: S,
+}
+
+impl<S: Fingerprint> Hashed<S> {
+    fn new(state: S) -> Hashed<S> {
+        let mut hashed = Hashed { hash: 0, state };
+        hashed.rehash();
+        hashed
+    }
+    fn rehash(&mut self) {
+        self.hash = self.state.fingerprint();
+    }
+    fn advance(&mut self, step: &mut impl FnMut(&mut S)) {
+        step(&mut self.state);
+        self.rehash();
+    }
+    fn same(&self, other: &Hashed<S>) -> bool {
+        self.hash == other.hash && self.state == other.state
+    }
+}
+
+// Brent's cycle detection on the sequence of states from `init`, each computed from
+// the previous one by `step`.
+pub fn find_cycle<S: Fingerprint>(init: S, mut step: impl FnMut(&mut S)) -> Cycle {
+    let mut power = 1;
+    let mut length = 1;
+    let mut tortoise = Hashed::new(init.clone());
+    let mut hare = Hashed::new(init.clone());
+    hare.advance(&mut step);
+    while !tortoise.same(&hare) {
+        if power == length {
+            tortoise = Hashed { hash: hare.hash, state: hare.state.clone() };
+            power *= 2;
+            length = 0;
+        }
+        hare.advance(&mut step);
+        length += 1;
+    }
+    // the hare runs `length` steps ahead, they meet where the cycle starts
+    let mut tortoise = Hashed::new(init.clone());
+    let mut hare = Hashed::new(init);
+    for _ in 0..length {
+        hare.advance(&mut step);
+    }
+    let mut start = 0;
+    while !tortoise.same(&hare) {
+        tortoise.advance(&mut step);
+        hare.advance(&mut step);
+        start += 1;
+    }
+    Cycle { start, length }
+}
+
+// Finds the cycle of one axis, reporting the steps taken every `progress` million.
+pub fn axis_cycle(name: char, axis: AxisState, progress: Option<u64>) -> Cycle {
+    let every = progress.map_or(u64::MAX, |millions| millions.saturating_mul(1_000_000).max(1));
+    let mut steps = 0u64;
+    let mut report = || {
+        steps += 1;
+        if steps.is_multiple_of(every) {
+            eprintln!("{}: {} steps", name, steps);
+        }
+    };
+    if let Some(packed) = PackedAxis::pack(&axis) {
+        let mut overflowed = false;
+        // a step that overflows leaves the state as it was, which ends the search
+        let cycle = find_cycle(packed, |packed| {
+            overflowed |= packed.step().is_none();
+            report();
+        });
+        if !overflowed {
+            return cycle;
+        }
+    }
+    find_cycle(axis, |axis| {
+        axis.step();
+        report();
+    })
+}
+
+// The first step whose state repeats an earlier one.
+pub fn first_repeat_of(positions: &[Vec3], progress: Option<u64>) -> u64 {
+    // the axes are independent, so they are searched at the same time
+    let cycles: Vec<Cycle> = std::thread::scope(|scope| {
+        let searches: Vec<_> = Simulation::new(positions).into_axes().into_iter().zip("xyz".chars())
+            .map(|(axis, name)| scope.spawn(move || axis_cycle(name, axis, progress)))
+            .collect();
+        searches.into_iter().map(|search| search.join().expect("the period search panicked")).collect()
+    });
+    first_repeat(&cycles)
+}
+
+// The same, from the cycles of the axes.
+pub fn first_repeat(cycles: &[Cycle]) -> u64 {
+    // all the axes are in their cycles after the latest start
+    let start = cycles.iter().map(|c| c.start).max().unwrap_or(0);
+    let length = cycles.iter().fold(1, |acc, c| lcm(acc, c.length));
+    start + length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Fingerprint for u64 {
+        fn fingerprint(&self) -> u64 {
+            // collisions everywhere, the states still tell the steps apart
+            self % 2
+        }
+    }
+
+    #[test]
+    fn a_cycle_after_a_tail() {
+        // 0 1 2 3 4 5 6 7 3 4 5 6 7 3 ...
+        let cycle = find_cycle(0u64, |x| *x = if *x == 7 { 3 } else { *x + 1 });
+        assert_eq!(cycle, Cycle { start: 3, length: 5 });
+        let cycle = find_cycle(10u64, |x| *x = 11);
+        assert_eq!(cycle, Cycle { start: 1, length: 1 });
+        let cycle = find_cycle(0u64, |x| *x = (*x + 1) % 4);
+        assert_eq!(cycle, Cycle { start: 0, length: 4 });
+    }
+
+    fn large_example() -> Vec<AxisState> {
+        let positions = [Vec3::new(-8, -10, 0), Vec3::new(5, 5, 10), Vec3::new(2, -7, 3), Vec3::new(9, -8, -3)];
+        Simulation::new(&positions).into_axes()
+    }
+
+    #[test]
+    fn packed_and_unpacked_searches_agree() {
+        let mut lengths = Vec::new();
+        for axis in large_example() {
+            let packed = find_cycle(PackedAxis::pack(&axis).unwrap(), |axis| axis.step().unwrap());
+            let unpacked = find_cycle(axis.clone(), AxisState::step);
+            assert_eq!(packed, unpacked);
+            assert_eq!(axis_cycle('?', axis, None), packed);
+            lengths.push(packed.length);
+        }
+        assert_eq!(lengths, [2028, 5898, 4702]);
+        assert_eq!(first_repeat_of(&[Vec3::new(-8, -10, 0), Vec3::new(5, 5, 10), Vec3::new(2, -7, 3), Vec3::new(9, -8, -3)], None), 4686774924);
+    }
+
+    #[test]
+    fn overflowing_i16_falls_back() {
+        // the outer moons fly far out before coming back
+        let axis = AxisState { positions: vec![32000, 32000, -32000, -32000], velocities: vec![2000, 2000, -2000, -2000] };
+        let mut packed = PackedAxis::pack(&axis).unwrap();
+        let before = packed;
+        assert_eq!(packed.step(), None);
+        assert_eq!(packed, before);
+        let expected = find_cycle(axis.clone(), AxisState::step);
+        assert_eq!(axis_cycle('?', axis, None), expected);
+        assert_eq!(expected.start, 0);
+        assert!(expected.length > 4000);
+    }
+
+    #[test]
+    fn only_four_small_moons_are_packed() {
+        assert!(PackedAxis::pack(&AxisState::new(vec![1, 2, 3])).is_none());
+        assert!(PackedAxis::pack(&AxisState::new(vec![1, 2, 3, 40000])).is_none());
+        assert!(PackedAxis::pack(&AxisState::new(vec![1, 2, 3, -32768])).is_some());
+        let mut three = AxisState::new(vec![0, 3, 7]);
+        let cycle = axis_cycle('?', three.clone(), None);
+        for _ in 0..cycle.length {
+            three.step();
+        }
+        assert_eq!(three, AxisState::new(vec![0, 3, 7]));
+    }
+
+    #[test]
+    fn axes_starting_their_cycles_late() {
+        let cycles = [Cycle { start: 3, length: 4 }, Cycle { start: 0, length: 6 }, Cycle { start: 1, length: 3 }];
+        assert_eq!(first_repeat(&cycles), 15);
+        assert_eq!(first_repeat(&cycles[1..]), 7);
+        assert_eq!(first_repeat(&[Cycle { start: 0, length: 18 }, Cycle { start: 0, length: 28 }]), 252);
+    }
+}
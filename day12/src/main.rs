@@ -1,6 +1,9 @@
 use anyhow::{anyhow, Result};
-use std::str::FromStr;
-use num::integer::lcm;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use aoc_geom::Vec3;
+use day12::cycle;
+use day12::simulation::Simulation;
 use nom::{
     IResult,
     character::complete::digit1,
@@ -9,43 +12,20 @@ use nom::{
     sequence::pair
 };
 
-fn parse() -> Result<Vec<Vec3>> {
-    std::fs::read_to_string("input")?
-        .trim()
-        .lines()
-        .map(FromStr::from_str)
-        .collect()
-}
-
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-struct Vec3 {
-    x: i64,
-    y: i64,
-    z: i64,
-}
-impl Vec3 {
-    fn new(x: i64, y: i64, z: i64) -> Vec3 {
-        Vec3 {
-            x,
-            y,
-            z,
+fn parse_reader(reader: impl BufRead) -> Result<Vec<Vec3>> {
+    let mut positions = Vec::new();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            positions.push(parse_vec3(line.trim()).map_err(|e| anyhow!("line {}: {}", n + 1, e))?);
         }
     }
-    fn zero() -> Vec3 {
-        Vec3::new(0, 0, 0,)
-    }
-    fn delta(&self, other: &Vec3) -> Vec3 {
-        Vec3 {
-            x: if self.x < other.x { 1 } else if self.x == other.x { 0 } else { -1 },
-            y: if self.y < other.y { 1 } else if self.y == other.y { 0 } else { -1 },
-            z: if self.z < other.z { 1 } else if self.z == other.z { 0 } else { -1 },
-        }
-    }
-    fn add(&mut self, other: &Vec3) {
-        self.x += other.x;
-        self.y += other.y;
-        self.z += other.z;
-    }
+    Ok(positions)
+}
+
+fn parse(path: &str) -> Result<Vec<Vec3>> {
+    let file = File::open(path).map_err(|e| anyhow!("cannot open {}: {}", path, e))?;
+    parse_reader(BufReader::new(file))
 }
 
 fn parser(s: &str) -> IResult<&str, Vec3> {
@@ -60,92 +40,159 @@ fn parser(s: &str) -> IResult<&str, Vec3> {
     Ok((s, Vec3::new(x,y,z)))
 }
 
-impl FromStr for Vec3 {
-    type Err = anyhow::Error;
-    fn from_str(s: &str) -> Result<Vec3> {
-        let (_, v) = parser(s).map_err(|_|anyhow!("failed to pars Vec3"))?;
-        Ok(v)
+fn parse_vec3(s: &str) -> Result<Vec3> {
+    match parser(s) {
+        Ok(("", v)) => Ok(v),
+        Ok((rest, _)) => Err(anyhow!("unexpected `{}` after the position in `{}`", rest, s)),
+        Err(_) => Err(anyhow!("cannot parse `{}`, expected a position like <x=1, y=-2, z=3>", s)),
     }
 }
 
-fn step(positions: &mut Vec<Vec3>, velocities: &mut Vec<Vec3>) {
-    for i in 0..positions.len() {
-        for j in 0..positions.len() {
-            if i == j {
-                continue;
-            }
-            let delta = positions[i].delta(&positions[j]);
-            velocities[i].add(&delta);
+// Writes the energies of every step as csv, as the simulation goes.
+struct Trace {
+    out: BufWriter<File>,
+}
+
+impl Trace {
+    fn create(path: &str, moons: usize) -> Result<Trace> {
+        let file = File::create(path).map_err(|e| anyhow!("cannot create {}: {}", path, e))?;
+        let mut out = BufWriter::new(file);
+        write!(out, "step,energy")?;
+        for moon in 0..moons {
+            write!(out, ",potential{},kinetic{}", moon, moon)?;
         }
+        writeln!(out)?;
+        Ok(Trace { out })
     }
-    for i in 0..positions.len() {
-        positions[i].add(&velocities[i]);
+    fn row(&mut self, simulation: &Simulation) -> Result<()> {
+        write!(self.out, "{},{}", simulation.steps(), simulation.energy())?;
+        for moon in simulation.state() {
+            write!(self.out, ",{},{}", moon.potential(), moon.kinetic())?;
+        }
+        writeln!(self.out)?;
+        Ok(())
+    }
+    fn finish(mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
     }
 }
 
-fn energy(positions: &Vec<Vec3>, velocities: &Vec<Vec3>) -> i64 {
-    let mut en = 0;
-
-    for (p,v) in positions.iter().zip(velocities.iter()) {
-        let enp = p.x.abs() + p.y.abs() + p.z.abs();
-        let enk = v.x.abs() + v.y.abs() + v.z.abs();
-        en += enp*enk;
+fn part1(positions: Vec<Vec3>, steps: usize, trace: Option<&str>) -> Result<impl std::fmt::Display> {
+    let mut simulation = Simulation::new(&positions);
+    let mut trace = trace.map(|path| Trace::create(path, positions.len())).transpose()?;
+    if let Some(trace) = &mut trace {
+        trace.row(&simulation)?;
     }
-    en
+    for _ in 0..steps {
+        simulation.next_state();
+        if let Some(trace) = &mut trace {
+            trace.row(&simulation)?;
+        }
+    }
+    if let Some(trace) = trace {
+        trace.finish()?;
+    }
+    Ok(simulation.energy())
 }
 
+fn part2(positions: Vec<Vec3>, progress: Option<u64>) -> Result<impl std::fmt::Display> {
+    Ok(cycle::first_repeat_of(&positions, progress))
+}
 
-fn part1(mut positions: Vec<Vec3>) -> Result<impl std::fmt::Display> {
-    let mut velocities = Vec::new();
-    velocities.resize_with(positions.len(), Vec3::zero);
-    for _ in 0..1000 {
-        step(&mut positions, &mut velocities);
-    }
-    Ok(energy(&positions, &velocities))
+struct Options {
+    progress: Option<u64>,
+    steps: usize,
+    trace: Option<String>,
 }
 
-fn find_period(mut positions: Vec<i64>) -> i64 {
-    let init_pos = positions.clone();
-    let mut velocities = Vec::new();
-    velocities.resize(positions.len(), 0);
-    let init_vel = velocities.clone();
-    let mut count = 0;
-    loop {
-        for i in 0..positions.len() {
-            for j in 0..positions.len() {
-                if i == j {
-                    continue;
-                }
-                let delta = if positions[i] < positions[j] { 1 } else if positions[i] == positions[j] { 0 } else { -1 };
-                velocities[i] += delta;
-            }
-        }
-        for i in 0..positions.len() {
-            positions[i] += velocities[i];
-        }
-        count += 1;
-        if positions == init_pos && velocities == init_vel {
-            break;
+fn parse_args() -> Result<Options> {
+    let mut options = Options {
+        progress: None,
+        steps: 1000,
+        trace: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--progress" => options.progress = Some(value()?.parse()?),
+            "--steps" => options.steps = value()?.parse()?,
+            "--trace" => options.trace = Some(value()?),
+            _ => return Err(anyhow!("unknown argument `{}`", arg)),
         }
     }
-    count
-}
-fn part2(positions: Vec<Vec3>) -> Result<impl std::fmt::Display> {
-    let positions_x = positions.iter().map(|p| p.x).collect();
-    let positions_y = positions.iter().map(|p| p.y).collect();
-    let positions_z = positions.iter().map(|p| p.z).collect();
-    let px = find_period(positions_x);
-    let py = find_period(positions_y);
-    let pz = find_period(positions_z);
-    let period = lcm(lcm(px, py), pz);
-    Ok(period)
+    Ok(options)
 }
 
 fn main() -> Result<()> {
-    let v = parse()?;
-    let p1 = part1(v.clone())?;
+    let options = parse_args()?;
+    let v = parse("input")?;
+    let p1 = part1(v.clone(), options.steps, options.trace.as_deref())?;
     println!("part 1: {}", p1);
-    let p2 = part2(v)?;
+    let p2 = part2(v, options.progress)?;
     println!("part 2: {}", p2);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: &str = "\
+<x=-1, y=0, z=2>
+<x=2, y=-10, z=-7>
+<x=4, y=-8, z=8>
+<x=3, y=5, z=-1>
+";
+
+    const LARGE: &str = "\
+<x=-8, y=-10, z=0>
+<x=5, y=5, z=10>
+<x=2, y=-7, z=3>
+<x=9, y=-8, z=-3>
+";
+
+    fn example(text: &str) -> Vec<Vec3> {
+        parse_reader(text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn repeating_the_examples() {
+        assert_eq!(part2(example(SMALL), None).unwrap().to_string(), "2772");
+        assert_eq!(part2(example(LARGE), None).unwrap().to_string(), "4686774924");
+    }
+
+    #[test]
+    fn energies_of_the_examples() {
+        assert_eq!(part1(example(SMALL), 10, None).unwrap().to_string(), "179");
+        assert_eq!(part1(example(LARGE), 100, None).unwrap().to_string(), "1940");
+        // nothing moves before the first step
+        assert_eq!(part1(example(SMALL), 0, None).unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn parsing_errors_name_the_line() {
+        let error = parse_reader("<x=1, y=2, z=3>\n\n<x=1, y=2>\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "line 3: cannot parse `<x=1, y=2>`, expected a position like <x=1, y=-2, z=3>");
+        let error = parse_reader("<x=1, y=2, z=3> !\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "line 1: unexpected ` !` after the position in `<x=1, y=2, z=3> !`");
+        assert_eq!(parse_reader("  <x=-1, y=0, z=2>  \n".as_bytes()).unwrap(), [Vec3::new(-1, 0, 2)]);
+        assert!(parse("no such file").unwrap_err().to_string().starts_with("cannot open no such file: "));
+    }
+
+    #[test]
+    fn tracing_energies() {
+        let path = std::env::temp_dir().join(format!("day12-trace-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert_eq!(part1(example(SMALL), 2, Some(path)).unwrap().to_string(), "245");
+        let csv = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(csv, "\
+step,energy,potential0,kinetic0,potential1,kinetic1,potential2,kinetic2,potential3,kinetic3
+0,0,3,0,19,0,20,0,9,0
+1,229,4,5,14,7,13,7,4,5
+2,245,9,7,5,13,6,9,7,9
+");
+    }
+}
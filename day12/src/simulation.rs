@@ -0,0 +1,86 @@
+use aoc_geom::{Axis, Vec3};
+
+// One axis of the simulation, which moves independently from the others.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct AxisState {
+    pub positions: Vec<i64>,
+    pub velocities: Vec<i64>,
+}
+
+impl AxisState {
+    pub fn new(positions: Vec<i64>) -> AxisState {
+        let velocities = vec![0; positions.len()];
+        AxisState { positions, velocities }
+    }
+    // The shared simulation step: gravity, then velocity.
+    pub fn step(&mut self) {
+        for i in 0..self.positions.len() {
+            for j in 0..self.positions.len() {
+                self.velocities[i] += (self.positions[j] - self.positions[i]).signum();
+            }
+        }
+        for i in 0..self.positions.len() {
+            self.positions[i] += self.velocities[i];
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Moon {
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+impl Moon {
+    pub fn potential(&self) -> i64 {
+        self.position.manhattan()
+    }
+    pub fn kinetic(&self) -> i64 {
+        self.velocity.manhattan()
+    }
+    pub fn energy(&self) -> i64 {
+        self.potential()*self.kinetic()
+    }
+}
+
+// The axes are simulated separately, and put back together for the moons.
+pub struct Simulation {
+    axes: Vec<AxisState>,
+    steps: u64,
+}
+
+impl Simulation {
+    pub fn new(positions: &[Vec3]) -> Simulation {
+        let axes = Axis::ALL.iter()
+            .map(|&axis| AxisState::new(positions.iter().map(|p| p[axis]).collect()))
+            .collect();
+        Simulation { axes, steps: 0 }
+    }
+    pub fn next_state(&mut self) -> Vec<Moon> {
+        for axis in &mut self.axes {
+            axis.step();
+        }
+        self.steps += 1;
+        self.state()
+    }
+    pub fn state(&self) -> Vec<Moon> {
+        let moons = self.axes.first().map_or(0, |axis| axis.positions.len());
+        (0..moons).map(|moon| {
+            let (mut position, mut velocity) = (Vec3::zero(), Vec3::zero());
+            for (&axis, state) in Axis::ALL.iter().zip(&self.axes) {
+                position[axis] = state.positions[moon];
+                velocity[axis] = state.velocities[moon];
+            }
+            Moon { position, velocity }
+        }).collect()
+    }
+    pub fn energy(&self) -> i64 {
+        self.state().iter().map(Moon::energy).sum()
+    }
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+    pub fn into_axes(self) -> Vec<AxisState> {
+        self.axes
+    }
+}
@@ -0,0 +1,833 @@
+use anyhow::Result;
+use anyhow::anyhow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use intcode::{InputSource, Snapshot, VMError, Vm};
+use serde::{Deserialize, Serialize};
+
+use crate::record::Recorder;
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum Tile {
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+}
+impl Tile {
+    pub fn parse(i: i64) -> Result<Tile> {
+        match i {
+            0 => Ok(Tile::Empty),
+            1 => Ok(Tile::Wall),
+            2 => Ok(Tile::Block),
+            3 => Ok(Tile::Paddle),
+            4 => Ok(Tile::Ball),
+            _ => Err(anyhow!("cannot parse tile {}", i)),
+        }
+    }
+    pub fn glyph(self) -> char {
+        match self {
+            Tile::Empty => '.',
+            Tile::Block => 'O',
+            Tile::Wall => '#',
+            Tile::Paddle => '=',
+            Tile::Ball => 'o',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Outcome {
+    Won,
+    Lost,
+}
+
+// Whatever runs the game: the intcode program, or a script standing in for it.
+pub trait Arcade {
+    // The next (x, y, tile) triple, reading joystick moves from `joystick` when the game
+    // asks for one. None once the game halted or when it waits for a move `joystick` lacks.
+    fn next_triple(&mut self, joystick: &mut dyn InputSource) -> Result<Option<[i64; 3]>>;
+    fn is_running(&self) -> bool;
+}
+
+impl Arcade for Vm {
+    fn next_triple(&mut self, joystick: &mut dyn InputSource) -> Result<Option<[i64; 3]>> {
+        loop {
+            match self.run_until_n_outputs() {
+                Ok(triple) => return Ok(triple),
+                Err(VMError::NoMoreInput { .. }) => match joystick.next_input() {
+                    Some(tilt) => self.add_inputs(&[tilt]),
+                    None => return Ok(None),
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    fn is_running(&self) -> bool {
+        Vm::is_running(self)
+    }
+}
+
+// A canned arcade: `Some(triple)` is drawn, `None` waits for a joystick move.
+pub struct Script {
+    steps: VecDeque<Option<[i64; 3]>>,
+    pub moves: Vec<i64>,
+}
+
+impl Script {
+    pub fn new(steps: Vec<Option<[i64; 3]>>) -> Script {
+        Script { steps: steps.into(), moves: Vec::new() }
+    }
+}
+
+impl Arcade for Script {
+    fn next_triple(&mut self, joystick: &mut dyn InputSource) -> Result<Option<[i64; 3]>> {
+        loop {
+            match self.steps.front() {
+                None => return Ok(None),
+                Some(&Some(triple)) => {
+                    self.steps.pop_front();
+                    return Ok(Some(triple));
+                },
+                Some(None) => match joystick.next_input() {
+                    Some(tilt) => {
+                        self.moves.push(tilt);
+                        self.steps.pop_front();
+                    },
+                    None => return Ok(None),
+                },
+            }
+        }
+    }
+    fn is_running(&self) -> bool {
+        !self.steps.is_empty()
+    }
+}
+
+pub trait Renderer {
+    fn clear(&mut self);
+    fn draw_tile(&mut self, x: usize, y: usize, tile: Tile);
+    fn draw_text(&mut self, row: usize, text: &str);
+}
+
+pub struct NoRenderer;
+
+impl Renderer for NoRenderer {
+    fn clear(&mut self) {}
+    fn draw_tile(&mut self, _x: usize, _y: usize, _tile: Tile) {}
+    fn draw_text(&mut self, _row: usize, _text: &str) {}
+}
+
+pub struct Game<A: Arcade = Vm> {
+    arcade: A,
+    score: i64,
+    map: Vec<Tile>,
+    width: usize,
+    height: usize,
+    ball: i64,
+    paddle: i64,
+    blocks: usize,
+    // the first score marks the end of the initial screen draw
+    drawn: bool,
+    cleared: bool,
+    recorder: Option<Recorder>,
+    // tiles changed since the last render, unless the whole board has to be drawn
+    dirty: HashSet<(usize, usize)>,
+    redraw: bool,
+    shown_status: String,
+    // an error in an interactive mode stops the game, leaving the board as it was
+    error: Option<anyhow::Error>,
+    // every joystick move the game consumed
+    moves: Vec<i64>,
+}
+
+// FNV-1a, stable across builds unlike the std hasher
+fn program_hash(program: &[i64]) -> u64 {
+    program.iter()
+        .flat_map(|word| word.to_le_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedGame {
+    program_hash: u64,
+    snapshot: Snapshot,
+    score: i64,
+    map: Vec<Tile>,
+    width: usize,
+    height: usize,
+    ball: i64,
+    paddle: i64,
+    blocks: usize,
+    drawn: bool,
+    cleared: bool,
+    #[serde(default)]
+    moves: Vec<i64>,
+}
+
+impl Game {
+    pub fn new(mem: Vec<i64>) -> Result<Game> {
+        let vm = Vm::builder()
+            .program(mem)
+            .patch(0, 2)
+            .build()?;
+        Game::from_arcade(vm)
+    }
+    // Without the coins the game only draws the screen and halts.
+    pub fn draw_only(mem: Vec<i64>) -> Result<Game> {
+        Game::from_arcade(Vm::new(mem))
+    }
+    pub fn saved(&self) -> SavedGame {
+        let snapshot = self.arcade.snapshot();
+        SavedGame {
+            program_hash: program_hash(&snapshot.program),
+            snapshot,
+            score: self.score,
+            map: self.map.clone(),
+            width: self.width,
+            height: self.height,
+            ball: self.ball,
+            paddle: self.paddle,
+            blocks: self.blocks,
+            drawn: self.drawn,
+            cleared: self.cleared,
+            moves: self.moves.clone(),
+        }
+    }
+    pub fn restore(&mut self, saved: SavedGame) -> Result<()> {
+        if saved.program_hash != program_hash(&self.arcade.snapshot().program) {
+            return Err(anyhow!("the saved game was played with a different program"));
+        }
+        if saved.map.len() != saved.width*saved.height {
+            return Err(anyhow!("the saved {}x{} board has {} tiles", saved.width, saved.height, saved.map.len()));
+        }
+        self.arcade.restore(saved.snapshot);
+        self.score = saved.score;
+        self.map = saved.map;
+        self.width = saved.width;
+        self.height = saved.height;
+        self.ball = saved.ball;
+        self.paddle = saved.paddle;
+        self.blocks = saved.blocks;
+        self.drawn = saved.drawn;
+        self.cleared = saved.cleared;
+        self.moves = saved.moves;
+        self.error = None;
+        self.redraw();
+        Ok(())
+    }
+    pub fn save(&self, path: &str) -> Result<()> {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &self.saved())?;
+        Ok(())
+    }
+    pub fn load(&mut self, path: &str) -> Result<()> {
+        let saved = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        self.restore(saved)
+    }
+}
+
+impl<A: Arcade> Game<A> {
+    pub fn from_arcade(mut arcade: A) -> Result<Game<A>> {
+        // the game draws the whole screen before asking for the first move,
+        // so its size is known before allocating the map
+        let mut screen = Vec::new();
+        while let Some(triple) = arcade.next_triple(&mut || None)? {
+            screen.push(triple);
+        }
+        let size = |coord: fn(&[i64; 3]) -> i64| {
+            screen.iter().filter(|t| t[0] != -1).map(coord).max().map_or(0, |max| max as usize + 1)
+        };
+        let (width, height) = (size(|t| t[0]), size(|t| t[1]));
+        let mut game = Game {
+            arcade,
+            map: vec![Tile::Empty; width*height],
+            width,
+            height,
+            score: 0,
+            ball: 0,
+            paddle: 0,
+            blocks: 0,
+            drawn: false,
+            cleared: false,
+            recorder: None,
+            dirty: HashSet::new(),
+            redraw: true,
+            shown_status: String::new(),
+            error: None,
+            moves: Vec::new(),
+        };
+        for [x, y, t] in screen {
+            game.apply(x, y, t)?;
+        }
+        Ok(game)
+    }
+    pub fn score(&self) -> i64 {
+        self.score
+    }
+    pub fn blocks(&self) -> usize {
+        self.blocks
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn tile(&self, x: usize, y: usize) -> Option<Tile> {
+        if x < self.width {
+            self.map.get(x + self.width*y).copied()
+        } else {
+            None
+        }
+    }
+    pub fn moves(&self) -> &[i64] {
+        &self.moves
+    }
+    pub fn count(&self, tile: Tile) -> usize {
+        self.map.iter().filter(|&&t| t == tile).count()
+    }
+    pub fn counts(&self) -> HashMap<Tile, usize> {
+        let mut counts = HashMap::new();
+        for &tile in &self.map {
+            *counts.entry(tile).or_insert(0) += 1;
+        }
+        counts
+    }
+    fn map_at(&mut self, x: i64, y: i64) -> Result<&mut Tile> {
+        if x < 0 || x >= (self.width as i64) || y < 0 || y >= (self.height as i64) {
+            Err(anyhow!("tile ({}, {}) is outside the {}x{} board", x, y, self.width, self.height))
+        } else {
+            Ok(&mut self.map[x as usize + self.width*(y as usize)])
+        }
+    }
+    fn apply(&mut self, x: i64, y: i64, t: i64) -> Result<()> {
+        if x == -1 {
+            self.score = t;
+            // the score for the last block comes right after the block is cleared
+            self.cleared = self.drawn && self.blocks == 0;
+            self.drawn = true;
+        } else {
+            let tile = Tile::parse(t)?;
+            let old = std::mem::replace(self.map_at(x, y)?, tile);
+            if old != tile {
+                self.dirty.insert((x as usize, y as usize));
+            }
+            if old == Tile::Block {
+                self.blocks -= 1;
+            }
+            if tile == Tile::Block {
+                self.blocks += 1;
+            }
+            if tile == Tile::Ball {
+                self.ball = x;
+            } else if tile == Tile::Paddle {
+                self.paddle = x;
+            }
+        }
+        Ok(())
+    }
+    // The move that keeps the paddle under the ball.
+    pub fn autopilot(&self) -> i64 {
+        (self.ball - self.paddle).signum()
+    }
+    // Applies the next triple the game draws. Returns false when the game is over or
+    // waits for a move `joystick` doesn't provide.
+    pub fn update(&mut self, joystick: &mut dyn InputSource) -> Result<bool> {
+        if self.error.is_some() || self.outcome().is_some() {
+            return Ok(false);
+        }
+        // a request for input means the previous frame is complete
+        let mut fed = Vec::new();
+        let mut tracked = || {
+            let tilt = joystick.next_input();
+            fed.extend(tilt);
+            tilt
+        };
+        let triple = self.arcade.next_triple(&mut tracked)?;
+        if !fed.is_empty() {
+            self.moves.extend(fed);
+            self.record_frame()?;
+        }
+        match triple {
+            Some([x, y, t]) => {
+                self.apply(x, y, t)?;
+                if self.cleared {
+                    self.record_frame()?;
+                }
+                Ok(true)
+            },
+            None => {
+                if !self.arcade.is_running() {
+                    self.record_frame()?;
+                }
+                Ok(false)
+            },
+        }
+    }
+    pub fn run_to_completion(&mut self) -> Result<i64> {
+        self.advance(usize::MAX)?;
+        Ok(self.score)
+    }
+    // Runs to the end feeding `moves` in order. Once they run out the autopilot takes
+    // over, unless `strict`, which makes an unfinished game an error.
+    pub fn run_scripted(&mut self, moves: &[i64], strict: bool) -> Result<i64> {
+        let mut moves = moves.iter().copied();
+        loop {
+            let tilt = self.autopilot();
+            let mut joystick = || moves.next().or(if strict { None } else { Some(tilt) });
+            if !self.update(&mut joystick)? {
+                break;
+            }
+        }
+        if self.outcome().is_none() {
+            return Err(anyhow!("the joystick moves ran out after {} moves", self.moves.len()));
+        }
+        Ok(self.score)
+    }
+    // Applies up to `n` output triples steered by the autopilot, stopping early when the
+    // game ends. Returns how many were applied.
+    pub fn advance(&mut self, n: usize) -> Result<usize> {
+        for done in 0..n {
+            let tilt = self.autopilot();
+            if !self.update(&mut || Some(tilt))? {
+                return Ok(done);
+            }
+        }
+        Ok(n)
+    }
+    // Feeds one joystick move and applies everything the game draws until it asks for the next one.
+    pub fn play_move(&mut self, joystick: i64) -> Result<()> {
+        let mut tilt = Some(joystick);
+        while self.update(&mut || tilt.take())? {}
+        Ok(())
+    }
+    pub fn outcome(&self) -> Option<Outcome> {
+        if !self.cleared && self.arcade.is_running() {
+            None
+        } else if self.blocks == 0 {
+            Some(Outcome::Won)
+        } else {
+            Some(Outcome::Lost)
+        }
+    }
+    // Keeps the error of `result`, if any, to stop the game and show it in the status line.
+    pub fn keep_error<T>(&mut self, result: Result<T>) {
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+    pub fn error(&self) -> Option<&anyhow::Error> {
+        self.error.as_ref()
+    }
+    pub fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.error.take()
+    }
+    pub fn record_to(&mut self, mut recorder: Recorder) -> Result<()> {
+        recorder.frame(&self.screen())?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+    fn record_frame(&mut self) -> Result<()> {
+        let screen = self.screen();
+        let over = self.outcome().is_some();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.frame(&screen)?;
+            if over {
+                recorder.flush()?;
+            }
+        }
+        Ok(())
+    }
+    pub fn status(&self) -> String {
+        if let Some(e) = &self.error {
+            return format!("ERROR: {:#}", e);
+        }
+        match self.outcome() {
+            Some(Outcome::Won) => format!("YOU WIN! score: {}", self.score),
+            Some(Outcome::Lost) => format!("GAME OVER score: {} blocks: {}", self.score, self.blocks),
+            None => format!("score: {} blocks: {}", self.score, self.blocks),
+        }
+    }
+    // The board as text, laid out like the window: the rows, a blank line and the status.
+    pub fn screen(&self) -> String {
+        let mut screen = String::new();
+        for row in self.map.chunks(self.width.max(1)) {
+            screen.extend(row.iter().map(|t| t.glyph()));
+            screen.push('\n');
+        }
+        screen.push('\n');
+        screen.push_str(&self.status());
+        screen
+    }
+    // Makes the next render draw the whole board.
+    pub fn redraw(&mut self) {
+        self.redraw = true;
+    }
+    // Draws what changed since the last render.
+    pub fn render(&mut self, renderer: &mut dyn Renderer) {
+        if self.redraw {
+            self.dirty.clear();
+            renderer.clear();
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    renderer.draw_tile(x, y, self.map[x + self.width*y]);
+                }
+            }
+        } else {
+            for (x, y) in self.dirty.drain() {
+                renderer.draw_tile(x, y, self.map[x + self.width*y]);
+            }
+        }
+        let status = self.status();
+        if self.redraw || status != self.shown_status {
+            // padded to wipe a longer previous status
+            renderer.draw_text(self.height + 1, &format!("{:1$}", status, self.width));
+            self.shown_status = status;
+        }
+        self.redraw = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 3x2 board: a block between two walls, the paddle under the ball, then the score.
+    const SCREEN: [[i64; 3]; 7] = [[0, 0, 1], [1, 0, 2], [2, 0, 1], [0, 1, 0], [1, 1, 3], [2, 1, 4], [-1, 0, 0]];
+
+    fn game(steps: &[Option<[i64; 3]>]) -> Game<Script> {
+        let mut script: Vec<_> = SCREEN.iter().copied().map(Some).collect();
+        // the first move request ends the initial draw
+        script.push(None);
+        script.extend_from_slice(steps);
+        Game::from_arcade(Script::new(script)).unwrap()
+    }
+
+    #[test]
+    fn completion_stops_once_the_blocks_are_cleared() {
+        let mut game = game(&[Some([1, 0, 0]), Some([-1, 0, 50]), Some([2, 1, 0]), None]);
+        assert_eq!(game.run_to_completion().unwrap(), 50);
+        assert_eq!(game.outcome(), Some(Outcome::Won));
+        // the autopilot followed the ball, and nothing after the last score was drawn
+        assert_eq!(game.arcade.moves, [1]);
+        assert_eq!(game.tile(2, 1), Some(Tile::Ball));
+        assert!(game.arcade.is_running());
+    }
+
+    #[test]
+    fn completion_stops_when_the_program_halts() {
+        let mut game = game(&[Some([-1, 0, 10])]);
+        assert_eq!(game.run_to_completion().unwrap(), 10);
+        assert_eq!(game.outcome(), Some(Outcome::Lost));
+        assert_eq!(game.blocks(), 1);
+        assert!(!game.arcade.is_running());
+    }
+
+    #[test]
+    fn the_board_is_sized_by_the_initial_draw() {
+        // the walls around a 10x5 board, drawn before the first move request
+        let mut steps = Vec::new();
+        for x in 0..10 {
+            steps.push(Some([x, 0, 1]));
+            steps.push(Some([x, 4, 1]));
+        }
+        for y in 1..4 {
+            steps.push(Some([0, y, 1]));
+            steps.push(Some([9, y, 1]));
+        }
+        steps.extend(vec![Some([-1, 0, 0]), None, Some([10, 2, 4])]);
+        let mut game = Game::from_arcade(Script::new(steps)).unwrap();
+        assert_eq!((game.width(), game.height()), (10, 5));
+        assert_eq!(game.count(Tile::Wall), 26);
+        assert_eq!(game.tile(9, 4), Some(Tile::Wall));
+        assert_eq!(game.tile(10, 0), None);
+        let error = game.update(&mut || Some(0)).unwrap_err();
+        assert_eq!(error.to_string(), "tile (10, 2) is outside the 10x5 board");
+    }
+
+    #[test]
+    fn playing_to_a_win() {
+        let mut game = game(&[Some([1, 0, 0]), Some([-1, 0, 50]), None]);
+        assert_eq!(game.outcome(), None);
+        assert_eq!(game.status(), "score: 0 blocks: 1");
+        game.play_move(-1).unwrap();
+        assert_eq!(game.arcade.moves, [-1]);
+        assert_eq!(game.outcome(), Some(Outcome::Won));
+        assert_eq!(game.status(), "YOU WIN! score: 50");
+        // a finished game takes no more moves
+        game.play_move(1).unwrap();
+        assert_eq!(game.moves(), [-1]);
+    }
+
+    #[test]
+    fn playing_to_a_loss() {
+        let mut game = game(&[Some([2, 1, 0]), None, Some([-1, 0, 7])]);
+        game.play_move(0).unwrap();
+        // the game waits for the next move
+        assert_eq!(game.outcome(), None);
+        assert_eq!(game.tile(2, 1), Some(Tile::Empty));
+        game.play_move(0).unwrap();
+        assert_eq!(game.outcome(), Some(Outcome::Lost));
+        assert_eq!(game.status(), "GAME OVER score: 7 blocks: 1");
+        assert_eq!(game.moves(), [0, 0]);
+    }
+
+    #[test]
+    fn advancing_a_number_of_updates() {
+        let mut game = game(&[Some([2, 1, 0]), Some([1, 1, 0]), None, Some([0, 1, 3]), Some([-1, 0, 3])]);
+        assert_eq!(game.advance(0).unwrap(), 0);
+        assert_eq!(game.advance(1).unwrap(), 1);
+        assert_eq!(game.tile(2, 1), Some(Tile::Empty));
+        assert_eq!(game.tile(1, 1), Some(Tile::Paddle));
+        // the move request is answered within the update that draws the next triple
+        assert_eq!(game.advance(2).unwrap(), 2);
+        assert_eq!(game.tile(0, 1), Some(Tile::Paddle));
+        assert_eq!(game.moves().len(), 2);
+        assert_eq!(game.outcome(), None);
+    }
+
+    #[test]
+    fn advancing_stops_early_when_the_game_halts() {
+        let mut game = game(&[Some([2, 1, 0]), Some([-1, 0, 3])]);
+        assert_eq!(game.advance(100).unwrap(), 2);
+        assert_eq!(game.outcome(), Some(Outcome::Lost));
+        assert_eq!(game.score(), 3);
+        assert_eq!(game.advance(100).unwrap(), 0);
+    }
+
+    #[test]
+    fn advancing_stops_early_when_the_blocks_are_cleared() {
+        let mut game = game(&[Some([1, 0, 0]), Some([-1, 0, 50]), Some([2, 1, 0]), Some([0, 1, 4])]);
+        assert_eq!(game.advance(usize::MAX).unwrap(), 2);
+        assert_eq!(game.outcome(), Some(Outcome::Won));
+        assert_eq!(game.tile(2, 1), Some(Tile::Ball));
+    }
+
+    #[test]
+    fn autoplay_records_every_frame() {
+        let path = std::env::temp_dir().join(format!("day13-autoplay-{}.cast", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut game = game(&[Some([1, 0, 0]), Some([-1, 0, 50])]);
+        game.record_to(Recorder::create(path, 3, 4, false).unwrap()).unwrap();
+        game.run_to_completion().unwrap();
+        let text = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        // the header, the initial board, the board after the first move and the final one
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        let (_, _, last): (f64, String, String) = serde_json::from_str(lines[3]).unwrap();
+        assert!(last.ends_with("#.#\r\n.=o\r\n\r\nYOU WIN! score: 50"), "{:?}", last);
+    }
+
+    #[test]
+    fn scripted_scores_and_tile_overwrites() {
+        let mut game = game(&[Some([-1, 0, 12]), Some([1, 0, 4]), Some([-1, 0, 30])]);
+        assert_eq!((game.width(), game.height()), (3, 2));
+        assert_eq!(game.advance(1).unwrap(), 1);
+        assert_eq!(game.score(), 12);
+        // the score channel leaves the board alone
+        assert_eq!(game.screen(), "#O#\n.=o\n\nscore: 12 blocks: 1");
+        // the ball overwrites the block
+        assert_eq!(game.advance(1).unwrap(), 1);
+        assert_eq!(game.tile(1, 0), Some(Tile::Ball));
+        assert_eq!(game.blocks(), 0);
+        assert_eq!(game.autopilot(), 0);
+        // the next score with no blocks left ends the game
+        assert_eq!(game.advance(1).unwrap(), 1);
+        assert_eq!(game.score(), 30);
+        assert_eq!(game.outcome(), Some(Outcome::Won));
+    }
+
+    #[test]
+    fn scripted_invalid_tiles() {
+        let error = Game::from_arcade(Script::new(vec![Some([0, 0, 5])])).err().unwrap();
+        assert_eq!(error.to_string(), "cannot parse tile 5");
+        let mut game = game(&[Some([0, 1, -2])]);
+        assert_eq!(game.update(&mut || Some(0)).unwrap_err().to_string(), "cannot parse tile -2");
+        // the board is left as it was
+        assert_eq!(game.tile(0, 1), Some(Tile::Empty));
+    }
+
+    #[test]
+    fn the_script_waits_for_moves() {
+        let mut script = Script::new(vec![None, Some([0, 0, 1])]);
+        assert_eq!(script.next_triple(&mut || None).unwrap(), None);
+        assert!(script.is_running());
+        assert_eq!(script.next_triple(&mut || Some(-1)).unwrap(), Some([0, 0, 1]));
+        assert_eq!(script.moves, [-1]);
+        assert_eq!(script.next_triple(&mut || Some(1)).unwrap(), None);
+        assert!(!script.is_running());
+    }
+
+    fn program() -> Vec<i64> {
+        intcode::parse_program(include_str!("../input")).unwrap()
+    }
+
+    #[test]
+    fn saving_and_restoring_mid_game() {
+        let expected = Game::new(program()).unwrap().run_to_completion().unwrap();
+        let path = std::env::temp_dir().join(format!("day13-save-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut game = Game::new(program()).unwrap();
+        assert_eq!(game.advance(500).unwrap(), 500);
+        game.save(path).unwrap();
+        let mut restored = Game::new(program()).unwrap();
+        restored.load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(restored.screen(), game.screen());
+        assert_eq!(restored.moves(), game.moves());
+        assert_eq!(restored.run_to_completion().unwrap(), expected);
+        assert_eq!(restored.outcome(), Some(Outcome::Won));
+    }
+
+    #[test]
+    fn restoring_checks_the_save() {
+        let game = Game::new(program()).unwrap();
+        let mut changed = program();
+        changed.push(0);
+        let mut other = Game::new(changed).unwrap();
+        let error = other.restore(game.saved()).err().unwrap();
+        assert_eq!(error.to_string(), "the saved game was played with a different program");
+        let mut saved = game.saved();
+        saved.map.pop();
+        let mut same = Game::new(program()).unwrap();
+        let error = same.restore(saved).err().unwrap();
+        assert_eq!(error.to_string(), format!("the saved {}x{} board has {} tiles", game.width(), game.height(), game.width()*game.height() - 1));
+    }
+
+    #[derive(Default)]
+    struct Log {
+        clears: usize,
+        tiles: Vec<(usize, usize, Tile)>,
+        text: Vec<String>,
+    }
+
+    impl Renderer for Log {
+        fn clear(&mut self) {
+            self.clears += 1;
+        }
+        fn draw_tile(&mut self, x: usize, y: usize, tile: Tile) {
+            self.tiles.push((x, y, tile));
+        }
+        fn draw_text(&mut self, _row: usize, text: &str) {
+            self.text.push(text.to_owned());
+        }
+    }
+
+    #[test]
+    fn only_dirty_tiles_are_drawn() {
+        let mut game = game(&[Some([0, 1, 4]), Some([0, 1, 3]), Some([2, 1, 2]), None]);
+        let mut log = Log::default();
+        game.render(&mut log);
+        assert_eq!((log.clears, log.tiles.len(), log.text.len()), (1, 6, 1));
+        // two updates to the same cell are drawn once, with the last tile
+        game.advance(2).unwrap();
+        assert_eq!(game.dirty.len(), 1);
+        let mut log = Log::default();
+        game.render(&mut log);
+        assert_eq!(log.clears, 0);
+        assert_eq!(log.tiles, [(0, 1, Tile::Paddle)]);
+        assert!(log.text.is_empty());
+        assert!(game.dirty.is_empty());
+        // nothing changed, nothing drawn
+        let mut log = Log::default();
+        game.render(&mut log);
+        assert!(log.tiles.is_empty());
+        // a new block changes the status line too
+        game.advance(1).unwrap();
+        let mut log = Log::default();
+        game.render(&mut log);
+        assert_eq!(log.tiles, [(2, 1, Tile::Block)]);
+        assert_eq!(log.text, ["score: 0 blocks: 2"]);
+        game.redraw();
+        let mut log = Log::default();
+        game.render(&mut log);
+        assert_eq!((log.clears, log.tiles.len(), log.text.len()), (1, 6, 1));
+    }
+
+    #[test]
+    fn rewriting_a_tile_is_not_a_change() {
+        let mut game = game(&[Some([1, 0, 2])]);
+        game.render(&mut NoRenderer);
+        game.advance(1).unwrap();
+        assert!(game.dirty.is_empty());
+    }
+
+    #[test]
+    fn counting_tiles_of_a_drawn_screen() {
+        let screen = vec![
+            Some([0, 0, 1]), Some([1, 0, 2]), Some([2, 0, 2]), Some([3, 0, 1]),
+            Some([0, 1, 1]), Some([1, 1, 2]), Some([2, 1, 0]), Some([3, 1, 1]),
+            Some([0, 2, 1]), Some([1, 2, 3]), Some([2, 2, 4]), Some([3, 2, 1]),
+            // a block drawn over and one redrawn as empty
+            Some([1, 1, 4]), Some([2, 0, 0]),
+        ];
+        let game = Game::from_arcade(Script::new(screen)).unwrap();
+        assert_eq!((game.width(), game.height()), (4, 3));
+        assert_eq!(game.count(Tile::Block), 1);
+        assert_eq!(game.blocks(), 1);
+        let counts = game.counts();
+        assert_eq!(counts.len(), 5);
+        assert_eq!(counts[&Tile::Wall], 6);
+        assert_eq!(counts[&Tile::Block], 1);
+        assert_eq!(counts[&Tile::Empty], 2);
+        assert_eq!(counts[&Tile::Paddle], 1);
+        assert_eq!(counts[&Tile::Ball], 2);
+        assert_eq!(counts.values().sum::<usize>(), 12);
+    }
+
+    #[test]
+    fn drawing_the_real_screen() {
+        let game = Game::draw_only(program()).unwrap();
+        assert_eq!(game.outcome(), Some(Outcome::Lost));
+        assert_eq!(game.count(Tile::Block), game.blocks());
+        assert_eq!(game.count(Tile::Paddle), 1);
+        assert_eq!(game.count(Tile::Ball), 1);
+    }
+
+    #[test]
+    fn a_bad_tile_stops_the_game() {
+        let mut game = game(&[Some([2, 1, 0]), Some([1, 0, 9]), Some([1, 0, 0]), Some([-1, 0, 5])]);
+        let result = game.advance(10);
+        assert_eq!(result.as_ref().unwrap_err().to_string(), "cannot parse tile 9");
+        game.keep_error(result);
+        assert_eq!(game.error().unwrap().to_string(), "cannot parse tile 9");
+        assert_eq!(game.status(), "ERROR: cannot parse tile 9");
+        // the game no longer advances, leaving the board that led to the error
+        assert_eq!(game.advance(10).unwrap(), 0);
+        assert_eq!(game.tile(1, 0), Some(Tile::Block));
+        assert_eq!(game.tile(2, 1), Some(Tile::Empty));
+        let mut log = Log::default();
+        game.render(&mut log);
+        assert_eq!(log.text, ["ERROR: cannot parse tile 9"]);
+        assert_eq!(game.take_error().unwrap().to_string(), "cannot parse tile 9");
+        assert!(game.error().is_none());
+    }
+
+    #[test]
+    fn replaying_dumped_moves() {
+        let mut autoplay = Game::new(program()).unwrap();
+        let score = autoplay.run_to_completion().unwrap();
+        let moves = autoplay.moves().to_vec();
+        let mut replay = Game::new(program()).unwrap();
+        assert_eq!(replay.run_scripted(&moves, true).unwrap(), score);
+        assert_eq!(replay.screen(), autoplay.screen());
+        assert_eq!(replay.moves(), &moves[..]);
+    }
+
+    #[test]
+    fn running_out_of_moves() {
+        let mut autoplay = Game::new(program()).unwrap();
+        let score = autoplay.run_to_completion().unwrap();
+        let moves = &autoplay.moves()[..100];
+        let mut strict = Game::new(program()).unwrap();
+        let error = strict.run_scripted(moves, true).unwrap_err();
+        assert_eq!(error.to_string(), "the joystick moves ran out after 100 moves");
+        // otherwise the autopilot takes over
+        let mut lenient = Game::new(program()).unwrap();
+        assert_eq!(lenient.run_scripted(moves, false).unwrap(), score);
+        assert_eq!(lenient.screen(), autoplay.screen());
+    }
+}
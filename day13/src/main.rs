@@ -1,163 +1,334 @@
 use anyhow::Result;
 use anyhow::anyhow;
-use std::collections::HashMap;
-use rltk::{Rltk, GameState, Console, VirtualKeyCode};
+use std::cell::RefCell;
+use std::rc::Rc;
+use rltk::{Rltk, GameState, Console, VirtualKeyCode, RGB};
+use day13::game::{Game, Renderer, Tile};
+use day13::record::{self, Recorder};
 
 fn parse() -> Result<Vec<i64>> {
-    std::fs::read_to_string("input")?
-        .trim()
-        .split(',')
-        .map(|s| s.parse().map_err(std::convert::From::from))
-        .collect()
+    Ok(intcode::parse_program(&std::fs::read_to_string("input")?)?)
 }
 
-fn part1(v: Vec<i64>) -> Result<impl std::fmt::Display> {
-    let mut vm = intcode::Vm::new(v);
-    let mut map = HashMap::new();
-    loop {
-        if let Some(x) = vm.run_until_output()? {
-            let y = vm.run_until_output()?.ok_or_else(||anyhow!("no y coord"))?;
-            let t = vm.run_until_output()?.ok_or_else(||anyhow!("no tile"))?;
-            map.insert((x,y), t);
-        } else {
-            break;
-        }
+fn part1(board: &Game) -> impl std::fmt::Display {
+    board.count(Tile::Block)
+}
+
+fn summary(board: &Game) -> String {
+    let counts = board.counts();
+    let count = |tile| counts.get(&tile).copied().unwrap_or(0);
+    format!("board: {}x{}, {} walls, {} blocks", board.width(), board.height(), count(Tile::Wall), count(Tile::Block))
+}
+
+// Foreground and background, as rgb triples.
+fn colors(tile: Tile) -> ((u8, u8, u8), (u8, u8, u8)) {
+    match tile {
+        Tile::Empty => (rltk::BLACK, rltk::BLACK),
+        Tile::Wall => (rltk::GREY, rltk::BLACK),
+        Tile::Block => (rltk::YELLOW, rltk::BLACK),
+        Tile::Paddle => (rltk::CYAN, rltk::BLACK),
+        Tile::Ball => (rltk::RED, rltk::BLACK),
     }
-    let count = map.values().filter(|&&t| t==2).count();
-    Ok(count)
-}
-
-#[derive(Clone, Copy, Eq, PartialEq)]
-enum Tile {
-    Empty,
-    Wall,
-    Block,
-    Paddle,
-    Ball,
-}
-impl Tile {
-    fn parse(i: i64) -> Result<Tile> {
-        match i {
-            0 => Ok(Tile::Empty),
-            1 => Ok(Tile::Wall),
-            2 => Ok(Tile::Block),
-            3 => Ok(Tile::Paddle),
-            4 => Ok(Tile::Ball),
-            _ => Err(anyhow!("cannot parse tile")),
-        }
+}
+
+struct RltkRenderer<'a>(&'a mut Rltk);
+
+impl Renderer for RltkRenderer<'_> {
+    fn clear(&mut self) {
+        self.0.cls();
+    }
+    fn draw_tile(&mut self, x: usize, y: usize, tile: Tile) {
+        let (fg, bg) = colors(tile);
+        self.0.print_color(x as i32, y as i32, RGB::named(fg), RGB::named(bg), &tile.glyph().to_string());
+    }
+    fn draw_text(&mut self, row: usize, text: &str) {
+        self.0.print(0, row as i32, text);
     }
 }
-struct Game {
-    vm: intcode::Vm,
-    score: i64,
-    map: Vec<Tile>,
-    ball: i64,
-    paddle: i64,
-}
-
-const WIDTH: usize = 44;
-const HEIGHT: usize = 24;
-impl Game {
-    fn new(mut mem: Vec<i64>) -> Game {
-        mem[0] = 2;
-        let mut map = Vec::new();
-        map.resize(WIDTH*HEIGHT, Tile::Empty);
-        Game {
-            vm: intcode::Vm::new(mem),
-            map,
-            score: 0,
-            ball: 0,
-            paddle: 0,
-        }
+
+// Saving, loading, redrawing and quitting, shared by the interactive modes.
+struct Session {
+    save_file: Option<String>,
+    message: String,
+}
+
+impl Session {
+    // Returns whether the key was one of the session keys.
+    fn handle_key(&mut self, ctx: &mut Rltk, game: &mut Game) -> bool {
+        let path = self.save_file.as_deref();
+        let result = match (ctx.key, path) {
+            (Some(VirtualKeyCode::S), Some(path)) => game.save(path).map(|()| format!("saved to {}", path)),
+            (Some(VirtualKeyCode::L), Some(path)) => game.load(path).map(|()| format!("loaded {}", path)),
+            (Some(VirtualKeyCode::S), None) | (Some(VirtualKeyCode::L), None) => Ok("no save file, pass --save-file".to_owned()),
+            (Some(VirtualKeyCode::R), _) => {
+                game.redraw();
+                return true;
+            },
+            (Some(VirtualKeyCode::Q), _) | (Some(VirtualKeyCode::Escape), _) => {
+                // progress is kept on exit
+                if let Some(path) = path {
+                    if let Err(e) = game.save(path) {
+                        eprintln!("cannot save to {}: {}", path, e);
+                    }
+                }
+                ctx.quit();
+                return true;
+            },
+            _ => return false,
+        };
+        self.message = result.unwrap_or_else(|e| format!("error: {}", e));
+        true
     }
-    fn map_at(&mut self, x: i64, y: i64) -> Result<&mut Tile> {
-        if x < 0 || x >= (WIDTH as i64) || y < 0 || y >= (HEIGHT as i64) {
-            Err(anyhow!("out of map access"))
-        } else {
-            Ok(&mut self.map[x as usize + WIDTH*(y as usize)])
-        }
+    fn draw(&self, ctx: &mut Rltk, row: usize, width: usize) {
+        ctx.print(0, row as i32, &format!("{:1$}", self.message, width));
     }
-    fn update(&mut self) -> Result<bool> {
-        if !self.vm.is_running() {
-            return Ok(false);
-        }
-        let ball = self.ball;
-        let paddle = self.paddle;
-        let input = || {
-            if ball == paddle {
+}
+
+// past this a tick takes longer than a frame anyway
+const MAX_SPEED: usize = 1 << 16;
+struct Watch {
+    game: Rc<RefCell<Game>>,
+    speed: usize,
+    paused: bool,
+    session: Session,
+}
+
+impl GameState for Watch {
+    fn tick(&mut self, ctx: &mut Rltk) {
+        let mut game = self.game.borrow_mut();
+        let handled = self.session.handle_key(ctx, &mut game);
+        let updates = match ctx.key {
+            _ if handled => 0,
+            Some(VirtualKeyCode::Add) | Some(VirtualKeyCode::Equals) => {
+                self.speed = self.speed.saturating_mul(2).min(MAX_SPEED);
+                0
+            },
+            Some(VirtualKeyCode::Subtract) | Some(VirtualKeyCode::Minus) => {
+                self.speed = (self.speed / 2).max(1);
                 0
-            } else if ball < paddle {
-                -1
-            } else {
-                1
-            }
+            },
+            Some(VirtualKeyCode::P) => {
+                self.paused = !self.paused;
+                0
+            },
+            Some(VirtualKeyCode::Period) if self.paused => 1,
+            _ if self.paused => 0,
+            _ => self.speed,
         };
-        if let Some(x) = self.vm.run_until_output_with_input(input)? {
-            let y = self.vm.run_until_output_with_input(input)?.ok_or_else(||anyhow!("no y coord"))?;
-            let t = self.vm.run_until_output_with_input(input)?.ok_or_else(||anyhow!("no tile"))?;
-
-            if x == -1 {
-                self.score = t;
-            } else {
-                let tile = Tile::parse(t)?;
-                *self.map_at(x, y)? = tile;
-                if tile == Tile::Ball {
-                    self.ball = x;
-                } else if tile == Tile::Paddle {
-                    self.paddle = x;
-                }
-            }
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        let result = game.advance(updates);
+        game.keep_error(result);
+        game.render(&mut RltkRenderer(ctx));
+        let state = if self.paused { " (paused)" } else { "" };
+        let speed = format!("speed: {}{}", self.speed, state);
+        ctx.print(0, (game.height()+2) as i32, &format!("{:1$}", speed, game.width()));
+        self.session.draw(ctx, game.height()+3, game.width());
     }
 }
 
-impl GameState for Game {
+// the game advances one move every MOVE_MS, steered with the arrow keys
+const MOVE_MS: f32 = 100.0;
+struct Play {
+    game: Rc<RefCell<Game>>,
+    joystick: i64,
+    elapsed_ms: f32,
+    session: Session,
+}
+
+impl GameState for Play {
     fn tick(&mut self, ctx: &mut Rltk) {
-        if !self.update().expect("update error") {
-            //ctx.quit();
-            return;
+        let mut game = self.game.borrow_mut();
+        let handled = self.session.handle_key(ctx, &mut game);
+        match ctx.key {
+            _ if handled => {},
+            Some(VirtualKeyCode::Left) => self.joystick = -1,
+            Some(VirtualKeyCode::Right) => self.joystick = 1,
+            _ => {},
+        }
+        self.elapsed_ms += ctx.frame_time_ms;
+        if self.elapsed_ms >= MOVE_MS && game.outcome().is_none() {
+            self.elapsed_ms = 0.0;
+            let result = game.play_move(self.joystick);
+            game.keep_error(result);
+            self.joystick = 0;
         }
-        ctx.cls();
-        for x in 0..WIDTH {
-            for y in 0..HEIGHT {
-                let ch = match *self.map_at(x as i64, y as i64).unwrap() {
-                    Tile::Empty => {
-                        "."
-                    },
-                    Tile::Block => {
-                        "O"
-                    },
-                    Tile::Wall => {
-                        "#"
-                    },
-                    Tile::Paddle => {
-                        "="
-                    },
-                    Tile::Ball => {
-                        "o"
-                    },
-                };
-                ctx.print(x as i32, y as i32, ch);
-            }
+        game.render(&mut RltkRenderer(ctx));
+        self.session.draw(ctx, game.height()+2, game.width());
+    }
+}
+
+fn session(game: &mut Game, save_file: Option<&str>) -> Result<Session> {
+    let mut message = String::new();
+    if let Some(path) = save_file {
+        if std::path::Path::new(path).exists() {
+            game.load(path).map_err(|e| anyhow!("cannot restore {}: {}", path, e))?;
+            message = format!("restored {}", path);
         }
-        ctx.print(0, (HEIGHT+1) as i32, &format!("score: {}", self.score));
     }
+    Ok(Session { save_file: save_file.map(str::to_owned), message })
+}
+
+fn new_game(v: Vec<i64>, record: Option<&str>, realtime: bool) -> Result<Game> {
+    let mut game = Game::new(v)?;
+    if let Some(path) = record {
+        let recorder = Recorder::create(path, game.width(), game.height() + 2, realtime)?;
+        game.record_to(recorder)?;
+    }
+    Ok(game)
+}
+
+fn read_joystick(path: &str) -> Result<Vec<i64>> {
+    let text = std::fs::read_to_string(path).map_err(|e| anyhow!("cannot read {}: {}", path, e))?;
+    text.split_whitespace()
+        .map(|token| match token.parse() {
+            Ok(tilt @ -1..=1) => Ok(tilt),
+            _ => Err(anyhow!("invalid joystick move `{}` in {}, expected -1, 0 or 1", token, path)),
+        })
+        .collect()
+}
+
+fn write_joystick(path: &str, moves: &[i64]) -> Result<()> {
+    let text: Vec<String> = moves.iter().map(|tilt| tilt.to_string()).collect();
+    std::fs::write(path, text.join(" ") + "\n").map_err(|e| anyhow!("cannot write {}: {}", path, e))
+}
+
+fn part2(v: Vec<i64>, options: &Options) -> Result<impl std::fmt::Display> {
+    let mut game = new_game(v, options.record.as_deref(), false)?;
+    let score = match &options.joystick {
+        Some(path) => game.run_scripted(&read_joystick(path)?, options.strict)?,
+        None => game.run_to_completion()?,
+    };
+    if let Some(path) = &options.dump_joystick {
+        write_joystick(path, game.moves())?;
+    }
+    Ok(score)
+}
+
+// An error stops the game but leaves the window open on it. Once the window is
+// closed, it becomes the error of the whole program.
+fn finish(game: &RefCell<Game>) -> Result<()> {
+    game.borrow_mut().take_error().map_or(Ok(()), Err)
+}
+
+fn watch(v: Vec<i64>, speed: usize, record: Option<&str>, save_file: Option<&str>) -> Result<()> {
+    let mut game = new_game(v, record, true)?;
+    let session = session(&mut game, save_file)?;
+    let ctx = Rltk::init_simple8x8(game.width() as u32, (game.height()+4) as u32, "breakout", "resources");
+    let game = Rc::new(RefCell::new(game));
+    rltk::main_loop(ctx, Watch { game: Rc::clone(&game), speed, paused: false, session });
+    finish(&game)
+}
+
+fn play(v: Vec<i64>, record: Option<&str>, save_file: Option<&str>) -> Result<()> {
+    let mut game = new_game(v, record, true)?;
+    let session = session(&mut game, save_file)?;
+    let ctx = Rltk::init_simple8x8(game.width() as u32, (game.height()+3) as u32, "breakout", "resources");
+    let game = Rc::new(RefCell::new(game));
+    rltk::main_loop(ctx, Play { game: Rc::clone(&game), joystick: 0, elapsed_ms: 0.0, session });
+    finish(&game)
 }
-fn part2(v: Vec<i64>) -> Result<impl std::fmt::Display> {
-    let game = Game::new(v);
-    let ctx = Rltk::init_simple8x8(WIDTH as u32, (HEIGHT+2) as u32, "breakout", "resources");
-    rltk::main_loop(ctx, game);
-    Ok(1)
+
+struct Options {
+    watch: bool,
+    play: bool,
+    speed: usize,
+    record: Option<String>,
+    replay: Option<String>,
+    save_file: Option<String>,
+    joystick: Option<String>,
+    dump_joystick: Option<String>,
+    strict: bool,
+}
+
+fn parse_args() -> Result<Options> {
+    let mut options = Options {
+        watch: false,
+        play: false,
+        speed: 1,
+        record: None,
+        replay: None,
+        save_file: None,
+        joystick: None,
+        dump_joystick: None,
+        strict: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--watch" => options.watch = true,
+            "--play" => options.play = true,
+            "--speed" => options.speed = value()?.parse::<usize>()?.clamp(1, MAX_SPEED),
+            "--record" => options.record = Some(value()?),
+            "--replay" => options.replay = Some(value()?),
+            "--save-file" => options.save_file = Some(value()?),
+            "--joystick" => options.joystick = Some(value()?),
+            "--dump-joystick" => options.dump_joystick = Some(value()?),
+            "--strict" => options.strict = true,
+            _ => return Err(anyhow!("unknown argument `{}`", arg)),
+        }
+    }
+    Ok(options)
 }
 
 fn main() -> Result<()> {
+    let options = parse_args()?;
+    if let Some(path) = &options.replay {
+        return record::replay(path);
+    }
     let v = parse()?;
-    let p1 = part1(v.clone())?;
-    println!("part 1: {}", p1);
-    let p2 = part2(v)?;
+    let record = options.record.as_deref();
+    let save_file = options.save_file.as_deref();
+    if options.play {
+        return play(v, record, save_file);
+    }
+    if options.watch {
+        return watch(v, options.speed, record, save_file);
+    }
+    let board = Game::draw_only(v.clone())?;
+    println!("part 1: {}", part1(&board));
+    println!("{}", summary(&board));
+    let p2 = part2(v, &options)?;
     println!("part 2: {}", p2);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn joystick_files_round_trip() {
+        let path = std::env::temp_dir().join(format!("day13-joystick-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_joystick(path, &[-1, 0, 1, 1]).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "-1 0 1 1\n");
+        assert_eq!(read_joystick(path).unwrap(), [-1, 0, 1, 1]);
+        std::fs::write(path, "0\n1 2").unwrap();
+        let error = read_joystick(path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(error.to_string(), format!("invalid joystick move `2` in {}, expected -1, 0 or 1", path));
+    }
+
+    #[test]
+    fn tiles_have_distinct_colors() {
+        let tiles = [Tile::Wall, Tile::Block, Tile::Paddle, Tile::Ball];
+        let distinct: HashSet<_> = tiles.iter().map(|&t| colors(t)).collect();
+        assert_eq!(distinct.len(), tiles.len());
+        assert_eq!(colors(Tile::Wall), (rltk::GREY, rltk::BLACK));
+        assert_eq!(colors(Tile::Block), (rltk::YELLOW, rltk::BLACK));
+        assert_eq!(colors(Tile::Paddle), (rltk::CYAN, rltk::BLACK));
+        assert_eq!(colors(Tile::Ball), (rltk::RED, rltk::BLACK));
+        // empty cells are invisible
+        assert_eq!(colors(Tile::Empty).0, colors(Tile::Empty).1);
+    }
+
+    #[test]
+    fn window_errors_become_the_program_error() {
+        // a single wall, drawn before halting
+        let game = RefCell::new(Game::draw_only(vec![104, 0, 104, 0, 104, 1, 99]).unwrap());
+        assert!(finish(&game).is_ok());
+        game.borrow_mut().keep_error::<()>(Err(anyhow!("cannot parse tile 9")));
+        assert_eq!(finish(&game).unwrap_err().to_string(), "cannot parse tile 9");
+    }
+}
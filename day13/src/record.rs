@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+// headless games have no clock of their own, so their frames are spaced evenly
+const HEADLESS_FRAME_SECS: f64 = 0.02;
+
+// Writes an asciinema v2 cast: a JSON header line, then one `[time, "o", text]` event per frame.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Option<Instant>,
+    frames: usize,
+}
+
+impl Recorder {
+    pub fn create(path: &str, width: usize, height: usize, realtime: bool) -> Result<Recorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", serde_json::json!({ "version": 2, "width": width, "height": height }))?;
+        Ok(Recorder {
+            writer,
+            start: if realtime { Some(Instant::now()) } else { None },
+            frames: 0,
+        })
+    }
+    pub fn frame(&mut self, screen: &str) -> Result<()> {
+        let time = match self.start {
+            Some(start) => start.elapsed().as_secs_f64(),
+            None => self.frames as f64 * HEADLESS_FRAME_SECS,
+        };
+        self.frames += 1;
+        // clear and home the cursor before redrawing; terminals want \r\n line breaks
+        let text = format!("\x1b[H\x1b[2J{}", screen.replace('\n', "\r\n"));
+        writeln!(self.writer, "{}", serde_json::to_string(&(time, "o", text))?)?;
+        Ok(())
+    }
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+pub fn replay(path: &str) -> Result<()> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header = lines.next().ok_or_else(|| anyhow!("{} is empty", path))??;
+    let header: serde_json::Value = serde_json::from_str(&header)?;
+    if header["version"] != 2 {
+        return Err(anyhow!("{} is not an asciicast v2 recording", path));
+    }
+    let start = Instant::now();
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for line in lines {
+        let (time, kind, text): (f64, String, String) = serde_json::from_str(&line?)?;
+        if kind != "o" {
+            continue;
+        }
+        let at = Duration::try_from_secs_f64(time).map_err(|_| anyhow!("invalid frame time {}", time))?;
+        if let Some(wait) = at.checked_sub(start.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        write!(out, "{}", text)?;
+        out.flush()?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("day13-{}-{}.cast", name, std::process::id()));
+        path.to_str().unwrap().to_owned()
+    }
+
+    fn events(path: &str) -> (serde_json::Value, Vec<(f64, String, String)>) {
+        let text = std::fs::read_to_string(path).unwrap();
+        let mut lines = text.lines();
+        let header = serde_json::from_str(lines.next().unwrap()).unwrap();
+        let events = lines.map(|line| serde_json::from_str(line).unwrap()).collect();
+        (header, events)
+    }
+
+    #[test]
+    fn headless_frames_parse_back() {
+        let path = temp_path("frames");
+        let mut recorder = Recorder::create(&path, 3, 4, false).unwrap();
+        recorder.frame("#O#\n.=o\n\nscore: 0 blocks: 1").unwrap();
+        recorder.frame("#.#\n.=o\n\nscore: 5 blocks: 0").unwrap();
+        recorder.flush().unwrap();
+        let (header, events) = events(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(header, serde_json::json!({ "version": 2, "width": 3, "height": 4 }));
+        assert_eq!(events, [
+            (0.0, "o".to_owned(), "\x1b[H\x1b[2J#O#\r\n.=o\r\n\r\nscore: 0 blocks: 1".to_owned()),
+            (HEADLESS_FRAME_SECS, "o".to_owned(), "\x1b[H\x1b[2J#.#\r\n.=o\r\n\r\nscore: 5 blocks: 0".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn realtime_frames_are_ordered() {
+        let path = temp_path("realtime");
+        let mut recorder = Recorder::create(&path, 1, 1, true).unwrap();
+        for _ in 0..3 {
+            recorder.frame("o").unwrap();
+        }
+        recorder.flush().unwrap();
+        let (_, events) = events(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(events.len(), 3);
+        assert!(events.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    }
+
+    #[test]
+    fn replay_rejects_other_formats() {
+        let path = temp_path("v1");
+        std::fs::write(&path, "{\"version\": 1}\n").unwrap();
+        let error = replay(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.to_string(), format!("{} is not an asciicast v2 recording", path));
+    }
+}
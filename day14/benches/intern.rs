@@ -0,0 +1,94 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::collections::{HashMap, HashSet};
+
+use day14::recipes::{self, RecipeBook};
+
+// the largest official example, 2210736 ORE for a FUEL
+const EXAMPLE: &str = "\
+171 ORE => 8 CNZTR
+7 ZLQW, 3 BMBT, 9 XCVML, 26 XMNCP, 1 WPTQ, 2 MZWV, 1 RJRHP => 4 PLWSL
+114 ORE => 4 BHXH
+14 VRPVC => 6 BMBT
+6 BHXH, 18 KTJDG, 12 WPTQ, 7 PLWSL, 31 FHTLT, 37 ZDVW => 1 FUEL
+6 WPTQ, 2 BMBT, 8 ZLQW, 18 KTJDG, 1 XMNCP, 6 MZWV, 1 RJRHP => 6 FHTLT
+15 XDBXC, 2 LTCX, 1 VRPVC => 6 ZLQW
+13 WPTQ, 10 LTCX, 3 RJRHP, 14 XMNCP, 2 MZWV, 1 ZLQW => 1 ZDVW
+5 BMBT => 4 WPTQ
+189 ORE => 9 KTJDG
+1 MZWV, 17 XDBXC, 3 XCVML => 2 XMNCP
+12 VRPVC, 27 CNZTR => 2 XDBXC
+15 KTJDG, 12 BHXH => 5 XCVML
+3 BHXH, 2 VRPVC => 7 MZWV
+121 ORE => 7 VRPVC
+7 XCVML => 6 RJRHP
+5 BHXH, 4 VRPVC => 5 LTCX
+";
+
+// the output quantity and the ingredients of the reaction making each chemical
+type Reactions = HashMap<String, (i64, Vec<(String, i64)>)>;
+
+// The same walk in topological order, keyed by chemical names as before they were interned.
+struct NamedBook {
+    reactions: Reactions,
+    order: Vec<String>,
+}
+
+fn ingredient(text: &str) -> (String, i64) {
+    let (quantity, chemical) = text.split_once(' ').unwrap();
+    (chemical.to_owned(), quantity.parse().unwrap())
+}
+
+fn visit(chemical: &str, reactions: &Reactions, done: &mut HashSet<String>, order: &mut Vec<String>) {
+    if !done.insert(chemical.to_owned()) {
+        return;
+    }
+    if let Some((_, inputs)) = reactions.get(chemical) {
+        for (input, _) in inputs {
+            visit(input, reactions, done, order);
+        }
+    }
+    order.push(chemical.to_owned());
+}
+
+fn named_book(text: &str) -> NamedBook {
+    let mut reactions = HashMap::new();
+    for line in text.lines() {
+        let (inputs, output) = line.split_once(" => ").unwrap();
+        let (chemical, quantity) = ingredient(output);
+        reactions.insert(chemical, (quantity, inputs.split(", ").map(ingredient).collect()));
+    }
+    let (mut done, mut order) = (HashSet::new(), Vec::new());
+    visit("FUEL", &reactions, &mut done, &mut order);
+    order.reverse();
+    NamedBook { reactions, order }
+}
+
+fn named_ore(book: &NamedBook, amount: i64) -> i64 {
+    let mut needed: HashMap<String, i64> = HashMap::new();
+    needed.insert("FUEL".to_owned(), amount);
+    for chemical in &book.order {
+        let need = needed.get(chemical).copied().unwrap_or(0);
+        if let Some((output, inputs)) = book.reactions.get(chemical) {
+            let runs = (need + output - 1) / output;
+            for (input, quantity) in inputs {
+                *needed.entry(input.clone()).or_insert(0) += runs*quantity;
+            }
+        }
+    }
+    needed["ORE"]
+}
+
+fn ore(c: &mut Criterion) {
+    let named = named_book(EXAMPLE);
+    let interned = RecipeBook::new(recipes::parse(EXAMPLE).unwrap()).unwrap();
+    for amount in [1, 1000, 460664] {
+        assert_eq!(named_ore(&named, amount), recipes::ore_cost(&interned, "FUEL", amount).unwrap());
+    }
+    let mut group = c.benchmark_group("ore for 460664 FUEL");
+    group.bench_function("names", |b| b.iter(|| named_ore(&named, 460664)));
+    group.bench_function("interned", |b| b.iter(|| recipes::ore_cost(&interned, "FUEL", 460664).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, ore);
+criterion_main!(benches);
@@ -1,127 +1,66 @@
 use anyhow::{anyhow, Result};
-use std::str::FromStr;
-use nom::{
-    IResult,
-    character::complete::{digit1, alpha1},
-    bytes::complete::tag,
-    combinator::{opt, map_res, recognize, map},
-    sequence::{pair, separated_pair},
-    multi::separated_list,
-};
-use std::collections::HashMap;
-use num::Integer;
+use day14::recipes::{self, RecipeBook, Recipe};
 
 fn parse() -> Result<Vec<Recipe>> {
-    std::fs::read_to_string("input")?
-        .trim()
-        .lines()
-        .map(FromStr::from_str)
-        .collect()
+    recipes::parse(&std::fs::read_to_string("input")?)
 }
 
-#[derive(Clone, Debug)]
-struct Ingredient {
-    chemical: String,
-    quantity: i64,
-}
+const ORE_BUDGET: i64 = 1_000_000_000_000;
 
-#[derive(Clone, Debug)]
-struct Recipe {
-    output: Ingredient,
-    inputs: Vec<Ingredient>,
+struct Options {
+    target: String,
+    amount: Option<i64>,
+    budget: Option<i64>,
+    report: bool,
 }
 
-fn parser(s: &str) -> IResult<&str, Recipe> {
-    let int_parse = || map_res(recognize(pair(opt(tag("-")), digit1)), |s: &str| s.parse::<i64>());
-    let str_parse = alpha1;
-    let ing_parse = || map(separated_pair(int_parse(), tag(" "), str_parse), |(q, c)| Ingredient { chemical: c.to_owned(), quantity: q });
-    let ing_seq_parse = separated_list(tag(", "), ing_parse());
-    let recipe_parse = map(separated_pair(ing_seq_parse, tag(" => "), ing_parse()), |(i, o)| Recipe { output: o, inputs: i });
-    recipe_parse(s)
+fn parse_args() -> Result<Options> {
+    let mut options = Options {
+        target: "FUEL".to_owned(),
+        amount: None,
+        budget: None,
+        report: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--target" => options.target = value()?,
+            "--amount" => match value()?.parse()? {
+                amount if amount > 0 => options.amount = Some(amount),
+                _ => return Err(anyhow!("--amount must be positive")),
+            },
+            "--budget" => options.budget = Some(value()?.parse()?),
+            "--report" => options.report = true,
+            _ => return Err(anyhow!("unknown argument `{}`", arg)),
+        }
+    }
+    Ok(options)
 }
 
-impl FromStr for Recipe {
-    type Err = anyhow::Error;
-    fn from_str(s: &str) -> Result<Recipe> {
-        let (_, v) = parser(s).map_err(|_|anyhow!("failed to parse Recipe"))?;
-        Ok(v)
+fn report(book: &RecipeBook, chemical: &str, amount: i64) -> Result<()> {
+    println!("{:>10} {:>10} {:>10} {:>10} {:>10}", "chemical", "runs", "produced", "consumed", "surplus");
+    for u in recipes::usage(book, chemical, amount)? {
+        println!("{:>10} {:>10} {:>10} {:>10} {:>10}", u.chemical, u.runs, u.produced, u.consumed, u.surplus);
     }
+    Ok(())
 }
 
-fn craft(ingredient: Ingredient, recipe_book: &HashMap<String, Recipe>, reserve: &mut HashMap<String, i64>) {
-    if ingredient.chemical == "ORE" {
-        return;
-    }
-    let recipe = &recipe_book[&ingredient.chemical];
-    let runs = -(-ingredient.quantity).div_floor(&recipe.output.quantity);
-    *reserve.entry(recipe.output.chemical.clone()).or_default() += runs*recipe.output.quantity;
-    for i in &recipe.inputs {
-        *reserve.entry(i.chemical.clone()).or_default() -= runs*i.quantity;
+fn main() -> Result<()> {
+    let options = parse_args()?;
+    let book = RecipeBook::new(parse()?)?;
+    // with neither flag, answer both parts of the puzzle
+    let both = options.amount.is_none() && options.budget.is_none();
+    if both || options.amount.is_some() {
+        let p1 = recipes::ore_cost(&book, &options.target, options.amount.unwrap_or(1))?;
+        println!("part 1: {}", p1);
     }
-}
-fn balance(recipe_book: &HashMap<String, Recipe>, reserve: &mut HashMap<String, i64>) {
-    loop {
-        let mut to_balance = Vec::new();
-        for chem in reserve.keys() {
-            if chem != "ORE" && reserve[chem] < 0 {
-                to_balance.push(chem.to_owned());
-            }
-        }
-        if to_balance.len() == 0 {
-            return;
-        }
-        for chem in to_balance {
-            let quantity = -reserve[&chem];
-            craft(Ingredient { chemical: chem, quantity}, recipe_book, reserve);
-        }
+    if both || options.budget.is_some() {
+        let p2 = recipes::max_craftable(&book, &options.target, options.budget.unwrap_or(ORE_BUDGET))?;
+        println!("part 2: {}", p2);
     }
-}
-fn part1(recipes: Vec<Recipe>) -> Result<impl std::fmt::Display> {
-    let recipe_book = {
-        let mut recipe_book = HashMap::new();
-        for r in recipes {
-            recipe_book.insert(r.output.chemical.clone(), r);
-        }
-        recipe_book
-    };
-    let mut reserve = HashMap::new();
-    let fuel = Ingredient { chemical: "FUEL".to_owned(), quantity: 1};
-    craft(fuel, &recipe_book, &mut reserve);
-    balance(&recipe_book, &mut reserve);
-    Ok(-reserve["ORE"])
-}
-fn part2(recipes: Vec<Recipe>) -> Result<impl std::fmt::Display> {
-    let recipe_book = {
-        let mut recipe_book = HashMap::new();
-        for r in recipes {
-            recipe_book.insert(r.output.chemical.clone(), r);
-        }
-        recipe_book
-    };
-    let mut reserve = HashMap::new();
-    reserve.insert("ORE".to_owned(), 1_000_000_000_000);
-    let mut fuel_q = 0;
-    let mut q = 1_877_913;
-    loop {
-        let fuel = Ingredient { chemical: "FUEL".to_owned(), quantity: q};
-        craft(fuel, &recipe_book, &mut reserve);
-        balance(&recipe_book, &mut reserve);
-        if reserve["ORE"] < 0 {
-            break;
-        }
-        fuel_q += q;
-        if q > 1 {
-            q = 1;
-        }
+    if options.report {
+        report(&book, &options.target, options.amount.unwrap_or(1))?;
     }
-    Ok(fuel_q)
-}
-
-fn main() -> Result<()> {
-    let v = parse()?;
-    let p1 = part1(v.clone())?;
-    println!("part 1: {}", p1);
-    let p2 = part2(v)?;
-    println!("part 2: {}", p2);
     Ok(())
 }
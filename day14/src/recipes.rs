@@ -0,0 +1,494 @@
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use nom::{
+    IResult,
+    character::complete::{digit1, alpha1},
+    bytes::complete::tag,
+    combinator::{opt, map_res, recognize, map},
+    sequence::{pair, separated_pair},
+    multi::separated_list,
+};
+use std::collections::HashMap;
+use thiserror::Error;
+use num::Integer;
+
+pub fn parse(text: &str) -> Result<Vec<Recipe>> {
+    // blank lines still count, so the numbers match the file
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            let mut recipe: Recipe = line.parse().map_err(|e| anyhow!("line {}: {}", n + 1, e))?;
+            recipe.line = n + 1;
+            Ok(recipe)
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+struct Ingredient {
+    chemical: String,
+    quantity: i64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Recipe {
+    output: Ingredient,
+    inputs: Vec<Ingredient>,
+    // where the recipe is in the input, for errors
+    line: usize,
+}
+
+fn parser(s: &str) -> IResult<&str, Recipe> {
+    let int_parse = || map_res(recognize(pair(opt(tag("-")), digit1)), |s: &str| s.parse::<i64>());
+    let str_parse = alpha1;
+    let ing_parse = || map(separated_pair(int_parse(), tag(" "), str_parse), |(q, c)| Ingredient { chemical: c.to_owned(), quantity: q });
+    let ing_seq_parse = separated_list(tag(", "), ing_parse());
+    let recipe_parse = map(separated_pair(ing_seq_parse, tag(" => "), ing_parse()), |(i, o)| Recipe { output: o, inputs: i, line: 0 });
+    recipe_parse(s)
+}
+
+impl FromStr for Recipe {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Recipe> {
+        match parser(s) {
+            Ok(("", v)) => Ok(v),
+            Ok((rest, _)) => Err(anyhow!("unexpected `{}` after the recipe in `{}`", rest, s)),
+            Err(_) => Err(anyhow!("cannot parse `{}`, expected a recipe like `7 A, 1 B => 1 C`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RecipeError {
+    #[error("no recipe produces {0}")]
+    MissingRecipe(String),
+    #[error("line {line}: no recipe produces {chemical}")]
+    UnknownInput { chemical: String, line: usize },
+    #[error("line {line}: {chemical} is already produced on line {first}")]
+    DuplicateOutput { chemical: String, line: usize, first: usize },
+    #[error("line {line}: ORE cannot be produced")]
+    OreOutput { line: usize },
+    #[error("line {line}: the quantity of {chemical} must be positive")]
+    NonPositive { chemical: String, line: usize },
+    #[error("the recipe for {0} depends on itself")]
+    Cycle(String),
+    #[error("{0} is made without ORE, there is no limit to how much can be crafted")]
+    NoOre(String),
+    #[error("crafting that much {0} overflows an i64")]
+    Overflow(String),
+}
+
+// Chemicals are numbered when the book is built, so crafting indexes vectors
+// instead of hashing names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ChemId(u32);
+
+const ORE: ChemId = ChemId(0);
+
+impl ChemId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+struct Reaction {
+    output: i64,
+    inputs: Vec<(ChemId, i64)>,
+}
+
+pub struct RecipeBook {
+    names: Vec<String>,
+    ids: HashMap<String, ChemId>,
+    // indexed by id, None for ORE
+    reactions: Vec<Option<Reaction>>,
+    // every chemical before the ingredients it is made of
+    order: Vec<ChemId>,
+}
+
+// Depth first, appending every chemical after its ingredients. `done` is false for
+// the chemicals being visited, and true once they are in `order`.
+fn visit(
+    chemical: ChemId,
+    book: &RecipeBook,
+    done: &mut Vec<Option<bool>>,
+    order: &mut Vec<ChemId>,
+) -> Result<(), RecipeError> {
+    match done[chemical.index()] {
+        Some(true) => return Ok(()),
+        Some(false) => return Err(RecipeError::Cycle(book.name(chemical).to_owned())),
+        None => {},
+    }
+    if let Some(reaction) = &book.reactions[chemical.index()] {
+        done[chemical.index()] = Some(false);
+        for &(input, _) in &reaction.inputs {
+            visit(input, book, done, order)?;
+        }
+    }
+    done[chemical.index()] = Some(true);
+    order.push(chemical);
+    Ok(())
+}
+
+impl RecipeBook {
+    pub fn new(recipes: Vec<Recipe>) -> Result<RecipeBook, RecipeError> {
+        let mut producers: HashMap<&str, usize> = HashMap::new();
+        for r in &recipes {
+            if r.output.chemical == "ORE" {
+                return Err(RecipeError::OreOutput { line: r.line });
+            }
+            if let Some(i) = r.inputs.iter().chain(Some(&r.output)).find(|i| i.quantity <= 0) {
+                return Err(RecipeError::NonPositive { chemical: i.chemical.clone(), line: r.line });
+            }
+            if let Some(&first) = producers.get(r.output.chemical.as_str()) {
+                let chemical = r.output.chemical.clone();
+                return Err(RecipeError::DuplicateOutput { chemical, line: r.line, first });
+            }
+            producers.insert(&r.output.chemical, r.line);
+        }
+        for r in &recipes {
+            if let Some(i) = r.inputs.iter().find(|i| i.chemical != "ORE" && !producers.contains_key(i.chemical.as_str())) {
+                return Err(RecipeError::UnknownInput { chemical: i.chemical.clone(), line: r.line });
+            }
+        }
+        let mut names = vec!["ORE".to_owned()];
+        names.extend(recipes.iter().map(|r| r.output.chemical.clone()));
+        let ids: HashMap<String, ChemId> = names.iter().enumerate().map(|(i, name)| (name.clone(), ChemId(i as u32))).collect();
+        let mut reactions = vec![None];
+        reactions.extend(recipes.iter().map(|r| Some(Reaction {
+            output: r.output.quantity,
+            inputs: r.inputs.iter().map(|i| (ids[&i.chemical], i.quantity)).collect(),
+        })));
+        let mut book = RecipeBook { names, ids, reactions, order: Vec::new() };
+        let (mut done, mut order) = (vec![None; book.names.len()], Vec::new());
+        for i in 0..book.names.len() {
+            visit(ChemId(i as u32), &book, &mut done, &mut order)?;
+        }
+        order.reverse();
+        book.order = order;
+        Ok(book)
+    }
+    fn name(&self, chemical: ChemId) -> &str {
+        &self.names[chemical.index()]
+    }
+    fn produced(&self, chemical: &str) -> Result<ChemId, RecipeError> {
+        match self.ids.get(chemical) {
+            Some(&id) if id != ORE => Ok(id),
+            _ => Err(RecipeError::MissingRecipe(chemical.to_owned())),
+        }
+    }
+    // How many times each reaction runs, and how much of each chemical is used, to make
+    // `amount` of `chemical` from scratch. Walking the chemicals in order, every use of one
+    // is known before deciding how many times to run its reaction. None on overflow.
+    fn craft(&self, chemical: ChemId, amount: i64) -> Option<(Vec<i64>, Vec<i64>)> {
+        let mut runs = vec![0; self.names.len()];
+        let mut needed = vec![0; self.names.len()];
+        needed[chemical.index()] = amount;
+        for &chemical in &self.order {
+            let need = needed[chemical.index()];
+            let reaction = match &self.reactions[chemical.index()] {
+                Some(reaction) if need > 0 => reaction,
+                _ => continue,
+            };
+            runs[chemical.index()] = Integer::div_ceil(&need, &reaction.output);
+            for &(input, quantity) in &reaction.inputs {
+                let used = runs[chemical.index()].checked_mul(quantity)?;
+                needed[input.index()] = needed[input.index()].checked_add(used)?;
+            }
+        }
+        Some((runs, needed))
+    }
+    fn ore_for(&self, chemical: ChemId, amount: i64) -> Option<i64> {
+        Some(self.craft(chemical, amount)?.1[ORE.index()])
+    }
+    fn overflow(&self, chemical: ChemId) -> RecipeError {
+        RecipeError::Overflow(self.name(chemical).to_owned())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChemicalUsage {
+    pub chemical: String,
+    pub runs: i64,
+    pub produced: i64,
+    pub consumed: i64,
+    pub surplus: i64,
+}
+
+// What crafting `amount` of `chemical` takes, in reaction order and ending with ORE.
+// The target counts as consumed, and ORE as produced as much as it is consumed.
+pub fn usage(book: &RecipeBook, chemical: &str, amount: i64) -> Result<Vec<ChemicalUsage>, RecipeError> {
+    let chemical = book.produced(chemical)?;
+    let (runs, needed) = book.craft(chemical, amount).ok_or_else(|| book.overflow(chemical))?;
+    let usage = book.order.iter()
+        .filter(|&&c| runs[c.index()] > 0 || c == ORE)
+        .map(|&c| {
+            let produced = match &book.reactions[c.index()] {
+                Some(reaction) => runs[c.index()]*reaction.output,
+                None => needed[c.index()],
+            };
+            ChemicalUsage {
+                chemical: book.name(c).to_owned(),
+                runs: runs[c.index()],
+                produced,
+                consumed: needed[c.index()],
+                surplus: produced - needed[c.index()],
+            }
+        })
+        .collect();
+    Ok(usage)
+}
+
+pub fn ore_cost(book: &RecipeBook, chemical: &str, amount: i64) -> Result<i64, RecipeError> {
+    let chemical = book.produced(chemical)?;
+    book.ore_for(chemical, amount).ok_or_else(|| book.overflow(chemical))
+}
+
+pub fn max_craftable(book: &RecipeBook, chemical: &str, budget: i64) -> Result<i64, RecipeError> {
+    let chemical = book.produced(chemical)?;
+    // an amount too large to compute costs more than any budget
+    let affordable = |amount| book.ore_for(chemical, amount).is_some_and(|ore| ore <= budget);
+    if !affordable(1) {
+        return Ok(0);
+    }
+    // doubling would never run out of budget
+    if book.ore_for(chemical, 1) == Some(0) {
+        return Err(RecipeError::NoOre(book.name(chemical).to_owned()));
+    }
+    // double until out of budget, then bisect between the last two
+    let (mut low, mut high) = (1, 2);
+    while affordable(high) {
+        low = high;
+        high = high.checked_mul(2).ok_or_else(|| book.overflow(chemical))?;
+    }
+    while high - low > 1 {
+        let mid = low + (high - low)/2;
+        if affordable(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: &str = "\
+10 ORE => 10 A
+1 ORE => 1 B
+7 A, 1 B => 1 C
+7 A, 1 C => 1 D
+7 A, 1 D => 1 E
+7 A, 1 E => 1 FUEL
+";
+
+    const SHARED: &str = "\
+9 ORE => 2 A
+8 ORE => 3 B
+7 ORE => 5 C
+3 A, 4 B => 1 AB
+5 B, 7 C => 1 BC
+4 C, 1 A => 1 CA
+2 AB, 3 BC, 4 CA => 1 FUEL
+";
+
+    const LARGE1: &str = "\
+157 ORE => 5 NZVS
+165 ORE => 6 DCFZ
+44 XJWVT, 5 KHKGT, 1 QDVJ, 29 NZVS, 9 GPVTF, 48 HKGWZ => 1 FUEL
+12 HKGWZ, 1 GPVTF, 8 PSHF => 9 QDVJ
+179 ORE => 7 PSHF
+177 ORE => 5 HKGWZ
+7 DCFZ, 7 PSHF => 2 XJWVT
+165 ORE => 2 GPVTF
+3 DCFZ, 7 NZVS, 5 HKGWZ, 10 PSHF => 8 KHKGT
+";
+
+    const LARGE2: &str = "\
+2 VPVL, 7 FWMGM, 2 CXFTF, 11 MNCFX => 1 STKFG
+17 NVRVD, 3 JNWZP => 8 VPVL
+53 STKFG, 6 MNCFX, 46 VJHF, 81 HVMC, 68 CXFTF, 25 GNMV => 1 FUEL
+22 VJHF, 37 MNCFX => 5 FWMGM
+139 ORE => 4 NVRVD
+144 ORE => 7 JNWZP
+5 MNCFX, 7 RFSQX, 2 FWMGM, 2 VPVL, 19 CXFTF => 3 HVMC
+5 VJHF, 7 MNCFX, 9 VPVL, 37 CXFTF => 6 GNMV
+145 ORE => 6 MNCFX
+1 NVRVD => 8 CXFTF
+1 VJHF, 6 MNCFX => 4 RFSQX
+176 ORE => 6 VJHF
+";
+
+    const LARGE3: &str = "\
+171 ORE => 8 CNZTR
+7 ZLQW, 3 BMBT, 9 XCVML, 26 XMNCP, 1 WPTQ, 2 MZWV, 1 RJRHP => 4 PLWSL
+114 ORE => 4 BHXH
+14 VRPVC => 6 BMBT
+6 BHXH, 18 KTJDG, 12 WPTQ, 7 PLWSL, 31 FHTLT, 37 ZDVW => 1 FUEL
+6 WPTQ, 2 BMBT, 8 ZLQW, 18 KTJDG, 1 XMNCP, 6 MZWV, 1 RJRHP => 6 FHTLT
+15 XDBXC, 2 LTCX, 1 VRPVC => 6 ZLQW
+13 WPTQ, 10 LTCX, 3 RJRHP, 14 XMNCP, 2 MZWV, 1 ZLQW => 1 ZDVW
+5 BMBT => 4 WPTQ
+189 ORE => 9 KTJDG
+1 MZWV, 17 XDBXC, 3 XCVML => 2 XMNCP
+12 VRPVC, 27 CNZTR => 2 XDBXC
+15 KTJDG, 12 BHXH => 5 XCVML
+3 BHXH, 2 VRPVC => 7 MZWV
+121 ORE => 7 VRPVC
+7 XCVML => 6 RJRHP
+5 BHXH, 4 VRPVC => 5 LTCX
+";
+
+    const TRILLION: i64 = 1_000_000_000_000;
+
+    fn book(text: &str) -> RecipeBook {
+        RecipeBook::new(parse(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn fuel_for_a_trillion_ore() {
+        assert_eq!(max_craftable(&book(LARGE1), "FUEL", TRILLION).unwrap(), 82892753);
+        assert_eq!(max_craftable(&book(LARGE2), "FUEL", TRILLION).unwrap(), 5586022);
+        assert_eq!(max_craftable(&book(LARGE3), "FUEL", TRILLION).unwrap(), 460664);
+    }
+
+    #[test]
+    fn budgets_at_the_edges() {
+        let book = book(SMALL);
+        assert_eq!(max_craftable(&book, "FUEL", 30).unwrap(), 0);
+        assert_eq!(max_craftable(&book, "FUEL", 31).unwrap(), 1);
+        // the surplus of the first fuel makes the next ones cheaper
+        let second = ore_cost(&book, "FUEL", 2).unwrap();
+        assert_eq!(max_craftable(&book, "FUEL", second - 1).unwrap(), 1);
+        assert_eq!(max_craftable(&book, "FUEL", second).unwrap(), 2);
+    }
+
+    #[test]
+    fn budgets_too_large_to_double() {
+        let book = book("1 ORE => 10 A\n1 A => 1 FUEL\n");
+        assert_eq!(max_craftable(&book, "FUEL", 100_000_000_000_000_000).unwrap(), 1_000_000_000_000_000_000);
+        let error = max_craftable(&book, "FUEL", i64::MAX).unwrap_err();
+        assert_eq!(error.to_string(), "crafting that much FUEL overflows an i64");
+        let book = self::book("1000 ORE => 1 FUEL\n");
+        assert_eq!(max_craftable(&book, "FUEL", i64::MAX).unwrap(), i64::MAX / 1000);
+        assert!(matches!(ore_cost(&book, "FUEL", i64::MAX), Err(RecipeError::Overflow(_))));
+    }
+
+    #[test]
+    fn ore_for_one_fuel() {
+        assert_eq!(ore_cost(&book(SMALL), "FUEL", 1).unwrap(), 31);
+        assert_eq!(ore_cost(&book(SHARED), "FUEL", 1).unwrap(), 165);
+        assert_eq!(ore_cost(&book(LARGE1), "FUEL", 1).unwrap(), 13312);
+        assert_eq!(ore_cost(&book(LARGE2), "FUEL", 1).unwrap(), 180697);
+        assert_eq!(ore_cost(&book(LARGE3), "FUEL", 1).unwrap(), 2210736);
+    }
+
+    #[test]
+    fn every_chemical_comes_before_its_ingredients() {
+        let book = book(LARGE3);
+        assert_eq!(book.order.len(), book.names.len());
+        assert_eq!(book.name(book.order[0]), "FUEL");
+        assert_eq!(*book.order.last().unwrap(), ORE);
+        let position = |c: ChemId| book.order.iter().position(|&o| o == c).unwrap();
+        for (i, reaction) in book.reactions.iter().enumerate() {
+            for &(input, _) in reaction.iter().flat_map(|r| &r.inputs) {
+                assert!(position(ChemId(i as u32)) < position(input));
+            }
+        }
+    }
+
+    #[test]
+    fn cyclic_recipes() {
+        let recipes = parse("1 ORE, 1 C => 1 A\n1 A => 1 B\n2 B => 1 C\n1 C => 1 FUEL").unwrap();
+        let error = RecipeBook::new(recipes).err().unwrap();
+        assert!(matches!(&error, RecipeError::Cycle(c) if c == "A"));
+        assert_eq!(error.to_string(), "the recipe for A depends on itself");
+        let recipes = parse("1 A => 1 A").unwrap();
+        assert_eq!(RecipeBook::new(recipes).err().unwrap().to_string(), "the recipe for A depends on itself");
+    }
+
+    fn invalid(text: &str) -> String {
+        RecipeBook::new(parse(text).unwrap()).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn validating_the_book() {
+        assert_eq!(invalid("1 ORE => 1 A\n2 A, 1 B => 1 FUEL"), "line 2: no recipe produces B");
+        assert_eq!(invalid("1 ORE => 1 A\n2 ORE => 1 A\n1 A => 1 FUEL"), "line 2: A is already produced on line 1");
+        assert_eq!(invalid("1 A => 1 FUEL\n1 FUEL => 2 ORE"), "line 2: ORE cannot be produced");
+        assert_eq!(invalid("1 ORE => 1 A\n0 A => 1 FUEL"), "line 2: the quantity of A must be positive");
+        assert_eq!(invalid("1 ORE => -1 A"), "line 1: the quantity of A must be positive");
+    }
+
+    #[test]
+    fn a_well_formed_book() {
+        let book = book(SHARED);
+        assert_eq!(book.names.len(), 8);
+        assert_eq!(book.name(ORE), "ORE");
+        assert_eq!(book.name(book.produced("CA").unwrap()), "CA");
+        assert!(matches!(book.produced("ORE"), Err(RecipeError::MissingRecipe(_))));
+        assert_eq!(ore_cost(&book, "ZZZ", 1).unwrap_err().to_string(), "no recipe produces ZZZ");
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = parse("1 ORE => 1 A\n1 A = 1 FUEL\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: cannot parse `1 A = 1 FUEL`, expected a recipe like `7 A, 1 B => 1 C`");
+        let error = parse("1 ORE => 1 A extra").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: unexpected ` extra` after the recipe in `1 ORE => 1 A extra`");
+        let recipes = parse("\n1 ORE => 1 A\n3 A => 1 FUEL\n").unwrap();
+        assert_eq!(recipes.iter().map(|r| r.line).collect::<Vec<_>>(), [2, 3]);
+    }
+
+    #[test]
+    fn intermediate_chemicals() {
+        let book = book(LARGE1);
+        // 3 runs for 12 HKGWZ, 1 for the GPVTF and 2 for 8 PSHF
+        assert_eq!(ore_cost(&book, "QDVJ", 1).unwrap(), 3*177 + 165 + 2*179);
+        // a run makes 9 QDVJ
+        assert_eq!(ore_cost(&book, "QDVJ", 9).unwrap(), 1054);
+        assert_eq!(max_craftable(&book, "QDVJ", 1053).unwrap(), 0);
+        assert_eq!(max_craftable(&book, "QDVJ", 1054).unwrap(), 9);
+        assert_eq!(ore_cost(&book, "KHKGT", 8).unwrap(), 165 + 2*157 + 177 + 2*179);
+        assert_eq!(ore_cost(&self::book(SHARED), "AB", 1).unwrap(), 2*9 + 2*8);
+        // ORE is not crafted
+        assert_eq!(ore_cost(&book, "ORE", 1).unwrap_err().to_string(), "no recipe produces ORE");
+        assert_eq!(max_craftable(&book, "NOPE", 10).unwrap_err().to_string(), "no recipe produces NOPE");
+    }
+
+    #[test]
+    fn usage_for_13312_ore() {
+        let usage = usage(&book(LARGE1), "FUEL", 1).unwrap();
+        assert_eq!(usage.first().unwrap().chemical, "FUEL");
+        assert_eq!(usage.last().unwrap().chemical, "ORE");
+        let rows: HashMap<&str, (i64, i64, i64, i64)> = usage.iter()
+            .map(|u| (u.chemical.as_str(), (u.runs, u.produced, u.consumed, u.surplus)))
+            .collect();
+        let expected: HashMap<&str, (i64, i64, i64, i64)> = vec![
+            ("FUEL", (1, 1, 1, 0)),
+            ("XJWVT", (22, 44, 44, 0)),
+            ("KHKGT", (1, 8, 5, 3)),
+            ("QDVJ", (1, 9, 1, 8)),
+            ("NZVS", (8, 40, 36, 4)),
+            ("GPVTF", (5, 10, 10, 0)),
+            ("HKGWZ", (13, 65, 65, 0)),
+            ("DCFZ", (27, 162, 157, 5)),
+            ("PSHF", (25, 175, 172, 3)),
+            ("ORE", (0, 13312, 13312, 0)),
+        ].into_iter().collect();
+        assert_eq!(rows, expected);
+        assert_eq!(usage.len(), expected.len());
+    }
+
+    #[test]
+    fn usage_leaves_out_unused_reactions() {
+        let usage = usage(&book(SHARED), "AB", 2).unwrap();
+        let chemicals: Vec<_> = usage.iter().map(|u| u.chemical.as_str()).collect();
+        assert_eq!(chemicals.len(), 4);
+        assert!(chemicals.contains(&"A") && chemicals.contains(&"B") && !chemicals.contains(&"C"));
+        assert_eq!(usage[0], ChemicalUsage { chemical: "AB".to_owned(), runs: 2, produced: 2, consumed: 2, surplus: 0 });
+    }
+}
@@ -3,34 +3,32 @@ use anyhow::anyhow;
 
 
 fn parse() -> Result<Vec<i64>> {
-    std::fs::read_to_string("input")?
-        .trim()
-        .split(',')
-        .map(|s| s.parse().map_err(std::convert::From::from))
-        .collect()
+    Ok(intcode::parse_program(&std::fs::read_to_string("input")?)?)
 }
 
 fn part1(v: Vec<i64>) -> Result<impl std::fmt::Display> {
-    let mut vm = intcode::Vm::new(v);
-    vm.write_at(1, 12)?;
-    vm.write_at(2, 2)?;
+    let mut vm = intcode::Vm::builder()
+        .program(v)
+        .patch(1, 12)
+        .patch(2, 2)
+        .build()?;
     vm.run()?;
     Ok(vm.read_at(0)?)
 }
 
 fn part2(v: Vec<i64>) -> Result<impl std::fmt::Display> {
-    for noun in 0..100 {
-        for verb in 0..100 {
-            let mut vm = intcode::Vm::new(v.clone());
-            vm.write_at(1, noun)?;
-            vm.write_at(2, verb)?;
-            vm.run()?;
-            if vm.read_at(0)? == 19690720 {
-                return Ok(100*noun + verb);
-            }
-        }
-    }
-    Err(anyhow!("no solution!"))
+    let pairs: Vec<(i64, i64)> = (0..100).flat_map(|noun| (0..100).map(move |verb| (noun, verb))).collect();
+    let results = intcode::pool::run_batch(&v, pairs.iter().copied(), |vm, (noun, verb)| {
+        vm.write_at(1, noun)?;
+        vm.write_at(2, verb)?;
+        vm.run()?;
+        vm.read_at(0)
+    })?;
+    let (noun, verb) = results.into_iter().zip(pairs)
+        .find(|&(res, _)| res == 19690720)
+        .map(|(_, pair)| pair)
+        .ok_or_else(|| anyhow!("no solution!"))?;
+    Ok(100*noun + verb)
 }
 
 fn main() -> Result<()> {
@@ -33,7 +33,7 @@ const DIGITS: [Digit; 10] = [
 ];
 impl Digit {
     fn next(self) -> Option<Digit> {
-        DIGITS.get(self as usize + 1).map(|d| *d)
+        DIGITS.get(self as usize + 1).copied()
     }
 }
 
@@ -42,6 +42,11 @@ struct Password {
     digits: [Digit; 6],
 }
 
+type Rule = fn(&Password) -> bool;
+
+const RULES: [Rule; 2] = [Password::check_increase, Password::check_double];
+const STRICT_RULES: [Rule; 2] = [Password::check_increase, Password::check_double_strict];
+
 impl Password {
     fn check_double(&self) -> bool {
         self.digits.windows(2).any(|w| w[0] == w[1])
@@ -66,11 +71,8 @@ impl Password {
     fn check_increase(&self) -> bool {
         self.digits.windows(2).all(|w| w[0] <= w[1])
     }
-    fn check(&self) -> bool {
-        self.check_double() && self.check_increase()
-    }
-    fn check_strict(&self) -> bool {
-        self.check_double_strict() && self.check_increase()
+    fn check(&self, rules: &[Rule]) -> bool {
+        rules.iter().all(|rule| rule(self))
     }
     fn next(mut self) -> Option<Password> {
         for d in self.digits.iter_mut().rev() {
@@ -85,6 +87,28 @@ impl Password {
     }
 }
 
+// The passwords from `cur` to `max`, both included.
+struct PasswordRange {
+    cur: Option<Password>,
+    max: Password,
+}
+
+impl PasswordRange {
+    fn new(min: Password, max: Password) -> PasswordRange {
+        PasswordRange { cur: Some(min), max }
+    }
+}
+
+impl Iterator for PasswordRange {
+    type Item = Password;
+    fn next(&mut self) -> Option<Password> {
+        let max = self.max;
+        let cur = self.cur.filter(|cur| *cur <= max)?;
+        self.cur = cur.next();
+        Some(cur)
+    }
+}
+
 impl std::fmt::Display for Password {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}{}{}{}{}{}",
@@ -126,29 +150,11 @@ fn parse() -> Result<(Password, Password)> {
 }
 
 fn part1((min, max): (Password, Password)) -> Result<impl std::fmt::Display> {
-    let mut count = 0;
-    let mut cur = min;
-    while let Some(c) = cur.next() {
-        cur = c;
-        if cur > max {
-            break;
-        }
-        count += cur.check() as u32;
-    }
-    Ok(count)
+    Ok(PasswordRange::new(min, max).filter(|p| p.check(&RULES)).count())
 }
 
 fn part2((min, max): (Password, Password)) -> Result<impl std::fmt::Display> {
-    let mut count = 0;
-    let mut cur = min;
-    while let Some(c) = cur.next() {
-        cur = c;
-        if cur > max {
-            break;
-        }
-        count += cur.check_strict() as u32;
-    }
-    Ok(count)
+    Ok(PasswordRange::new(min, max).filter(|p| p.check(&STRICT_RULES)).count())
 }
 
 fn main() -> Result<()> {
@@ -159,3 +165,65 @@ fn main() -> Result<()> {
     println!("part 2: {}", p2);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password(s: &str) -> Password {
+        s.parse().unwrap()
+    }
+
+    fn range(min: &str, max: &str) -> Vec<String> {
+        PasswordRange::new(password(min), password(max)).map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn bounds_are_included() {
+        assert_eq!(range("111111", "111113"), ["111111", "111112", "111113"]);
+        assert_eq!(range("123444", "123444"), ["123444"]);
+        assert_eq!(range("199998", "200001"), ["199998", "199999", "200000", "200001"]);
+        assert_eq!(range("999998", "999999"), ["999998", "999999"]);
+    }
+
+    #[test]
+    fn valid_bounds_are_counted() {
+        let count = |min, max, rules: &[Rule]| {
+            PasswordRange::new(password(min), password(max)).filter(|p| p.check(rules)).count()
+        };
+        assert_eq!(count("111111", "111111", &RULES), 1);
+        assert_eq!(count("111111", "111112", &RULES), 2);
+        assert_eq!(count("111110", "111111", &RULES), 1);
+        assert_eq!(count("112233", "112233", &STRICT_RULES), 1);
+        assert_eq!(count("112233", "112240", &STRICT_RULES), 7);
+        assert_eq!(count("112232", "112233", &STRICT_RULES), 1);
+        assert_eq!(part1((password("111111"), password("111122"))).unwrap().to_string(), "10");
+    }
+
+    #[test]
+    fn empty_ranges() {
+        assert!(range("111112", "111111").is_empty());
+        assert!(range("999999", "000000").is_empty());
+        assert_eq!(part1((password("223450"), password("111111"))).unwrap().to_string(), "0");
+        assert_eq!(part2((password("223450"), password("111111"))).unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn documented_examples() {
+        assert!(password("111111").check(&RULES));
+        assert!(!password("223450").check(&RULES));
+        assert!(!password("223450").check_increase());
+        assert!(!password("123789").check(&RULES));
+        assert!(!password("123789").check_double());
+        assert!(password("112233").check(&STRICT_RULES));
+        assert!(!password("123444").check(&STRICT_RULES));
+        assert!(password("111122").check(&STRICT_RULES));
+        assert!(!password("111111").check(&STRICT_RULES));
+    }
+
+    #[test]
+    fn invalid_passwords() {
+        assert!("12345".parse::<Password>().is_err());
+        assert!("12345x".parse::<Password>().is_err());
+    }
+}
@@ -3,18 +3,11 @@ use anyhow::anyhow;
 
 
 fn parse() -> Result<Vec<i64>> {
-    std::fs::read_to_string("input")?
-        .trim()
-        .split(',')
-        .map(|s| s.parse().map_err(std::convert::From::from))
-        .collect()
+    Ok(intcode::parse_program(&std::fs::read_to_string("input")?)?)
 }
 
 fn part1(v: Vec<i64>) -> Result<impl std::fmt::Display> {
-    let mut vm = intcode::Vm::new(v);
-    vm.add_inputs(&[1]);
-    vm.run()?;
-    let mut outs: Vec<_> = vm.get_outputs().collect();
+    let mut outs = intcode::Vm::new(v).run_with_inputs(&[1])?;
     let last = outs.pop().ok_or_else(|| anyhow!("no outputs!"))?;
     for (i,o) in outs.into_iter().enumerate() {
         if o != 0  {
@@ -25,10 +18,7 @@ fn part1(v: Vec<i64>) -> Result<impl std::fmt::Display> {
 }
 
 fn part2(v: Vec<i64>) -> Result<impl std::fmt::Display> {
-    let mut vm = intcode::Vm::new(v);
-    vm.add_inputs(&[5]);
-    vm.run()?;
-    let mut outs: Vec<_> = vm.get_outputs().collect();
+    let mut outs = intcode::Vm::new(v).run_with_inputs(&[5])?;
     let last = outs.pop().ok_or_else(|| anyhow!("no outputs!"))?;
     if !outs.is_empty() {
         return Err(anyhow!("More than one output!"));
@@ -3,11 +3,7 @@ use anyhow::anyhow;
 
 
 fn parse() -> Result<Vec<i64>> {
-    std::fs::read_to_string("input")?
-        .trim()
-        .split(',')
-        .map(|s| s.parse().map_err(std::convert::From::from))
-        .collect()
+    Ok(intcode::parse_program(&std::fs::read_to_string("input")?)?)
 }
 
 fn part1(v: Vec<i64>) -> Result<impl std::fmt::Display> {
@@ -16,7 +12,7 @@ fn part1(v: Vec<i64>) -> Result<impl std::fmt::Display> {
     vm.run()?;
     let mut outs = vm.get_outputs();
     let o = outs.next().ok_or_else(||anyhow!("no output"))?;
-    if let Some(_) = outs.next() {
+    if outs.next().is_some() {
         return Err(anyhow!("failed some check"));
     }
     Ok(o)
@@ -28,7 +24,7 @@ fn part2(v: Vec<i64>) -> Result<impl std::fmt::Display> {
     vm.run()?;
     let mut outs = vm.get_outputs();
     let o = outs.next().ok_or_else(||anyhow!("no output"))?;
-    if let Some(_) = outs.next() {
+    if outs.next().is_some() {
         return Err(anyhow!("failed some check"));
     }
     Ok(o)
@@ -0,0 +1,37 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use intcode::Vm;
+
+const CELLS: usize = 4000;
+const CLONES: usize = 10_000;
+
+// A ROM sized like the day 13 one: a halt followed by data.
+fn rom() -> Vec<i64> {
+    let mut program = vec![99];
+    program.extend((1..CELLS as i64).map(|i| i * 7 % 1000));
+    program
+}
+
+fn clones(c: &mut Criterion) {
+    let program = rom();
+    let vm: Vm = Vm::new(program.clone());
+    let mut group = c.benchmark_group("10000 clones of 4000 cells");
+    group.bench_function("clone", |b| b.iter(|| {
+        (0..CLONES).map(|_| vm.clone()).filter(|clone| clone.memory_len() == CELLS).count()
+    }));
+    group.bench_function("clone then write", |b| b.iter(|| {
+        (0..CLONES).map(|_| {
+            let mut clone = vm.clone();
+            clone.write_at(1, 5).unwrap();
+            clone
+        }).filter(|clone| clone.memory_len() == CELLS).count()
+    }));
+    // what every clone cost when the memory was a plain vector
+    group.bench_function("copy of the program", |b| b.iter(|| {
+        (0..CLONES).map(|_| Vm::new(program.clone())).filter(|vm| vm.memory_len() == CELLS).count()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, clones);
+criterion_main!(benches);
@@ -0,0 +1,25 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use intcode::Vm;
+
+// Counts [20] down from 100000 without any I/O, so the whole run is decoding and arithmetic.
+const BUSY_LOOP: &str = "1101,0,100000,20,1001,20,-1,20,1005,20,4,99";
+
+fn busy_loop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("busy loop");
+    for &caching in &[true, false] {
+        let mut vm: Vm = Vm::from_source(BUSY_LOOP).unwrap();
+        vm.set_decode_cache(caching);
+        let name = if caching { "cached decode" } else { "uncached decode" };
+        group.bench_function(name, |b| b.iter(|| {
+            vm.reset();
+            vm.run().unwrap()
+        }));
+        // both paths execute the same instructions
+        assert_eq!(vm.steps_executed(), 200_002);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, busy_loop);
+criterion_main!(benches);
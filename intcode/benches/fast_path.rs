@@ -0,0 +1,25 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use intcode::{Event, Vm};
+
+// Counts [20] down from 100000 without any I/O, so the whole run can stay on the fast path.
+const BUSY_LOOP: &str = "1101,0,100000,20,1001,20,-1,20,1005,20,4,99";
+
+fn busy_loop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("busy loop");
+    let mut vm: Vm = Vm::from_source(BUSY_LOOP).unwrap();
+    group.bench_function("run", |b| b.iter(|| {
+        vm.reset();
+        vm.run().unwrap()
+    }));
+    assert_eq!(vm.steps_executed(), 200_002);
+    group.bench_function("step_event", |b| b.iter(|| {
+        vm.reset();
+        while let Event::Continue = vm.step_event().unwrap() {}
+    }));
+    assert_eq!(vm.steps_executed(), 200_002);
+    group.finish();
+}
+
+criterion_group!(benches, busy_loop);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use intcode::Vm;
+
+// A day 9 style program that writes and reads back a cell far past its image.
+const FAR_WRITE: &str = "1101,0,42,1000000000,4,1000000000,99";
+
+fn far_write(c: &mut Criterion) {
+    let mut vm: Vm = Vm::from_source(FAR_WRITE).unwrap();
+    c.bench_function("write at 1e9", |b| b.iter(|| {
+        vm.reset();
+        vm.run_collect().unwrap()
+    }));
+    // the old dense memory grew to a billion cells here
+    assert_eq!(vm.memory_len(), 8);
+    println!("memory after the write at 1e9: {} cells", vm.memory_len());
+}
+
+fn near_write(c: &mut Criterion) {
+    let mut vm: Vm = Vm::from_source("1101,0,42,1000,4,1000,99").unwrap();
+    c.bench_function("write at 1e3", |b| b.iter(|| {
+        vm.reset();
+        vm.run_collect().unwrap()
+    }));
+}
+
+criterion_group!(benches, far_write, near_write);
+criterion_main!(benches);
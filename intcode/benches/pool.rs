@@ -0,0 +1,30 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use intcode::{parse_program, pool, VMError, Vm};
+
+// Counts its input down to zero.
+const COUNTDOWN: &str = "3,12,1001,12,-1,12,1005,12,2,4,12,99,0";
+const JOBS: i64 = 256;
+
+fn countdown(vm: &mut Vm, n: i64) -> Result<Vec<i64>, VMError> {
+    vm.run_with_inputs(&[n])
+}
+
+fn batch(c: &mut Criterion) {
+    let program = parse_program(COUNTDOWN).unwrap();
+    let mut group = c.benchmark_group("countdown batch");
+    group.bench_function("serial", |b| b.iter(|| {
+        let mut vm = Vm::new(program.clone());
+        (0..JOBS).map(|i| {
+            vm.reset();
+            countdown(&mut vm, 10_000 + i)
+        }).collect::<Result<Vec<_>, _>>().unwrap()
+    }));
+    group.bench_function("pool", |b| b.iter(|| {
+        pool::run_batch(&program, (0..JOBS).map(|i| 10_000 + i), countdown).unwrap()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, batch);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use intcode::{Event, Vm};
+
+// Run once as is and once with `--features trace-log`; criterion reports the change
+// between the two runs of the same benchmark.
+const BUSY_LOOP: &str = "1101,0,100000,20,1001,20,-1,20,1005,20,4,99";
+
+fn stepping(c: &mut Criterion) {
+    println!("trace-log compiled in: {}", cfg!(feature = "trace-log"));
+    let mut vm: Vm = Vm::from_source(BUSY_LOOP).unwrap();
+    // every instruction goes through step_event, where the logging is
+    c.bench_function("busy loop stepping", |b| b.iter(|| {
+        vm.reset();
+        while let Event::Continue = vm.step_event().unwrap() {}
+    }));
+    assert_eq!(vm.steps_executed(), 200_002);
+}
+
+criterion_group!(benches, stepping);
+criterion_main!(benches);
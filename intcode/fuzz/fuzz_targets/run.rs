@@ -0,0 +1,21 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use intcode::Vm;
+
+// Any program must either run to completion or fail with a VMError, never panic.
+fuzz_target!(|program: Vec<i64>| {
+    let program: Vec<i64> = program.into_iter().take(64).collect();
+    let _ = intcode::verify::verify(&program);
+    let _ = intcode::disasm::disassemble(&program);
+    let mut vm = Vm::builder()
+        .program(program)
+        .step_limit(10_000)
+        .memory_limit(1 << 20)
+        .inputs(&[0, 1, -1, i64::MAX, i64::MIN])
+        .build()
+        .unwrap();
+    vm.enable_rewind(64);
+    let _ = vm.run();
+    while vm.step_back().is_ok() {}
+});
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AsmError {
+    #[error("line {line}: unknown mnemonic `{token}`")]
+    UnknownMnemonic {
+        line: usize,
+        token: String,
+    },
+    #[error("line {line}: invalid operand `{token}`")]
+    InvalidOperand {
+        line: usize,
+        token: String,
+    },
+    #[error("line {line}: `{token}` expects {expected} operands, found {found}")]
+    OperandCount {
+        line: usize,
+        token: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("line {line}: immediate operand `{token}` cannot be written to")]
+    ImmediateDestination {
+        line: usize,
+        token: String,
+    },
+    #[error("line {line}: label `{token}` is already defined")]
+    DuplicateLabel {
+        line: usize,
+        token: String,
+    },
+    #[error("line {line}: undefined label `{token}`")]
+    UndefinedLabel {
+        line: usize,
+        token: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, AsmError>;
+
+enum Value {
+    Number(i64),
+    Label(String),
+}
+
+struct Word {
+    line: usize,
+    value: Value,
+}
+
+fn mnemonic(token: &str) -> Option<(i64, usize)> {
+    let op = match token.to_ascii_lowercase().as_str() {
+        "add" => (1, 3),
+        "mul" => (2, 3),
+        "in" => (3, 1),
+        "out" => (4, 1),
+        "jnz" => (5, 2),
+        "jz" => (6, 2),
+        "lt" => (7, 3),
+        "eq" => (8, 3),
+        "arb" => (9, 1),
+        "halt" => (99, 0),
+        _ => return None,
+    };
+    Some(op)
+}
+
+fn writes(opcode: i64) -> bool {
+    matches!(opcode, 1 | 2 | 3 | 7 | 8)
+}
+
+fn is_label(token: &str) -> bool {
+    let mut chars = token.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn value(token: &str, line: usize) -> Result<Value> {
+    if let Ok(n) = token.parse() {
+        Ok(Value::Number(n))
+    } else if is_label(token) {
+        Ok(Value::Label(token.to_owned()))
+    } else {
+        Err(AsmError::InvalidOperand { line, token: token.to_owned() })
+    }
+}
+
+fn operand(token: &str, line: usize) -> Result<(i64, Value)> {
+    let invalid = || AsmError::InvalidOperand { line, token: token.to_owned() };
+    if let Some(inner) = token.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        Ok((0, value(inner, line)?))
+    } else if let Some(inner) = token.strip_prefix('#') {
+        Ok((1, value(inner, line)?))
+    } else if let Some(inner) = token.strip_prefix("rb+") {
+        Ok((2, value(inner, line)?))
+    } else if let Some(inner) = token.strip_prefix("rb-") {
+        match value(inner, line)? {
+            Value::Number(n) => Ok((2, Value::Number(-n))),
+            Value::Label(_) => Err(invalid()),
+        }
+    } else {
+        Err(invalid())
+    }
+}
+
+pub fn assemble(src: &str) -> Result<Vec<i64>> {
+    let mut labels = HashMap::new();
+    let mut words = Vec::new();
+    for (idx, text) in src.lines().enumerate() {
+        let line = idx + 1;
+        let mut text = text.split(';').next().unwrap_or("").trim();
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_label(label) {
+                return Err(AsmError::InvalidOperand { line, token: label.to_owned() });
+            }
+            if labels.insert(label.to_owned(), words.len() as i64).is_some() {
+                return Err(AsmError::DuplicateLabel { line, token: label.to_owned() });
+            }
+            text = text[colon+1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (head, rest) = match text.find(char::is_whitespace) {
+            Some(i) => (&text[..i], &text[i..]),
+            None => (text, ""),
+        };
+        let tokens: Vec<_> = rest
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty() && *t != "->")
+            .collect();
+        if head == ".data" {
+            for token in tokens {
+                words.push(Word { line, value: value(token, line)? });
+            }
+            continue;
+        }
+        let (opcode, arity) = mnemonic(head)
+            .ok_or_else(|| AsmError::UnknownMnemonic { line, token: head.to_owned() })?;
+        if tokens.len() != arity {
+            return Err(AsmError::OperandCount { line, token: head.to_owned(), expected: arity, found: tokens.len() });
+        }
+        let operands = tokens.iter()
+            .map(|t| operand(t, line))
+            .collect::<Result<Vec<_>>>()?;
+        if writes(opcode) && operands.last().map(|(mode, _)| *mode) == Some(1) {
+            return Err(AsmError::ImmediateDestination { line, token: tokens[arity-1].to_owned() });
+        }
+        let instruction = operands.iter()
+            .enumerate()
+            .fold(opcode, |acc, (i, (mode, _))| acc + mode * 10i64.pow(i as u32 + 2));
+        words.push(Word { line, value: Value::Number(instruction) });
+        for (_, value) in operands {
+            words.push(Word { line, value });
+        }
+    }
+    words.into_iter()
+        .map(|w| match w.value {
+            Value::Number(n) => Ok(n),
+            Value::Label(l) => labels.get(&l)
+                .copied()
+                .ok_or(AsmError::UndefinedLabel { line: w.line, token: l }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vm;
+
+    #[test]
+    fn instructions_and_modes() {
+        let program = assemble("
+            add #2, [9] -> rb+3   ; sum
+            MUL [1], rb-1, [0]
+            halt
+        ").unwrap();
+        assert_eq!(program, vec![20101, 2, 9, 3, 2002, 1, -1, 0, 99]);
+    }
+
+    #[test]
+    fn forward_labels_and_data() {
+        let program = assemble("
+            in [n]
+        loop: jz [n], #done
+            add [n], #-1, [n]
+            out [n]
+            jz #0, #loop
+        done: halt
+        n: .data 0
+        ").unwrap();
+        assert_eq!(program[..6], [3, 15, 1006, 15, 14, 1001]);
+        let mut vm = Vm::new(program);
+        assert_eq!(vm.run_with_inputs(&[3]).unwrap(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn errors_name_the_line_and_token() {
+        let error = |src| assemble(src).unwrap_err();
+        assert_eq!(error("halt\nmov [1], [2]"), AsmError::UnknownMnemonic { line: 2, token: "mov".into() });
+        assert_eq!(error("out @3"), AsmError::InvalidOperand { line: 1, token: "@3".into() });
+        assert_eq!(error("out rb-x"), AsmError::InvalidOperand { line: 1, token: "rb-x".into() });
+        assert_eq!(error("add #1, #2"), AsmError::OperandCount { line: 1, token: "add".into(), expected: 3, found: 2 });
+        assert_eq!(error("\n\nin #4"), AsmError::ImmediateDestination { line: 3, token: "#4".into() });
+        assert_eq!(error("a: halt\na: halt"), AsmError::DuplicateLabel { line: 2, token: "a".into() });
+        assert_eq!(error("jz #0, #nowhere"), AsmError::UndefinedLabel { line: 1, token: "nowhere".into() });
+    }
+}
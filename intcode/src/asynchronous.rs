@@ -0,0 +1,103 @@
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use crate::{Result, VMError, Vm, VmState};
+
+pub struct AsyncVm {
+    vm: Vm,
+    input: Receiver<i64>,
+    output: Sender<i64>,
+}
+
+impl AsyncVm {
+    pub fn new(vm: Vm, input: Receiver<i64>, output: Sender<i64>) -> AsyncVm {
+        AsyncVm {
+            vm,
+            input,
+            output,
+        }
+    }
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+    pub fn vm_mut(&mut self) -> &mut Vm {
+        &mut self.vm
+    }
+    pub fn into_inner(self) -> Vm {
+        self.vm
+    }
+    pub async fn run_until_output(&mut self) -> Result<Option<i64>> {
+        loop {
+            if let Some(o) = self.vm.outputs.pop_front() {
+                // a closed downstream is not an error for this VM
+                let _ = self.output.send(o).await;
+                return Ok(Some(o));
+            }
+            match self.vm.step()? {
+                VmState::Running => {},
+                VmState::Stopped => {return Ok(None);},
+                VmState::WaitingForInput => match self.input.recv().await {
+                    Some(i) => self.vm.add_inputs(&[i]),
+                    None => {
+                        return Err(VMError::NoMoreInput { pc: self.vm.pc, rb: self.vm.rb });
+                    },
+                },
+                VmState::Paused => {return Err(VMError::Paused { pc: self.vm.pc });},
+            }
+        }
+    }
+    pub async fn run(&mut self) -> Result<()> {
+        while self.run_until_output().await?.is_some() {}
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+
+    const FEEDBACK: &str = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+
+    #[tokio::test]
+    async fn feedback_ring() {
+        let phases = [9, 8, 7, 6, 5];
+        let (senders, receivers): (Vec<_>, Vec<_>) = phases.iter().map(|_| channel(8)).unzip();
+        for (tx, &phase) in senders.iter().zip(&phases) {
+            tx.send(phase).await.unwrap();
+        }
+        senders[0].send(0).await.unwrap();
+        // amplifier i reads from channel i and writes to channel i + 1
+        let mut amps: Vec<_> = receivers.into_iter().enumerate().map(|(i, rx)| {
+            let next = senders[(i + 1) % senders.len()].clone();
+            AsyncVm::new(Vm::from_source(FEEDBACK).unwrap(), rx, next)
+        }).collect();
+        drop(senders);
+        let mut last = None;
+        let mut e = amps.pop().unwrap();
+        let mut d = amps.pop().unwrap();
+        let mut c = amps.pop().unwrap();
+        let mut b = amps.pop().unwrap();
+        let mut a = amps.pop().unwrap();
+        let last_amp = async {
+            while let Some(o) = e.run_until_output().await? {
+                last = Some(o);
+            }
+            Ok(())
+        };
+        tokio::try_join!(a.run(), b.run(), c.run(), d.run(), last_amp).unwrap();
+        assert_eq!(last, Some(139629729));
+        assert_eq!(e.vm().state(), VmState::Stopped);
+    }
+
+    #[tokio::test]
+    async fn closed_inputs_are_an_error() {
+        let (tx, rx) = channel(1);
+        let (out, mut outputs) = channel(1);
+        let mut vm = AsyncVm::new(Vm::from_source("3,0,4,0,99").unwrap(), rx, out);
+        drop(tx);
+        assert!(matches!(vm.run().await, Err(VMError::NoMoreInput { pc: 0, .. })));
+        drop(vm);
+        assert!(outputs.recv().await.is_none());
+    }
+}
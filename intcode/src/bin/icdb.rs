@@ -0,0 +1,178 @@
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+
+use intcode::{BreakReason, Vm, VmState};
+
+const HELP: &str = "commands:
+  step [n]            execute n instructions (default 1)
+  run                 run until a breakpoint, a watchpoint, input is needed or the program halts
+  break <addr>        stop before executing the instruction at addr
+  watch <addr>        stop after a write to addr
+  print <addr>[..end] show memory cells
+  regs                show pc, relative base and state
+  in <values...>      queue input values
+  out                 drain and show outputs
+  dis [addr] [count]  disassemble count instructions starting at addr (default pc, 10)
+  restart             reload the program, keeping breakpoints and watchpoints
+  quit                exit";
+
+// the most cells print or instructions dis show at once
+const MAX_LISTING: i64 = 1000;
+
+fn number(token: &str) -> Result<i64, String> {
+    token.parse().map_err(|_| format!("invalid number `{}`", token))
+}
+
+fn address(token: &str) -> Result<i64, String> {
+    match number(token)? {
+        addr if addr < 0 => Err(format!("invalid address `{}`", token)),
+        addr => Ok(addr),
+    }
+}
+
+fn arg(args: &[&str], idx: usize) -> Result<Option<i64>, String> {
+    args.get(idx).map(|a| number(a)).transpose()
+}
+
+fn show_pc(vm: &Vm) -> Result<String, String> {
+    // a jump can leave pc anywhere
+    let origin = usize::try_from(vm.pc()).map_err(|_| format!("pc {} is not an address", vm.pc()))?;
+    let words = (0..4)
+        .filter_map(|i| vm.pc().checked_add(i))
+        .map(|addr| vm.peek_at(addr))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let listing = intcode::disasm::instructions_at(&words, origin);
+    Ok(listing[0].to_string())
+}
+
+fn execute(vm: &mut Vm, line: &str) -> Result<String, String> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    let (cmd, args) = match words.split_first() {
+        Some((cmd, args)) => (*cmd, args),
+        None => return Ok(String::new()),
+    };
+    match cmd {
+        "step" | "s" => {
+            let n = arg(args, 0)?.unwrap_or(1);
+            for _ in 0..n {
+                match vm.step().map_err(|e| e.to_string())? {
+                    VmState::Running => {},
+                    VmState::Stopped => return Ok("halted".to_owned()),
+                    VmState::WaitingForInput => return Ok("waiting for input".to_owned()),
+                    VmState::Paused => return Ok("paused".to_owned()),
+                }
+            }
+            show_pc(vm)
+        },
+        "run" | "r" => {
+            match vm.run_until_breakpoint().map_err(|e| e.to_string())? {
+                BreakReason::Breakpoint(pc) => Ok(format!("breakpoint at {}\n{}", pc, show_pc(vm)?)),
+                BreakReason::Watchpoint { addr, old, new, pc } => {
+                    Ok(format!("watchpoint: [{}] {} -> {} by the instruction at {}", addr, old, new, pc))
+                },
+                BreakReason::NeedsInput => Ok("waiting for input".to_owned()),
+                BreakReason::Halted => Ok("halted".to_owned()),
+            }
+        },
+        "break" | "b" => {
+            let addr = address(args.first().ok_or("break needs an address")?)?;
+            vm.add_breakpoint(addr);
+            Ok(format!("breakpoint at {}", addr))
+        },
+        "watch" | "w" => {
+            let addr = address(args.first().ok_or("watch needs an address")?)?;
+            vm.add_watchpoint(addr);
+            Ok(format!("watchpoint at {}", addr))
+        },
+        "print" | "p" => {
+            let range = args.first().ok_or("print needs an address")?;
+            // the last cell is kept instead of the end so that i64::MAX can be printed
+            let (start, last) = match range.split_once("..") {
+                Some((start, end)) => {
+                    let (start, end) = (address(start)?, address(end)?);
+                    if end <= start {
+                        return Ok(String::new());
+                    }
+                    (start, end - 1)
+                },
+                None => {
+                    let addr = address(range)?;
+                    (addr, addr)
+                },
+            };
+            if last - start >= MAX_LISTING {
+                return Err(format!("print shows at most {} cells", MAX_LISTING));
+            }
+            let cells = (start..=last)
+                .map(|addr| vm.peek_at(addr).map(|v| format!("{:04}: {}", addr, v)))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            Ok(cells.join("\n"))
+        },
+        "regs" => Ok(format!("pc: {}, rb: {}, state: {:?}", vm.pc(), vm.relative_base(), vm.state())),
+        "in" | "i" => {
+            let values = args.iter()
+                .map(|a| number(a))
+                .collect::<Result<Vec<_>, _>>()?;
+            vm.add_inputs(&values);
+            Ok(String::new())
+        },
+        "out" | "o" => {
+            let outputs: Vec<_> = vm.get_outputs().map(|o| o.to_string()).collect();
+            Ok(outputs.join(","))
+        },
+        "dis" | "d" => {
+            let start = match args.first() {
+                Some(start) => address(start)?,
+                None => vm.pc(),
+            };
+            let count = arg(args, 1)?.unwrap_or(10);
+            if !(1..=MAX_LISTING).contains(&count) {
+                return Err(format!("dis shows between 1 and {} instructions", MAX_LISTING));
+            }
+            let origin = usize::try_from(start).map_err(|_| format!("{} is not an address", start))?;
+            // instructions are at most four words long
+            let words = (0..4 * count)
+                .map_while(|i| start.checked_add(i))
+                .map(|addr| vm.peek_at(addr))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            let listing: Vec<_> = intcode::disasm::instructions_at(&words, origin)
+                .into_iter()
+                .take(count as usize)
+                .map(|i| i.to_string())
+                .collect();
+            Ok(listing.join("\n"))
+        },
+        "restart" => {
+            vm.reset();
+            Ok("restarted".to_owned())
+        },
+        _ => Ok(HELP.to_owned()),
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::args().nth(1).ok_or("usage: icdb <program>")?;
+    let mut vm = Vm::from_source(&std::fs::read_to_string(path)?)?;
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write!(out, "(icdb) ")?;
+    out.flush()?;
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if matches!(line.trim(), "quit" | "q") {
+            break;
+        }
+        match execute(&mut vm, &line) {
+            Ok(text) if text.is_empty() => {},
+            Ok(text) => writeln!(out, "{}", text)?,
+            Err(e) => writeln!(out, "error: {}", e)?,
+        }
+        write!(out, "(icdb) ")?;
+        out.flush()?;
+    }
+    Ok(())
+}
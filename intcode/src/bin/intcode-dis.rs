@@ -0,0 +1,77 @@
+use intcode::disasm::instructions_at;
+use intcode::{Coverage, SymbolTable};
+
+const USAGE: &str = "usage: intcode-dis <program> [--symbols file] [--start addr] [--count n] [--coverage file]";
+
+struct Options {
+    path: String,
+    symbols: Option<String>,
+    start: usize,
+    count: Option<usize>,
+    coverage: Option<String>,
+}
+
+fn number(token: &str) -> Result<usize, String> {
+    token.trim().parse().map_err(|_| format!("invalid number `{}`", token))
+}
+
+fn parse_args<I: Iterator<Item=String>>(mut args: I) -> Result<Options, String> {
+    let mut path = None;
+    let mut options = Options {
+        path: String::new(),
+        symbols: None,
+        start: 0,
+        count: None,
+        coverage: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--symbols" => options.symbols = Some(value()?),
+            "--start" => options.start = number(&value()?)?,
+            "--count" => options.count = Some(number(&value()?)?),
+            "--coverage" => options.coverage = Some(value()?),
+            "--help" | "-h" => return Err(USAGE.to_owned()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option `{}`\n{}", flag, USAGE)),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_owned()),
+        }
+    }
+    options.path = path.ok_or(USAGE)?;
+    Ok(options)
+}
+
+// Garbage words are listed as DATA, only unreadable files and bad option values are errors.
+fn listing(options: &Options) -> Result<String, String> {
+    let source = std::fs::read_to_string(&options.path).map_err(|e| format!("cannot read {}: {}", options.path, e))?;
+    let program = intcode::parse_program(&source).map_err(|e| e.to_string())?;
+    let symbols = match &options.symbols {
+        Some(path) => SymbolTable::load(path).map_err(|e| format!("cannot load {}: {}", path, e))?,
+        None => SymbolTable::new(),
+    };
+    let start = options.start.min(program.len());
+    let mut text = String::new();
+    for instruction in instructions_at(&program[start..], start).iter().take(options.count.unwrap_or(usize::MAX)) {
+        if let Some(name) = symbols.name(instruction.addr() as i64) {
+            text.push_str(&format!("{}:\n", name));
+        }
+        text.push_str(&format!("{}\n", instruction.with_symbols(&symbols)));
+    }
+    match &options.coverage {
+        Some(path) => {
+            let coverage = Coverage::load(path).map_err(|e| format!("cannot load {}: {}", path, e))?;
+            Ok(coverage.annotate(&text))
+        },
+        None => Ok(text),
+    }
+}
+
+fn main() {
+    match parse_args(std::env::args().skip(1)).and_then(|options| listing(&options)) {
+        Ok(text) => print!("{}", text),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,104 @@
+use std::io;
+
+use intcode::Vm;
+
+const USAGE: &str = "usage: intcode-run <program> [--input 1,2,3] [--ascii] [--patch addr=value]... [--steps-limit N] [--trace] [--profile] [--coverage file]";
+
+struct Options {
+    path: String,
+    inputs: Vec<i64>,
+    ascii: bool,
+    patches: Vec<(i64, i64)>,
+    steps_limit: Option<u64>,
+    trace: bool,
+    profile: bool,
+    coverage: Option<String>,
+}
+
+fn number<T: std::str::FromStr>(token: &str) -> Result<T, String> {
+    token.trim().parse().map_err(|_| format!("invalid number `{}`", token))
+}
+
+fn parse_args<I: Iterator<Item=String>>(mut args: I) -> Result<Options, String> {
+    let mut path = None;
+    let mut options = Options {
+        path: String::new(),
+        inputs: Vec::new(),
+        ascii: false,
+        patches: Vec::new(),
+        steps_limit: None,
+        trace: false,
+        profile: false,
+        coverage: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--input" => {
+                for token in value()?.split(',').filter(|t| !t.trim().is_empty()) {
+                    options.inputs.push(number(token)?);
+                }
+            },
+            "--patch" => {
+                let patch = value()?;
+                let (addr, val) = patch.split_once('=').ok_or(format!("invalid patch `{}`, expected addr=value", patch))?;
+                options.patches.push((number(addr)?, number(val)?));
+            },
+            "--steps-limit" => options.steps_limit = Some(number(&value()?)?),
+            "--coverage" => options.coverage = Some(value()?),
+            "--ascii" => options.ascii = true,
+            "--trace" => options.trace = true,
+            "--profile" => options.profile = true,
+            "--help" | "-h" => return Err(USAGE.to_owned()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option `{}`\n{}", flag, USAGE)),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_owned()),
+        }
+    }
+    options.path = path.ok_or(USAGE)?;
+    Ok(options)
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let source = std::fs::read_to_string(&options.path).map_err(|e| format!("cannot read {}: {}", options.path, e))?;
+    let mut builder = Vm::builder()
+        .program(intcode::parse_program(&source).map_err(|e| e.to_string())?)
+        .inputs(&options.inputs);
+    for &(addr, value) in &options.patches {
+        builder = builder.patch(addr, value);
+    }
+    if let Some(limit) = options.steps_limit {
+        builder = builder.step_limit(limit);
+    }
+    let mut vm = builder.build().map_err(|e| e.to_string())?;
+    if options.trace {
+        vm.set_trace_hook(intcode::trace_to_writer(io::stderr()));
+    }
+    if options.profile || options.coverage.is_some() {
+        vm.enable_profiling();
+    }
+    let result = if options.ascii {
+        vm.run_interactive(io::stdin().lock(), io::stdout().lock())
+    } else {
+        let result = vm.run();
+        for o in vm.get_outputs() {
+            println!("{}", o);
+        }
+        result
+    };
+    // the profile is useful for a failing program too
+    if options.profile {
+        eprintln!("{}", vm.profile());
+    }
+    if let Some(path) = &options.coverage {
+        vm.coverage().save(path).map_err(|e| format!("cannot write {}: {}", path, e))?;
+    }
+    result.map_err(|e| e.to_string())
+}
+
+fn main() {
+    if let Err(e) = parse_args(std::env::args().skip(1)).and_then(run) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
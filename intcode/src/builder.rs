@@ -0,0 +1,142 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::{InputSource, OutputSink, Result, VMError, Vm};
+
+#[derive(Default)]
+pub struct VmBuilder {
+    program: Vec<i64>,
+    patches: Vec<(i64, i64)>,
+    inputs: Vec<i64>,
+    step_limit: Option<u64>,
+    memory_limit: Option<usize>,
+    strict_decoding: bool,
+    input_source: Option<Box<dyn InputSource + Send>>,
+    output_sink: Option<Box<dyn OutputSink + Send>>,
+}
+
+impl VmBuilder {
+    pub fn new() -> VmBuilder {
+        VmBuilder::default()
+    }
+    pub fn program(mut self, program: Vec<i64>) -> VmBuilder {
+        self.program = program;
+        self
+    }
+    pub fn patch(mut self, addr: i64, value: i64) -> VmBuilder {
+        self.patches.push((addr, value));
+        self
+    }
+    pub fn input(mut self, input: i64) -> VmBuilder {
+        self.inputs.push(input);
+        self
+    }
+    pub fn inputs(mut self, inputs: &[i64]) -> VmBuilder {
+        self.inputs.extend_from_slice(inputs);
+        self
+    }
+    pub fn step_limit(mut self, limit: u64) -> VmBuilder {
+        self.step_limit = Some(limit);
+        self
+    }
+    pub fn memory_limit(mut self, limit: usize) -> VmBuilder {
+        self.memory_limit = Some(limit);
+        self
+    }
+    pub fn strict_decoding(mut self, strict: bool) -> VmBuilder {
+        self.strict_decoding = strict;
+        self
+    }
+    pub fn input_source<S: InputSource + Send + 'static>(mut self, source: S) -> VmBuilder {
+        self.input_source = Some(Box::new(source));
+        self
+    }
+    pub fn output_sink<S: OutputSink + Send + 'static>(mut self, sink: S) -> VmBuilder {
+        self.output_sink = Some(Box::new(sink));
+        self
+    }
+    pub fn build(self) -> Result<Vm> {
+        if let Some(&(addr, _)) = self.patches.iter().find(|&&(addr, _)| addr < 0) {
+            return Err(VMError::InvalidPatch { addr });
+        }
+        let mut vm = Vm::new(self.program);
+        if let Some(limit) = self.memory_limit {
+            vm.set_memory_limit(Some(limit));
+        }
+        for (addr, value) in self.patches {
+            vm.write_at(addr, value)?;
+        }
+        vm.set_step_limit(self.step_limit);
+        vm.set_strict_decoding(self.strict_decoding);
+        vm.add_inputs(&self.inputs);
+        vm.input_source = self.input_source;
+        vm.output_sink = self.output_sink;
+        Ok(vm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::parse_program;
+
+    const DAY2: &str = "1,9,10,3,2,3,11,0,99,30,40,50";
+
+    #[test]
+    fn matches_manual_setup() {
+        let mut built = Vm::builder()
+            .program(parse_program(DAY2).unwrap())
+            .patch(1, 10)
+            .patch(2, 9)
+            .step_limit(10)
+            .memory_limit(100)
+            .build()
+            .unwrap();
+        let mut manual = Vm::new(parse_program(DAY2).unwrap());
+        manual.write_at(1, 10).unwrap();
+        manual.write_at(2, 9).unwrap();
+        manual.set_step_limit(Some(10));
+        manual.set_memory_limit(Some(100));
+        assert!(built == manual);
+        built.run().unwrap();
+        manual.run().unwrap();
+        assert!(built == manual);
+        assert_eq!(built.peek_at(0).unwrap(), 3500);
+        assert_eq!(built.steps_executed(), manual.steps_executed());
+    }
+
+    #[test]
+    fn limits_and_inputs_are_applied() {
+        let mut vm = Vm::builder()
+            .program(parse_program("3,0,3,1,1105,1,4").unwrap())
+            .input(1)
+            .inputs(&[2, 3])
+            .step_limit(50)
+            .build()
+            .unwrap();
+        assert!(matches!(vm.run(), Err(VMError::StepLimitExceeded { steps: 50, .. })));
+        assert_eq!(vm.pending_inputs(), 1);
+        let result = Vm::builder().program(vec![1101, 0, 0, 1000, 99]).memory_limit(10).build().unwrap().run();
+        assert!(matches!(result, Err(VMError::MemoryLimitExceeded { addr: 1000, limit: 10, .. })));
+    }
+
+    #[test]
+    fn sources_and_sinks() {
+        let mut vm = Vm::builder()
+            .program(parse_program("3,0,4,0,99").unwrap())
+            .input_source(|| Some(4))
+            .output_sink(|o: i64| assert_eq!(o, 4))
+            .build()
+            .unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.pending_outputs(), 0);
+    }
+
+    #[test]
+    fn negative_patches_are_rejected() {
+        let result = Vm::builder().program(vec![99]).patch(3, 1).patch(-2, 1).build();
+        assert!(matches!(result, Err(VMError::InvalidPatch { addr: -2 })));
+    }
+}
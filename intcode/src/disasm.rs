@@ -0,0 +1,188 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::symbols::{SymbolTable, Symbolized};
+use crate::{Opcode, ParameterMode};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Instruction {
+    Op {
+        addr: usize,
+        op: Opcode,
+        params: Vec<i64>,
+    },
+    Data {
+        addr: usize,
+        value: i64,
+    },
+}
+
+impl Instruction {
+    pub fn addr(&self) -> usize {
+        match self {
+            Instruction::Op { addr, .. } | Instruction::Data { addr, .. } => *addr,
+        }
+    }
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::Op { params, .. } => 1 + params.len(),
+            Instruction::Data { .. } => 1,
+        }
+    }
+    pub fn with_symbols<'a>(&'a self, symbols: &'a SymbolTable) -> Symbolized<'a, Instruction> {
+        Symbolized { item: self, symbols }
+    }
+    fn fmt_with(&self, f: &mut fmt::Formatter<'_>, symbols: Option<&SymbolTable>) -> fmt::Result {
+        match self {
+            Instruction::Op { addr, op, params } => {
+                let jump = matches!(op, Opcode::JumpIfTrue(..) | Opcode::JumpIfFalse(..));
+                let mut operands: Vec<_> = op.modes().into_iter()
+                    .zip(params)
+                    .enumerate()
+                    .map(|(idx, (m, &v))| operand(m, v, symbols, jump && idx == 1))
+                    .collect();
+                let dst = if op.writes() { operands.pop() } else { None };
+                write!(f, "{:04}: {:<4}", addr, mnemonic(*op))?;
+                if !operands.is_empty() {
+                    write!(f, " {}", operands.join(", "))?;
+                }
+                if let Some(dst) = dst {
+                    write!(f, " -> {}", dst)?;
+                }
+                Ok(())
+            },
+            Instruction::Data { addr, value } => write!(f, "{:04}: DATA {}", addr, value),
+        }
+    }
+}
+
+fn mnemonic(op: Opcode) -> &'static str {
+    match op {
+        Opcode::Add(..) => "ADD",
+        Opcode::Mul(..) => "MUL",
+        Opcode::Input(..) => "IN",
+        Opcode::Output(..) => "OUT",
+        Opcode::JumpIfTrue(..) => "JNZ",
+        Opcode::JumpIfFalse(..) => "JZ",
+        Opcode::LessThan(..) => "LT",
+        Opcode::Equals(..) => "EQ",
+        Opcode::RelativeBaseOffset(..) => "ARB",
+        Opcode::End => "HALT",
+    }
+}
+
+fn operand(mode: ParameterMode, value: i64, symbols: Option<&SymbolTable>, jump_target: bool) -> String {
+    let name = symbols.and_then(|s| s.name(value));
+    match (mode, name) {
+        (ParameterMode::Position, Some(name)) => format!("[{}]", name),
+        (ParameterMode::Immediate, Some(name)) if jump_target => name.into(),
+        _ => plain_operand(mode, value),
+    }
+}
+
+fn plain_operand(mode: ParameterMode, value: i64) -> String {
+    match mode {
+        ParameterMode::Position => format!("[{}]", value),
+        ParameterMode::Immediate => format!("#{}", value),
+        ParameterMode::Relative if value < 0 => format!("rb{}", value),
+        ParameterMode::Relative => format!("rb+{}", value),
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, None)
+    }
+}
+
+impl fmt::Display for Symbolized<'_, Instruction> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.item.fmt_with(f, Some(self.symbols))
+    }
+}
+
+pub fn instructions(program: &[i64]) -> Vec<Instruction> {
+    instructions_at(program, 0)
+}
+
+pub fn instructions_at(words: &[i64], origin: usize) -> Vec<Instruction> {
+    let mut listing = Vec::new();
+    let mut offset = 0;
+    while offset < words.len() {
+        let addr = origin + offset;
+        let value = words[offset];
+        let instruction = match Opcode::decode(value) {
+            Ok((op, len)) if offset + len <= words.len() => Instruction::Op {
+                addr,
+                op,
+                params: words[offset+1..=offset+op.arity()].to_vec(),
+            },
+            _ => Instruction::Data { addr, value },
+        };
+        offset += instruction.size();
+        listing.push(instruction);
+    }
+    listing
+}
+
+pub fn disassemble(program: &[i64]) -> String {
+    instructions(program).iter().map(|i| format!("{}\n", i)).collect()
+}
+
+pub fn disassemble_with_symbols(program: &[i64], symbols: &SymbolTable) -> String {
+    let mut listing = String::new();
+    for instruction in instructions(program) {
+        if let Some(name) = symbols.name(instruction.addr() as i64) {
+            listing.push_str(&format!("{}:\n", name));
+        }
+        listing.push_str(&format!("{}\n", instruction.with_symbols(symbols)));
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn day2_example_listing() {
+        let program = [1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        assert_eq!(disassemble(&program), "\
+0000: ADD  [9], [10] -> [3]
+0004: MUL  [3], [11] -> [0]
+0008: HALT
+0009: DATA 30
+0010: DATA 40
+0011: DATA 50
+");
+    }
+
+    #[test]
+    fn operand_modes() {
+        let program = [1101, 2, 3, 5, 21002, -1, 7, 4, 1105, 0, 4, 99];
+        let listing: Vec<_> = instructions(&program).iter().map(|i| i.to_string()).collect();
+        assert_eq!(listing, vec![
+            "0000: ADD  #2, #3 -> [5]",
+            "0004: MUL  [-1], #7 -> rb+4",
+            "0008: JNZ  #0, #4",
+            "0011: HALT",
+        ]);
+    }
+
+    #[test]
+    fn truncated_instructions_are_data() {
+        let listing = instructions(&[1, 2, 3]);
+        assert_eq!(listing, vec![
+            Instruction::Data { addr: 0, value: 1 },
+            Instruction::Data { addr: 1, value: 2 },
+            Instruction::Data { addr: 2, value: 3 },
+        ]);
+        assert_eq!(instructions_at(&[104, -3], 20)[0].to_string(), "0020: OUT  #-3");
+        assert_eq!(instructions_at(&[204, -3], 20)[0].to_string(), "0020: OUT  rb-3");
+    }
+}
@@ -1,36 +1,246 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
 use thiserror::Error;
-use std::convert::TryFrom;
-use std::collections::VecDeque;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::str::FromStr;
+use core::fmt;
+use core::ops::Range;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::io::{BufRead, Write};
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::time::Instant;
+#[cfg(feature = "std")]
+use std::sync::mpsc::{Receiver, Sender};
+#[cfg(feature = "trace-log")]
 use log::debug;
+#[cfg(not(feature = "trace-log"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {};
+}
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+mod memory;
+use memory::Memory;
+mod word;
+pub use word::IntcodeWord;
+mod builder;
+pub use builder::VmBuilder;
+mod profile;
+pub use profile::{Coverage, Profile};
+pub mod net;
+#[cfg(feature = "std")]
+pub mod threaded;
+pub mod network;
+pub mod disasm;
+pub mod symbols;
+pub use symbols::SymbolTable;
+use symbols::Symbolized;
+pub mod verify;
+#[cfg(feature = "std")]
+pub mod asm;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "std")]
+mod transcript;
+#[cfg(feature = "std")]
+use transcript::Transcript;
+#[cfg(feature = "rayon")]
+pub mod pool;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::AsyncVm;
 
-#[derive(Error, Debug)]
+#[derive(Debug)]
+#[cfg_attr(feature = "std", derive(Error))]
 pub enum VMError {
-    #[error("The opcode `{opcode}` at address {addr} is invalid")]
     InvalidOpcode {
         opcode: i64,
-        addr: i64,
+        pc: i64,
+        rb: i64,
     },
-    #[error("Invalid address `{addr}`")]
     InvalidAddress {
         addr: i64,
+        pc: i64,
+        rb: i64,
+    },
+    NegativePositionAddress {
+        addr: i64,
+        pc: i64,
+    },
+    NegativeRelativeAddress {
+        offset: i64,
+        relative_base: i64,
+        pc: i64,
+    },
+    Stopped {
+        pc: i64,
+        rb: i64,
+    },
+    Paused {
+        pc: i64,
+    },
+    NoMoreInput {
+        pc: i64,
+        rb: i64,
+    },
+    ParseError {
+        index: usize,
+        token: String,
+    },
+    InvalidPatch {
+        addr: i64,
+    },
+    Deadlock {
+        stages: Vec<usize>,
+    },
+    UnroutablePacket {
+        from: usize,
+        to: i64,
+    },
+    NetworkHalted,
+    InvalidRecording {
+        line: usize,
+        text: String,
+    },
+    InvalidSymbol {
+        line: usize,
+        text: String,
+    },
+    InvalidCoverage {
+        line: usize,
+        text: String,
+    },
+    TruncatedOutput {
+        expected: usize,
+        got: usize,
+    },
+    NonAscii {
+        value: i64,
+    },
+    MemoryLimitExceeded {
+        addr: i64,
+        limit: usize,
+        pc: i64,
+    },
+    InvalidWriteMode {
+        opcode: i64,
+        pc: i64,
+    },
+    ArithmeticOverflow {
+        pc: i64,
+        lhs: i64,
+        rhs: i64,
+        op: &'static str,
+    },
+    Timeout {
+        elapsed: Duration,
+        steps: u64,
+    },
+    NothingToRewind {
+        pc: i64,
+    },
+    StepLimitExceeded {
+        steps: u64,
+        pc: i64,
+    },
+    #[cfg(feature = "std")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
+    Persistence {
+        reason: String,
     },
-    #[error("The VM is stopped")]
-    Stopped,
-    #[error("The VM is waiting for input, but none is available")]
-    NoMoreInput,
 }
 
-type Result<T> = std::result::Result<T, VMError>;
+// Written by hand rather than derived, so that no_std builds get the same messages.
+impl fmt::Display for VMError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VMError::InvalidOpcode { opcode, pc, rb } => write!(f, "The opcode `{opcode}` at address {pc} is invalid (relative base {rb})"),
+            VMError::InvalidAddress { addr, pc, rb } => write!(f, "Invalid address `{addr}` at pc {pc} (relative base {rb})"),
+            VMError::NegativePositionAddress { addr, pc } => write!(f, "Negative position mode address {addr} at pc {pc}"),
+            VMError::NegativeRelativeAddress { offset, relative_base, pc } => write!(f, "Negative relative mode address: offset {offset} + relative base {relative_base} at pc {pc}"),
+            VMError::Stopped { pc, rb } => write!(f, "The VM is stopped at pc {pc} (relative base {rb})"),
+            VMError::Paused { pc } => write!(f, "The VM is paused at pc {pc}"),
+            VMError::NoMoreInput { pc, rb } => write!(f, "The VM is waiting for input at pc {pc} (relative base {rb}), but none is available"),
+            VMError::ParseError { index, token } => write!(f, "Cannot parse `{token}` at index {index} of the program"),
+            VMError::InvalidPatch { addr } => write!(f, "Cannot patch the negative address {addr}"),
+            VMError::Deadlock { stages } => write!(f, "The VMs {stages:?} are all waiting for input that will never arrive"),
+            VMError::UnroutablePacket { from, to } => write!(f, "Machine {from} sent a packet to unknown address {to}"),
+            VMError::NetworkHalted => write!(f, "Every machine in the network has halted"),
+            VMError::InvalidRecording { line, text } => write!(f, "Invalid recording entry `{text}` at line {line}"),
+            VMError::InvalidSymbol { line, text } => write!(f, "Invalid symbol entry `{text}` at line {line}"),
+            VMError::InvalidCoverage { line, text } => write!(f, "Invalid coverage entry `{text}` at line {line}"),
+            VMError::TruncatedOutput { expected, got } => write!(f, "The VM halted after {got} of {expected} outputs"),
+            VMError::NonAscii { value } => write!(f, "The value {value} is not an ASCII character"),
+            VMError::MemoryLimitExceeded { addr, limit, pc } => write!(f, "Writing address {addr} at pc {pc} would exceed the memory limit of {limit} cells"),
+            VMError::InvalidWriteMode { opcode, pc } => write!(f, "The opcode `{opcode}` at address {pc} writes through an immediate mode parameter"),
+            VMError::ArithmeticOverflow { pc, lhs, rhs, op } => write!(f, "Overflow in {op} of {lhs} and {rhs} at pc {pc}"),
+            VMError::Timeout { elapsed, steps } => write!(f, "Timed out after {elapsed:?} and {steps} steps"),
+            VMError::NothingToRewind { pc } => write!(f, "Nothing left to rewind at pc {pc}"),
+            VMError::StepLimitExceeded { steps, pc } => write!(f, "Step limit exceeded after {steps} steps at pc {pc}"),
+            #[cfg(feature = "std")]
+            VMError::Io { source } => write!(f, "I/O error: {source}"),
+            VMError::Persistence { reason } => write!(f, "Cannot save or load the VM: {reason}"),
+        }
+    }
+}
+
+type Result<T> = core::result::Result<T, VMError>;
+
+pub const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024 / core::mem::size_of::<i64>();
+pub const DEFAULT_TIMEOUT_CHECK_INTERVAL: u64 = 10_000;
+
+pub fn parse_program(source: &str) -> Result<Vec<i64>> {
+    parse_words(source)
+}
+
+pub fn parse_words<W: IntcodeWord>(source: &str) -> Result<Vec<W>> {
+    let mut tokens: Vec<_> = source.trim().split(',').map(str::trim).collect();
+    while tokens.last() == Some(&"") {
+        tokens.pop();
+    }
+    tokens
+        .into_iter()
+        .enumerate()
+        .map(|(index, token)| {
+            token.parse().map_err(|_| VMError::ParseError { index, token: token.into() })
+        })
+        .collect()
+}
+
+pub fn to_source<W: IntcodeWord>(program: &[W]) -> String {
+    program.iter().map(ToString::to_string).collect::<Vec<_>>().join(",")
+}
+
+fn ascii_char<W: IntcodeWord>(value: W) -> Option<char> {
+    value.to_i64().and_then(|v| u8::try_from(v).ok()).filter(u8::is_ascii).map(char::from)
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
-enum ParameterMode {
+pub enum ParameterMode {
     Immediate,
     Position,
     Relative,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
-enum Opcode {
+pub enum Opcode {
     Add(ParameterMode, ParameterMode, ParameterMode),
     Mul(ParameterMode, ParameterMode, ParameterMode),
     Input(ParameterMode),
@@ -43,256 +253,2925 @@ enum Opcode {
     End,
 }
 
-pub struct Vm {
-    memory: Vec<i64>,
+impl Opcode {
+    // Mode digits past the last parameter are ignored (as they are while running,
+    // unless strict decoding is on), so `1104` decodes like `104`; encode always
+    // produces the canonical form.
+    pub fn decode(word: i64) -> Result<(Opcode, usize)> {
+        let op = Opcode::decode_at(word, 0, 0, false)?;
+        Ok((op, op.arity() + 1))
+    }
+    pub fn encode(self) -> i64 {
+        let code = match self {
+            Opcode::Add(..) => 1,
+            Opcode::Mul(..) => 2,
+            Opcode::Input(..) => 3,
+            Opcode::Output(..) => 4,
+            Opcode::JumpIfTrue(..) => 5,
+            Opcode::JumpIfFalse(..) => 6,
+            Opcode::LessThan(..) => 7,
+            Opcode::Equals(..) => 8,
+            Opcode::RelativeBaseOffset(..) => 9,
+            Opcode::End => 99,
+        };
+        self.modes().into_iter().rev().fold(0, |acc, mode| {
+            acc * 10 + match mode {
+                ParameterMode::Position => 0,
+                ParameterMode::Immediate => 1,
+                ParameterMode::Relative => 2,
+            }
+        }) * 100 + code
+    }
+    fn decode_at(word: i64, pc: i64, rb: i64, strict: bool) -> Result<Opcode> {
+        let mode = |param| decode_mode(word, param).ok_or(VMError::InvalidOpcode { opcode: word, pc, rb });
+        let write_mode = |param| match mode(param)? {
+            ParameterMode::Immediate => Err(VMError::InvalidWriteMode { opcode: word, pc }),
+            m => Ok(m),
+        };
+        let op = match word % 100 {
+            1  => Opcode::Add(mode(0)?, mode(1)?, write_mode(2)?),
+            2  => Opcode::Mul(mode(0)?, mode(1)?, write_mode(2)?),
+            3  => Opcode::Input(write_mode(0)?),
+            4  => Opcode::Output(mode(0)?),
+            5  => Opcode::JumpIfTrue(mode(0)?, mode(1)?),
+            6  => Opcode::JumpIfFalse(mode(0)?, mode(1)?),
+            7  => Opcode::LessThan(mode(0)?, mode(1)?, write_mode(2)?),
+            8  => Opcode::Equals(mode(0)?, mode(1)?, write_mode(2)?),
+            9  => Opcode::RelativeBaseOffset(mode(0)?),
+            99 => Opcode::End,
+            o  => return Err(VMError::InvalidOpcode{opcode: o, pc, rb}),
+        };
+        if strict && mode_digits(word, op.arity() as u32) != 0 {
+            return Err(VMError::InvalidOpcode { opcode: word, pc, rb });
+        }
+        Ok(op)
+    }
+    pub fn arity(self) -> usize {
+        match self {
+            Opcode::Add(..) | Opcode::Mul(..) | Opcode::LessThan(..) | Opcode::Equals(..) => 3,
+            Opcode::JumpIfTrue(..) | Opcode::JumpIfFalse(..) => 2,
+            Opcode::Input(..) | Opcode::Output(..) | Opcode::RelativeBaseOffset(..) => 1,
+            Opcode::End => 0,
+        }
+    }
+    pub fn modes(self) -> Vec<ParameterMode> {
+        match self {
+            Opcode::Add(m1, m2, m3) | Opcode::Mul(m1, m2, m3)
+                | Opcode::LessThan(m1, m2, m3) | Opcode::Equals(m1, m2, m3) => vec![m1, m2, m3],
+            Opcode::JumpIfTrue(m1, m2) | Opcode::JumpIfFalse(m1, m2) => vec![m1, m2],
+            Opcode::Input(m1) | Opcode::Output(m1) | Opcode::RelativeBaseOffset(m1) => vec![m1],
+            Opcode::End => vec![],
+        }
+    }
+    pub fn writes(self) -> bool {
+        matches!(self, Opcode::Add(..) | Opcode::Mul(..) | Opcode::LessThan(..) | Opcode::Equals(..) | Opcode::Input(..))
+    }
+}
+
+// The digits of `opcode` from the mode of parameter `param` upwards.
+fn mode_digits(opcode: i64, param: u32) -> i64 {
+    param.checked_add(2)
+        .and_then(|exp| 10i64.checked_pow(exp))
+        .map_or(0, |divisor| opcode / divisor)
+}
+
+fn decode_mode(opcode: i64, param: u32) -> Option<ParameterMode> {
+    match mode_digits(opcode, param) % 10 {
+        0 => { Some(ParameterMode::Position) },
+        1 => { Some(ParameterMode::Immediate) },
+        2 => { Some(ParameterMode::Relative) },
+        _ => { None },
+    }
+}
+
+impl fmt::Display for ParameterMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParameterMode::Immediate => write!(f, "imm"),
+            ParameterMode::Position => write!(f, "pos"),
+            ParameterMode::Relative => write!(f, "rel"),
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Opcode::Add(m1, m2, m3) => write!(f, "Add({}, {}, {})", m1, m2, m3),
+            Opcode::Mul(m1, m2, m3) => write!(f, "Mul({}, {}, {})", m1, m2, m3),
+            Opcode::Input(m1) => write!(f, "Input({})", m1),
+            Opcode::Output(m1) => write!(f, "Output({})", m1),
+            Opcode::JumpIfTrue(m1, m2) => write!(f, "JumpIfTrue({}, {})", m1, m2),
+            Opcode::JumpIfFalse(m1, m2) => write!(f, "JumpIfFalse({}, {})", m1, m2),
+            Opcode::LessThan(m1, m2, m3) => write!(f, "LessThan({}, {}, {})", m1, m2, m3),
+            Opcode::Equals(m1, m2, m3) => write!(f, "Equals({}, {}, {})", m1, m2, m3),
+            Opcode::RelativeBaseOffset(m1) => write!(f, "RelativeBaseOffset({})", m1),
+            Opcode::End => write!(f, "End"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Executed<W = i64> {
+    pc: i64,
+    op: Opcode,
+    args: [W; 3],
+}
+
+impl<W: IntcodeWord> fmt::Display for Executed<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.pc, self.op)?;
+        for a in &self.args[..self.op.arity()] {
+            write!(f, " {}", a)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct JournalEntry<W> {
+    pc: i64,
+    rb: i64,
+    state: VmState,
+    old: Option<(i64, W)>,
+    input: Option<W>,
+    output: bool,
+    queued_output: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceEvent<W = i64> {
+    pub pc: i64,
+    pub op: Opcode,
+    pub args: Vec<W>,
+    pub dst: Option<i64>,
+    pub old: Option<W>,
+    pub rb: i64,
+}
+
+impl<W: IntcodeWord> TraceEvent<W> {
+    pub fn with_symbols<'a>(&'a self, symbols: &'a SymbolTable) -> Symbolized<'a, TraceEvent<W>> {
+        Symbolized { item: self, symbols }
+    }
+    fn fmt_with(&self, f: &mut fmt::Formatter<'_>, symbols: Option<&SymbolTable>) -> fmt::Result {
+        let addr = |addr: i64| match symbols {
+            Some(symbols) => symbols.addr_or_name(addr),
+            None => addr.to_string(),
+        };
+        write!(f, "[{}] {}", addr(self.pc), self.op)?;
+        for a in &self.args {
+            write!(f, " {}", a)?;
+        }
+        if let (Some(dst), Some(old)) = (self.dst, self.old) {
+            write!(f, " -> [{}] (was {})", addr(dst), old)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: IntcodeWord> fmt::Display for TraceEvent<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, None)
+    }
+}
+
+impl<W: IntcodeWord> fmt::Display for Symbolized<'_, TraceEvent<W>> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.item.fmt_with(f, Some(self.symbols))
+    }
+}
+
+pub type TraceHook<W = i64> = Box<dyn FnMut(TraceEvent<W>) + Send>;
+
+#[cfg(feature = "std")]
+pub fn trace_to_writer<W: IntcodeWord, O: Write + Send + 'static>(mut writer: O) -> TraceHook<W> {
+    Box::new(move |event| {
+        let _ = writeln!(writer, "{}", event);
+    })
+}
+
+#[cfg(feature = "std")]
+pub fn trace_to_writer_with_symbols<W, O>(mut writer: O, symbols: SymbolTable) -> TraceHook<W>
+where
+    W: IntcodeWord,
+    O: Write + Send + 'static,
+{
+    Box::new(move |event| {
+        let _ = writeln!(writer, "{}", event.with_symbols(&symbols));
+    })
+}
+
+pub struct Context<W = i64> {
+    pub pc: i64,
+    pub rb: i64,
+    pub state: VmState,
+    pub history: Vec<Executed<W>>,
+}
+
+impl<W: IntcodeWord> fmt::Display for Context<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pc: {}, relative base: {}, state: {:?}", self.pc, self.rb, self.state)?;
+        if !self.history.is_empty() {
+            write!(f, "\nrecent instructions:")?;
+            for e in &self.history {
+                write!(f, "\n  {}", e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub trait InputSource<W = i64> {
+    fn next_input(&mut self) -> Option<W>;
+}
+
+impl<W> InputSource<W> for VecDeque<W> {
+    fn next_input(&mut self) -> Option<W> {
+        self.pop_front()
+    }
+}
+
+impl<W, F: FnMut() -> Option<W>> InputSource<W> for F {
+    fn next_input(&mut self) -> Option<W> {
+        self()
+    }
+}
+
+pub struct IterInput<I>(pub I);
+
+impl<W, I: Iterator<Item=W>> InputSource<W> for IterInput<I> {
+    fn next_input(&mut self) -> Option<W> {
+        self.0.next()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W> InputSource<W> for Receiver<W> {
+    fn next_input(&mut self) -> Option<W> {
+        self.try_recv().ok()
+    }
+}
+
+pub trait OutputSink<W = i64> {
+    fn push_output(&mut self, value: W);
+}
+
+impl<W> OutputSink<W> for Vec<W> {
+    fn push_output(&mut self, value: W) {
+        self.push(value);
+    }
+}
+
+impl<W, F: FnMut(W)> OutputSink<W> for F {
+    fn push_output(&mut self, value: W) {
+        self(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W> OutputSink<W> for Sender<W> {
+    fn push_output(&mut self, value: W) {
+        let _ = self.send(value);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W, S: OutputSink<W>> OutputSink<W> for Arc<Mutex<S>> {
+    fn push_output(&mut self, value: W) {
+        if let Ok(mut s) = self.lock() {
+            s.push_output(value);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RunSummary {
+    pub steps: u64,
+    pub inputs_consumed: usize,
+    pub outputs_produced: usize,
+    pub peak_memory_cells: usize,
+    pub halted: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound = "W: IntcodeWord"))]
+pub struct Snapshot<W = i64> {
+    pub program: Vec<W>,
+    pub memory: Vec<W>,
+    pub sparse_memory: Vec<(i64, W)>,
+    pub pc: i64,
+    pub rb: i64,
+    pub state: VmState,
+    pub inputs: Vec<W>,
+    pub outputs: Vec<W>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "Snapshot<W>", into = "Snapshot<W>", bound = "W: IntcodeWord"))]
+pub struct Vm<W: IntcodeWord = i64> {
+    // the image is shared with clones and copied into memory only on the first write
+    program: Arc<Vec<W>>,
+    memory: Memory<W>,
+    decode_cache: Vec<Option<Opcode>>,
+    decode_caching: bool,
+    strict_decoding: bool,
+    memory_limit: Option<usize>,
     pc: i64,
+    op_pc: i64,
     rb: i64,
     state: VmState,
-    inputs: VecDeque<i64>,
-    outputs: VecDeque<i64>,
+    inputs: VecDeque<W>,
+    outputs: VecDeque<W>,
+    history: VecDeque<Executed<W>>,
+    history_len: usize,
+    journal: VecDeque<JournalEntry<W>>,
+    journal_capacity: usize,
+    steps: u64,
+    inputs_consumed: usize,
+    outputs_produced: usize,
+    step_limit: Option<u64>,
+    timeout_check_interval: u64,
+    breakpoints: BTreeSet<i64>,
+    break_pc: Option<i64>,
+    watchpoints: BTreeSet<i64>,
+    watch_hit: Option<BreakReason<W>>,
+    input_source: Option<Box<dyn InputSource<W> + Send>>,
+    output_sink: Option<Box<dyn OutputSink<W> + Send>>,
+    trace_hook: Option<TraceHook<W>>,
+    #[cfg(feature = "std")]
+    recorder: Option<Box<dyn Write + Send>>,
+    #[cfg(feature = "std")]
+    transcript: Option<Transcript>,
+    profiling: bool,
+    profile: Profile,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakReason<W = i64> {
+    Breakpoint(i64),
+    Watchpoint {
+        addr: i64,
+        old: W,
+        new: W,
+        pc: i64,
+    },
+    Halted,
+    NeedsInput,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event<W = i64> {
+    Output(W),
+    NeedsInput,
+    Halted,
+    Continue,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum VmState {
     Running,
     Stopped,
     WaitingForInput,
+    Paused,
 }
 
 impl Vm {
-    pub fn new(memory: Vec<i64>) -> Vm {
+    pub fn builder() -> VmBuilder {
+        VmBuilder::new()
+    }
+}
+
+impl<W: IntcodeWord> Vm<W> {
+    pub fn new(memory: Vec<W>) -> Vm<W> {
+        let program = Arc::new(memory);
         Vm {
-            memory,
+            decode_cache: Vec::new(),
+            decode_caching: true,
+            strict_decoding: false,
+            memory: Memory::new(program.clone()),
+            program,
+            memory_limit: Some(DEFAULT_MEMORY_LIMIT),
             pc: 0,
+            op_pc: 0,
             rb: 0,
             state: VmState::Running,
             inputs: VecDeque::new(),
             outputs: VecDeque::new(),
+            history: VecDeque::new(),
+            history_len: 0,
+            journal: VecDeque::new(),
+            journal_capacity: 0,
+            steps: 0,
+            inputs_consumed: 0,
+            outputs_produced: 0,
+            step_limit: None,
+            timeout_check_interval: DEFAULT_TIMEOUT_CHECK_INTERVAL,
+            breakpoints: BTreeSet::new(),
+            break_pc: None,
+            watchpoints: BTreeSet::new(),
+            watch_hit: None,
+            input_source: None,
+            output_sink: None,
+            trace_hook: None,
+            #[cfg(feature = "std")]
+            recorder: None,
+            #[cfg(feature = "std")]
+            transcript: None,
+            profiling: false,
+            profile: Profile::default(),
+        }
+    }
+    pub fn from_source(source: &str) -> Result<Vm<W>> {
+        Ok(Vm::new(parse_words(source)?))
+    }
+    pub fn from_snapshot(snapshot: Snapshot<W>) -> Vm<W> {
+        let mut vm = Vm::new(Vec::new());
+        vm.restore(snapshot);
+        vm
+    }
+    pub fn snapshot(&self) -> Snapshot<W> {
+        Snapshot {
+            program: self.program.to_vec(),
+            memory: self.memory.dense().to_vec(),
+            sparse_memory: self.memory.sparse().into_iter().map(|(a, v)| (a as i64, v)).collect(),
+            pc: self.pc,
+            rb: self.rb,
+            state: self.state,
+            inputs: self.inputs.iter().copied().collect(),
+            outputs: self.outputs.iter().copied().collect(),
+        }
+    }
+    pub fn restore(&mut self, snapshot: Snapshot<W>) {
+        self.program = Arc::new(snapshot.program);
+        let sparse = snapshot.sparse_memory.into_iter()
+            .filter_map(|(a, v)| usize::try_from(a).ok().map(|a| (a, v)))
+            .collect();
+        self.memory = Memory::from_parts(Arc::new(snapshot.memory), sparse);
+        self.decode_cache.clear();
+        self.pc = snapshot.pc;
+        self.op_pc = snapshot.pc;
+        self.rb = snapshot.rb;
+        self.state = snapshot.state;
+        self.inputs = snapshot.inputs.into();
+        self.outputs = snapshot.outputs.into();
+        self.history.clear();
+        self.journal.clear();
+        self.break_pc = None;
+        self.watch_hit = None;
+    }
+    #[cfg(feature = "serde")]
+    pub fn save_to<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        let persistence = |e: &dyn fmt::Display| VMError::Persistence { reason: e.to_string() };
+        let file = std::fs::File::create(path).map_err(|e| persistence(&e))?;
+        bincode::serialize_into(std::io::BufWriter::new(file), &self.snapshot()).map_err(|e| persistence(&e))
+    }
+    #[cfg(feature = "serde")]
+    pub fn load_from<P: AsRef<std::path::Path>>(path: P) -> Result<Vm<W>> {
+        let persistence = |e: &dyn fmt::Display| VMError::Persistence { reason: e.to_string() };
+        let file = std::fs::File::open(path).map_err(|e| persistence(&e))?;
+        let snapshot = bincode::deserialize_from(std::io::BufReader::new(file)).map_err(|e| persistence(&e))?;
+        Ok(Vm::from_snapshot(snapshot))
+    }
+    pub fn reset(&mut self) {
+        self.memory.load(&self.program);
+        self.decode_cache.clear();
+        self.pc = 0;
+        self.op_pc = 0;
+        self.rb = 0;
+        self.state = VmState::Running;
+        self.inputs.clear();
+        self.outputs.clear();
+        self.history.clear();
+        self.journal.clear();
+        self.steps = 0;
+        self.inputs_consumed = 0;
+        self.outputs_produced = 0;
+        if self.profiling {
+            self.profile = Profile::new(self.memory.len(), self.program.len());
+        }
+        self.break_pc = None;
+        self.watch_hit = None;
+    }
+    pub fn reset_with_patches(&mut self, patches: &[(i64, W)]) -> Result<()> {
+        self.reset();
+        for &(addr, val) in patches {
+            self.write_at(addr, val)?;
         }
+        Ok(())
     }
     pub fn step(&mut self) -> Result<VmState> {
+        if let Event::Output(o) = self.step_event()? {
+            self.emit_output(o);
+        }
+        Ok(self.state)
+    }
+    pub fn step_event(&mut self) -> Result<Event<W>> {
+        let event = self.execute();
+        // logs are flushed whenever the program stops, fails or waits for the user
+        if !matches!(event, Ok(Event::Continue) | Ok(Event::Output(_))) {
+            self.flush_io_logs();
+        }
+        event
+    }
+    fn execute(&mut self) -> Result<Event<W>> {
         debug!("[{}] stepping", self.pc);
+        let state = self.state;
         match self.state {
             VmState::Stopped => {
-                return Err(VMError::Stopped);
+                return Err(VMError::Stopped { pc: self.pc, rb: self.rb });
             },
             VmState::WaitingForInput => {
-                if self.inputs.is_empty() {
-                    return Ok(VmState::WaitingForInput);
+                if self.inputs.is_empty() && self.input_source.is_none() {
+                    return Ok(Event::NeedsInput);
                 }
                 self.state = VmState::Running;
             },
+            VmState::Paused => {
+                return Err(VMError::Paused { pc: self.pc });
+            },
             VmState::Running => {},
         }
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(VMError::StepLimitExceeded { steps: self.steps, pc: self.pc });
+            }
+        }
+        let pc = self.pc;
+        self.op_pc = pc;
         let op = self.read_opcode()?;
         debug!("[{}] executing {:?}", self.pc, op);
-        match op {
+        let rb = self.rb;
+        let mut consumed = None;
+        let (args, write, event) = match op {
             Opcode::Add(par1, par2, par3) => {
-                let arg1 = *self.fetch_param(par1)?;
-                let arg2 = *self.fetch_param(par2)?;
-                let arg3 = self.fetch_param(par3)?;
-                *arg3 = arg1 + arg2;
+                let arg1 = self.fetch_param(par1)?;
+                let arg2 = self.fetch_param(par2)?;
+                let dst = self.param_addr(par3)?;
+                let val = self.checked("add", arg1, arg2, W::checked_add)?;
+                ([arg1, arg2, W::from_i64(dst)], Some((dst, val)), Event::Continue)
             },
             Opcode::Mul(par1, par2, par3) => {
-                let arg1 = *self.fetch_param(par1)?;
-                let arg2 = *self.fetch_param(par2)?;
-                let arg3 = self.fetch_param(par3)?;
-                *arg3 = arg1 * arg2;
+                let arg1 = self.fetch_param(par1)?;
+                let arg2 = self.fetch_param(par2)?;
+                let dst = self.param_addr(par3)?;
+                let val = self.checked("mul", arg1, arg2, W::checked_mul)?;
+                ([arg1, arg2, W::from_i64(dst)], Some((dst, val)), Event::Continue)
             },
             Opcode::Input(par1) => {
-                if let Some(i) = self.inputs.pop_front() {
-                    let arg1 = self.fetch_param(par1)?;
-                    *arg1 = i;
+                // a bad destination fails before an input is taken and lost
+                let dst = self.param_addr(par1)?;
+                if let Some(i) = self.next_input() {
+                    consumed = Some(i);
+                    self.inputs_consumed += 1;
+                    self.record_io_event("in", i);
+                    ([W::from_i64(dst), W::default(), W::default()], Some((dst, i)), Event::Continue)
                 } else {
-                    self.pc -= 1;
+                    self.pc -= 2;
                     self.state = VmState::WaitingForInput;
+                    return Ok(Event::NeedsInput);
                 }
             },
             Opcode::Output(par1) => {
-                let arg1 = *self.fetch_param(par1)?;
-                self.outputs.push_back(arg1);
+                let arg1 = self.fetch_param(par1)?;
+                self.outputs_produced += 1;
+                self.record_io_event("out", arg1);
+                ([arg1, W::default(), W::default()], None, Event::Output(arg1))
             },
             Opcode::JumpIfTrue(par1, par2) => {
-                let arg1 = *self.fetch_param(par1)?;
+                let arg1 = self.fetch_param(par1)?;
                 let arg2 = self.fetch_param(par2)?;
-                if arg1 != 0 {
-                    self.pc = *arg2;
+                if arg1 != W::default() {
+                    self.pc = self.jump_target(arg2)?;
                 }
+                ([arg1, arg2, W::default()], None, Event::Continue)
             },
             Opcode::JumpIfFalse(par1, par2) => {
-                let arg1 = *self.fetch_param(par1)?;
+                let arg1 = self.fetch_param(par1)?;
                 let arg2 = self.fetch_param(par2)?;
-                if arg1 == 0 {
-                    self.pc = *arg2;
+                if arg1 == W::default() {
+                    self.pc = self.jump_target(arg2)?;
                 }
+                ([arg1, arg2, W::default()], None, Event::Continue)
             },
             Opcode::LessThan(par1, par2, par3) => {
-                let arg1 = *self.fetch_param(par1)?;
-                let arg2 = *self.fetch_param(par2)?;
-                let arg3 = self.fetch_param(par3)?;
-                *arg3 = (arg1 < arg2) as i64;
+                let arg1 = self.fetch_param(par1)?;
+                let arg2 = self.fetch_param(par2)?;
+                let dst = self.param_addr(par3)?;
+                ([arg1, arg2, W::from_i64(dst)], Some((dst, W::from_i64((arg1 < arg2) as i64))), Event::Continue)
             },
             Opcode::Equals(par1, par2, par3) => {
-                let arg1 = *self.fetch_param(par1)?;
-                let arg2 = *self.fetch_param(par2)?;
-                let arg3 = self.fetch_param(par3)?;
-                *arg3 = (arg1 == arg2) as i64;
+                let arg1 = self.fetch_param(par1)?;
+                let arg2 = self.fetch_param(par2)?;
+                let dst = self.param_addr(par3)?;
+                ([arg1, arg2, W::from_i64(dst)], Some((dst, W::from_i64((arg1 == arg2) as i64))), Event::Continue)
             },
             Opcode::RelativeBaseOffset(par1) => {
-                let arg1 = *self.fetch_param(par1)?;
-                self.rb += arg1;
+                let arg1 = self.fetch_param(par1)?;
+                let rb = self.checked("relative base offset", W::from_i64(self.rb), arg1, W::checked_add)?;
+                self.rb = rb.to_i64().ok_or(VMError::ArithmeticOverflow {
+                    pc: self.op_pc,
+                    lhs: self.rb,
+                    rhs: arg1.saturating_i64(),
+                    op: "relative base offset",
+                })?;
+                ([arg1, W::default(), W::default()], None, Event::Continue)
             },
             Opcode::End => {
                 self.state = VmState::Stopped;
+                ([W::default(); 3], None, Event::Halted)
             }
         };
-        Ok(self.state)
-}
-    pub fn run(&mut self) -> Result<()> {
-        loop {
-            match self.step()? {
-                VmState::Running => {},
-                VmState::Stopped => {return Ok(());},
-                VmState::WaitingForInput => {return Err(VMError::NoMoreInput);},
+        if self.trace_hook.is_some() {
+            let old = match write {
+                Some((dst, _)) => Some(self.peek_at(dst)?),
+                None => None,
+            };
+            let inputs = op.arity() - write.is_some() as usize;
+            let event = TraceEvent {
+                pc,
+                op,
+                args: args[..inputs].to_vec(),
+                dst: write.map(|(dst, _)| dst),
+                old,
+                rb,
+            };
+            if let Some(hook) = &mut self.trace_hook {
+                hook(event);
+            }
+        }
+        let old = match write {
+            Some((dst, val)) => Some((dst, self.store(dst, val, pc)?)),
+            None => None,
+        };
+        self.steps += 1;
+        if self.profiling {
+            self.profile.record(pc, op, self.memory.len());
+        }
+        if self.history_len > 0 {
+            if self.history.len() == self.history_len {
+                self.history.pop_front();
+            }
+            self.history.push_back(Executed { pc, op, args });
+        }
+        if self.journal_capacity > 0 {
+            if self.journal.len() == self.journal_capacity {
+                self.journal.pop_front();
+            }
+            let output = matches!(event, Event::Output(_));
+            self.journal.push_back(JournalEntry { pc, rb, state, old, input: consumed, output, queued_output: false });
+        }
+        Ok(event)
+    }
+    pub fn enable_rewind(&mut self, capacity: usize) {
+        self.journal_capacity = capacity;
+        while self.journal.len() > capacity {
+            self.journal.pop_front();
+        }
+    }
+    pub fn disable_rewind(&mut self) {
+        self.enable_rewind(0);
+    }
+    pub fn step_back(&mut self) -> Result<()> {
+        let entry = self.journal.pop_back().ok_or(VMError::NothingToRewind { pc: self.pc })?;
+        if let Some((addr, old)) = entry.old {
+            *self.access(addr)? = old;
+            if let Some(op) = self.decode_cache.get_mut(addr as usize) {
+                *op = None;
+            }
+        }
+        if let Some(i) = entry.input {
+            self.inputs.push_front(i);
+            self.inputs_consumed -= 1;
+        }
+        if entry.output {
+            self.outputs_produced -= 1;
+        }
+        if entry.queued_output {
+            self.outputs.pop_back();
+        }
+        self.pc = entry.pc;
+        self.op_pc = entry.pc;
+        self.rb = entry.rb;
+        self.state = entry.state;
+        self.steps -= 1;
+        if self.history_len > 0 {
+            self.history.pop_back();
+        }
+        self.break_pc = None;
+        self.watch_hit = None;
+        Ok(())
+    }
+    fn fast_path_enabled(&self) -> bool {
+        self.state == VmState::Running
+            && self.step_limit.is_none()
+            && self.history_len == 0
+            && self.journal_capacity == 0
+            && self.trace_hook.is_none()
+            && !self.profiling
+            && self.watchpoints.is_empty()
+    }
+    // Executes instructions that only touch already allocated memory and can't fail,
+    // stopping before anything else (I/O, growth, errors) so that step_event handles it.
+    fn run_fast_steps(&mut self, until: u64) {
+        fn addr<W: IntcodeWord>(mem: &[W], at: usize, mode: ParameterMode, rb: i64) -> Option<usize> {
+            let a = match mode {
+                ParameterMode::Immediate => return Some(at).filter(|&a| a < mem.len()),
+                ParameterMode::Position => *mem.get(at)?,
+                ParameterMode::Relative => mem.get(at)?.checked_add(W::from_i64(rb))?,
+            };
+            a.to_usize().filter(|&a| a < mem.len())
+        }
+        // A read-only image refuses writes, so that the loop stops before the first
+        // one and an image shared with other VMs is only copied if it is written.
+        trait Cells<W> {
+            fn cells(&self) -> &[W];
+            fn set(&mut self, idx: usize, val: W) -> bool;
+        }
+        impl<W> Cells<W> for &[W] {
+            fn cells(&self) -> &[W] {
+                self
+            }
+            fn set(&mut self, _: usize, _: W) -> bool {
+                false
+            }
+        }
+        impl<W> Cells<W> for &mut [W] {
+            fn cells(&self) -> &[W] {
+                self
+            }
+            fn set(&mut self, idx: usize, val: W) -> bool {
+                self[idx] = val;
+                true
+            }
+        }
+        // Returns whether it stopped at a write that `mem` refused.
+        fn run<W: IntcodeWord, M: Cells<W>>(
+            mut mem: M,
+            cache: &mut [Option<Opcode>],
+            regs: &mut (i64, i64, u64),
+            until: u64,
+            strict: bool,
+        ) -> bool {
+            let (mut pc, mut rb, mut steps) = *regs;
+            let mut stopped_at_write = false;
+            while steps < until {
+                let cells = mem.cells();
+                let at = match usize::try_from(pc) {
+                    Ok(at) if at < cells.len() => at,
+                    _ => break,
+                };
+                let op = match cache.get(at).copied().flatten() {
+                    Some(op) => op,
+                    None => match cells[at].to_i64().map(|word| Opcode::decode_at(word, pc, rb, strict)) {
+                        Some(Ok(op)) => {
+                            if let Some(slot) = cache.get_mut(at) {
+                                *slot = Some(op);
+                            }
+                            op
+                        },
+                        _ => break,
+                    },
+                };
+                let arith = |m1, m2, m3, f: fn(W, W) -> Option<W>| {
+                    let a1 = addr(cells, at + 1, m1, rb)?;
+                    let a2 = addr(cells, at + 2, m2, rb)?;
+                    let dst = addr(cells, at + 3, m3, rb)?;
+                    Some((dst, f(cells[a1], cells[a2])?))
+                };
+                let write = match op {
+                    Opcode::Add(m1, m2, m3) => arith(m1, m2, m3, W::checked_add),
+                    Opcode::Mul(m1, m2, m3) => arith(m1, m2, m3, W::checked_mul),
+                    Opcode::LessThan(m1, m2, m3) => arith(m1, m2, m3, |a, b| Some(W::from_i64((a < b) as i64))),
+                    Opcode::Equals(m1, m2, m3) => arith(m1, m2, m3, |a, b| Some(W::from_i64((a == b) as i64))),
+                    Opcode::JumpIfTrue(m1, m2) | Opcode::JumpIfFalse(m1, m2) => {
+                        let (a1, a2) = match (addr(cells, at + 1, m1, rb), addr(cells, at + 2, m2, rb)) {
+                            (Some(a1), Some(a2)) => (a1, a2),
+                            _ => break,
+                        };
+                        let jump = (cells[a1] != W::default()) == matches!(op, Opcode::JumpIfTrue(..));
+                        pc = match (jump, cells[a2].to_i64()) {
+                            (false, _) => pc + 3,
+                            (true, Some(target)) => target,
+                            (true, None) => break,
+                        };
+                        steps += 1;
+                        continue;
+                    },
+                    Opcode::RelativeBaseOffset(m1) => {
+                        match addr(cells, at + 1, m1, rb).and_then(|a1| W::from_i64(rb).checked_add(cells[a1])?.to_i64()) {
+                            Some(new_rb) => rb = new_rb,
+                            None => break,
+                        }
+                        pc += 2;
+                        steps += 1;
+                        continue;
+                    },
+                    Opcode::Input(..) | Opcode::Output(..) | Opcode::End => break,
+                };
+                match write {
+                    Some((dst, val)) => {
+                        if !mem.set(dst, val) {
+                            stopped_at_write = true;
+                            break;
+                        }
+                        if let Some(slot) = cache.get_mut(dst) {
+                            *slot = None;
+                        }
+                    },
+                    None => break,
+                }
+                pc += 4;
+                steps += 1;
             }
+            *regs = (pc, rb, steps);
+            stopped_at_write
         }
+        let mut regs = (self.pc, self.rb, self.steps);
+        let strict = self.strict_decoding;
+        let cached_len = self.cached_len();
+        let cache = Self::sized_decode_cache(&mut self.decode_cache, cached_len);
+        if run(self.memory.dense(), cache, &mut regs, until, strict) {
+            run(self.memory.dense_mut(), cache, &mut regs, until, strict);
+        }
+        let (pc, rb, steps) = regs;
+        self.pc = pc;
+        self.op_pc = pc;
+        self.rb = rb;
+        self.steps = steps;
     }
-    pub fn run_until_output(&mut self) -> Result<Option<i64>> {
+    fn run_fast(&mut self) -> Result<Event<W>> {
         loop {
-            if let Some(o) = self.outputs.pop_front() {
-                return Ok(Some(o));
+            if self.fast_path_enabled() {
+                self.run_fast_steps(u64::MAX);
+            }
+            match self.step_event()? {
+                Event::Continue => {},
+                event => {return Ok(event);},
             }
-            match self.step()? {
-                VmState::Running => {},
-                VmState::Stopped => {return Ok(None);},
-                VmState::WaitingForInput => {return Err(VMError::NoMoreInput);},
+        }
+    }
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            match self.run_fast()? {
+                Event::Continue => {},
+                Event::Output(o) => self.emit_output(o),
+                Event::Halted => {return Ok(());},
+                Event::NeedsInput => {return Err(VMError::NoMoreInput { pc: self.pc, rb: self.rb });},
             }
         }
     }
-    pub fn run_until_output_with_input<F: FnMut() -> i64>(&mut self, mut f: F) -> Result<Option<i64>> {
+    #[cfg(feature = "std")]
+    pub fn run_with_timeout(&mut self, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
         loop {
-            if let Some(o) = self.outputs.pop_front() {
-                return Ok(Some(o));
+            // the clock is only read every few thousand instructions
+            let until = self.steps.saturating_add(self.timeout_check_interval);
+            while self.steps < until {
+                if self.fast_path_enabled() {
+                    self.run_fast_steps(until);
+                    if self.steps >= until {
+                        break;
+                    }
+                }
+                match self.step_event()? {
+                    Event::Continue => {},
+                    Event::Output(o) => self.emit_output(o),
+                    Event::Halted => {return Ok(());},
+                    Event::NeedsInput => {return Err(VMError::NoMoreInput { pc: self.pc, rb: self.rb });},
+                }
             }
-            match self.step()? {
-                VmState::Running => {},
-                VmState::Stopped => {return Ok(None);},
-                VmState::WaitingForInput => {self.add_inputs(&[f()])},
+            let elapsed = start.elapsed();
+            if elapsed > timeout {
+                return Err(VMError::Timeout { elapsed, steps: self.steps });
             }
         }
     }
-    pub fn read_at(&mut self, addr: i64) -> Result<i64> {
-        let a = self.access(addr)?;
-        Ok(*a)
+    pub fn set_timeout_check_interval(&mut self, steps: u64) {
+        self.timeout_check_interval = steps.max(1);
     }
-    pub fn write_at(&mut self, addr: i64, val: i64) -> Result<()> {
-        let a = self.access(addr)?;
-        *a = val;
-        Ok(())
+    // Like run, but stopping without an error when input is needed.
+    pub fn run_with_summary(&mut self) -> Result<RunSummary> {
+        self.run_until_blocked()?;
+        Ok(self.summary())
     }
-
-    pub fn is_running(&self) -> bool {
-        self.state != VmState::Stopped
+    pub fn summary(&self) -> RunSummary {
+        RunSummary {
+            steps: self.steps,
+            inputs_consumed: self.inputs_consumed,
+            outputs_produced: self.outputs_produced,
+            // memory only grows until the next reset, so its size is the peak
+            peak_memory_cells: self.memory.len(),
+            halted: self.state == VmState::Stopped,
+        }
     }
-
-    pub fn get_outputs(&mut self) -> impl Iterator<Item=i64> + '_ {
-        self.outputs.drain(..)
+    pub fn run_until_input_needed(&mut self) -> Result<(Vec<W>, VmState)> {
+        self.run_until_blocked()?;
+        Ok((self.get_outputs().collect(), self.state))
     }
-
-    pub fn add_inputs(&mut self, inputs: &[i64]) {
-        for i in inputs {
-            self.inputs.push_back(*i);
+    fn run_until_blocked(&mut self) -> Result<()> {
+        if self.state != VmState::Stopped && self.state != VmState::Paused {
+            loop {
+                match self.run_fast()? {
+                    Event::Continue => {},
+                    Event::Output(o) => self.emit_output(o),
+                    Event::NeedsInput | Event::Halted => break,
+                }
+            }
         }
+        Ok(())
     }
-
-    fn access(&mut self, addr: i64) -> Result<&mut i64> {
-        debug!("[{}] accessing [{}]", self.pc, addr);
-        let idx = usize::try_from(addr).map_err(|_| VMError::InvalidAddress{addr: addr})?;
-        if self.memory.len() <= idx {
-            self.memory.resize(idx+1, 0);
+    pub fn run_until_breakpoint(&mut self) -> Result<BreakReason<W>> {
+        loop {
+            match self.state {
+                VmState::Stopped => {return Ok(BreakReason::Halted);},
+                VmState::Paused => {return Err(VMError::Paused { pc: self.pc });},
+                VmState::Running | VmState::WaitingForInput => {},
+            }
+            if let Some(hit) = self.watch_hit.take() {
+                return Ok(hit);
+            }
+            // after reporting a breakpoint the next run steps over it
+            if self.breakpoints.contains(&self.pc) && self.break_pc != Some(self.pc) {
+                self.break_pc = Some(self.pc);
+                return Ok(BreakReason::Breakpoint(self.pc));
+            }
+            self.break_pc = None;
+            match self.step_event()? {
+                Event::Continue => {},
+                Event::Output(o) => self.emit_output(o),
+                Event::Halted => {return Ok(BreakReason::Halted);},
+                Event::NeedsInput => {
+                    self.break_pc = Some(self.pc);
+                    return Ok(BreakReason::NeedsInput);
+                },
+            }
         }
-        Ok(&mut self.memory[idx])
     }
-    fn decode_mode(opcode: i64, param: u32) -> Option<ParameterMode> {
-        match (opcode / (10i64.pow(param+2))) % 10 {
-            0 => { Some(ParameterMode::Position) },
-            1 => { Some(ParameterMode::Immediate) },
-            2 => { Some(ParameterMode::Relative) },
-            _ => { None },
+    pub fn pause(&mut self) {
+        if self.state != VmState::Stopped {
+            self.state = VmState::Paused;
         }
     }
-    fn read_opcode(&mut self) -> Result<Opcode> {
-        debug!("[{}] reading opcode",self.pc);
-        let i = *self.access(self.pc)?;
-        self.pc += 1;
-        Ok(match i % 100 {
-            1  => {
-                let mode1 = Self::decode_mode(i, 0).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode2 = Self::decode_mode(i, 1).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode3 = Self::decode_mode(i, 2).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                Opcode::Add(mode1, mode2, mode3)
-            },
-            2  => {
-                let mode1 = Self::decode_mode(i, 0).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode2 = Self::decode_mode(i, 1).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode3 = Self::decode_mode(i, 2).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                Opcode::Mul(mode1, mode2, mode3)
-            },
-            3  => {
-                let mode1 = Self::decode_mode(i, 0).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                Opcode::Input(mode1)
-            },
-            4  => {
-                let mode1 = Self::decode_mode(i, 0).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                Opcode::Output(mode1)
-            },
-            5  => {
-                let mode1 = Self::decode_mode(i, 0).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode2 = Self::decode_mode(i, 1).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                Opcode::JumpIfTrue(mode1, mode2)
+    pub fn resume(&mut self) {
+        if self.state == VmState::Paused {
+            self.state = VmState::Running;
+        }
+    }
+    pub fn run_collect(&mut self) -> Result<Vec<W>> {
+        self.run()?;
+        Ok(self.get_outputs().collect())
+    }
+    pub fn run_with_inputs(&mut self, inputs: &[W]) -> Result<Vec<W>> {
+        self.add_inputs(inputs);
+        self.run_collect()
+    }
+    pub fn run_until_output(&mut self) -> Result<Option<W>> {
+        if let Some(o) = self.outputs.pop_front() {
+            return Ok(Some(o));
+        }
+        loop {
+            match self.run_fast()? {
+                Event::Continue => {},
+                Event::Output(o) => {return Ok(Some(o));},
+                Event::Halted => {return Ok(None);},
+                Event::NeedsInput => {return Err(VMError::NoMoreInput { pc: self.pc, rb: self.rb });},
+            }
+        }
+    }
+    pub fn run_until_output_matching<P: FnMut(W) -> bool>(&mut self, mut pred: P) -> Result<Option<W>> {
+        if let Some(idx) = self.outputs.iter().position(|&o| pred(o)) {
+            return Ok(self.outputs.remove(idx));
+        }
+        loop {
+            match self.run_fast()? {
+                Event::Continue => {},
+                Event::Output(o) if pred(o) => {return Ok(Some(o));},
+                Event::Output(o) => self.emit_output(o),
+                Event::Halted => {return Ok(None);},
+                Event::NeedsInput => {return Err(VMError::NoMoreInput { pc: self.pc, rb: self.rb });},
+            }
+        }
+    }
+    pub fn run_until_output_with_input<F: FnMut() -> W>(&mut self, mut f: F) -> Result<Option<W>> {
+        if let Some(o) = self.outputs.pop_front() {
+            return Ok(Some(o));
+        }
+        loop {
+            match self.run_fast()? {
+                Event::Continue => {},
+                Event::Output(o) => {return Ok(Some(o));},
+                Event::Halted => {return Ok(None);},
+                Event::NeedsInput => {self.add_inputs(&[f()])},
+            }
+        }
+    }
+    pub fn run_until_n_outputs<const N: usize>(&mut self) -> Result<Option<[W; N]>> {
+        self.run_until_n_outputs_with(Self::run_until_output)
+    }
+    pub fn run_until_n_outputs_with_input<const N: usize, F: FnMut() -> W>(&mut self, mut f: F) -> Result<Option<[W; N]>> {
+        self.run_until_n_outputs_with(|vm| vm.run_until_output_with_input(&mut f))
+    }
+    fn run_until_n_outputs_with<const N: usize, F>(&mut self, next: F) -> Result<Option<[W; N]>>
+    where
+        F: FnMut(&mut Vm<W>) -> Result<Option<W>>,
+    {
+        let mut record = [W::default(); N];
+        Ok(if self.fill_outputs(&mut record, next)? { Some(record) } else { None })
+    }
+    fn fill_outputs<F>(&mut self, record: &mut [W], mut next: F) -> Result<bool>
+    where
+        F: FnMut(&mut Vm<W>) -> Result<Option<W>>,
+    {
+        let expected = record.len();
+        for got in 0..expected {
+            let error = match next(self) {
+                Ok(Some(v)) => {
+                    record[got] = v;
+                    continue;
+                },
+                Ok(None) if got == 0 => return Ok(false),
+                Ok(None) => VMError::TruncatedOutput { expected, got },
+                Err(e) => e,
+            };
+            // the caller only sees the error, keep what was read for get_outputs
+            self.unread_outputs(record[..got].iter().copied());
+            return Err(error);
+        }
+        Ok(true)
+    }
+    fn unread_outputs<I: DoubleEndedIterator<Item=W>>(&mut self, values: I) {
+        for v in values.rev() {
+            self.outputs.push_front(v);
+        }
+    }
+    /// ```
+    /// let mut vm: intcode::Vm = "104,1,104,2,99".parse().unwrap();
+    /// let outputs: Result<Vec<_>, _> = vm.outputs_iter().collect();
+    /// assert_eq!(outputs.unwrap(), vec![1, 2]);
+    /// ```
+    pub fn outputs_iter(&mut self) -> Outputs<'_, W> {
+        Outputs { vm: self, done: false }
+    }
+    /// ```
+    /// let mut vm: intcode::Vm = "104,1,104,2,104,3,99".parse().unwrap();
+    /// for tile in vm.output_chunks(3) {
+    ///     assert_eq!(tile.unwrap(), vec![1, 2, 3]);
+    /// }
+    /// ```
+    pub fn output_chunks(&mut self, size: usize) -> OutputChunks<'_, W> {
+        OutputChunks { vm: self, size, done: false }
+    }
+    pub fn run_until_ascii_line(&mut self) -> Result<Option<String>> {
+        let mut line = String::new();
+        loop {
+            let error = match self.run_until_output() {
+                Ok(Some(o)) => match ascii_char(o) {
+                    Some('\n') => return Ok(Some(line)),
+                    Some(c) => {
+                        line.push(c);
+                        continue;
+                    },
+                    None => {
+                        self.outputs.push_front(o);
+                        VMError::NonAscii { value: o.saturating_i64() }
+                    },
+                },
+                Ok(None) => return Ok(if line.is_empty() { None } else { Some(line) }),
+                Err(e) => e,
+            };
+            self.unread_outputs(line.bytes().map(|b| W::from_i64(i64::from(b))));
+            return Err(error);
+        }
+    }
+    #[cfg(feature = "std")]
+    pub fn run_interactive<R: BufRead, O: Write>(&mut self, mut reader: R, mut writer: O) -> Result<()> {
+        let mut line_start = true;
+        let queued: Vec<_> = self.outputs.drain(..).collect();
+        for o in queued {
+            Self::write_interactive(&mut writer, o, &mut line_start)?;
+        }
+        loop {
+            match self.run_fast()? {
+                Event::Continue => {},
+                Event::Output(o) => Self::write_interactive(&mut writer, o, &mut line_start)?,
+                Event::Halted => {
+                    writer.flush()?;
+                    return Ok(());
+                },
+                Event::NeedsInput => {
+                    writer.flush()?;
+                    let mut line = String::new();
+                    if reader.read_line(&mut line)? == 0 {
+                        return Err(VMError::NoMoreInput { pc: self.pc, rb: self.rb });
+                    }
+                    self.add_ascii_input(line.trim_end_matches(&['\r', '\n'][..]))?;
+                },
+            }
+        }
+    }
+    #[cfg(feature = "std")]
+    fn write_interactive<O: Write>(writer: &mut O, value: W, line_start: &mut bool) -> Result<()> {
+        match ascii_char(value) {
+            Some('\n') => {
+                writeln!(writer)?;
+                writer.flush()?;
+                *line_start = true;
             },
-            6  => {
-                let mode1 = Self::decode_mode(i, 0).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode2 = Self::decode_mode(i, 1).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                Opcode::JumpIfFalse(mode1, mode2)
+            Some(c) => {
+                write!(writer, "{}", c)?;
+                *line_start = false;
             },
-            7  => {
-                let mode1 = Self::decode_mode(i, 0).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode2 = Self::decode_mode(i, 1).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode3 = Self::decode_mode(i, 2).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                Opcode::LessThan(mode1, mode2, mode3)
+            None => {
+                if !*line_start {
+                    writeln!(writer)?;
+                }
+                writeln!(writer, "{}", value)?;
+                *line_start = true;
             },
-            8  => {
-                let mode1 = Self::decode_mode(i, 0).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode2 = Self::decode_mode(i, 1).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                let mode3 = Self::decode_mode(i, 2).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                Opcode::Equals(mode1, mode2, mode3)
+        }
+        Ok(())
+    }
+    pub fn read_at(&self, addr: i64) -> Result<W> {
+        self.peek_at(addr)
+    }
+    pub fn peek_at(&self, addr: i64) -> Result<W> {
+        let idx = usize::try_from(addr).map_err(|_| VMError::InvalidAddress{addr, pc: self.pc, rb: self.rb})?;
+        Ok(self.memory.get(idx))
+    }
+    pub fn write_at(&mut self, addr: i64, val: W) -> Result<()> {
+        self.store(addr, val, self.pc).map(drop)
+    }
+
+    pub fn pc(&self) -> i64 {
+        self.pc
+    }
+    pub fn set_pc(&mut self, pc: i64) {
+        self.pc = pc;
+    }
+    pub fn relative_base(&self) -> i64 {
+        self.rb
+    }
+    pub fn set_relative_base(&mut self, rb: i64) {
+        self.rb = rb;
+    }
+    pub fn state(&self) -> VmState {
+        self.state
+    }
+
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+    pub fn steps_executed(&self) -> u64 {
+        self.steps
+    }
+    pub fn inputs_consumed(&self) -> usize {
+        self.inputs_consumed
+    }
+    pub fn outputs_produced(&self) -> usize {
+        self.outputs_produced
+    }
+    pub fn add_breakpoint(&mut self, addr: i64) {
+        self.breakpoints.insert(addr);
+    }
+    pub fn remove_breakpoint(&mut self, addr: i64) {
+        self.breakpoints.remove(&addr);
+    }
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+    pub fn add_watchpoint(&mut self, addr: i64) {
+        self.watchpoints.insert(addr);
+    }
+    pub fn remove_watchpoint(&mut self, addr: i64) {
+        self.watchpoints.remove(&addr);
+    }
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+    pub fn set_trace_hook(&mut self, hook: TraceHook<W>) {
+        self.trace_hook = Some(hook);
+    }
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+    #[cfg(feature = "std")]
+    pub fn record_io<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.recorder = Some(Box::new(std::io::BufWriter::new(file)));
+        Ok(())
+    }
+    #[cfg(feature = "std")]
+    pub fn stop_recording(&mut self) -> Result<()> {
+        if let Some(mut recorder) = self.recorder.take() {
+            recorder.flush()?;
+        }
+        Ok(())
+    }
+    #[cfg(feature = "std")]
+    pub fn set_transcript<T: Write + Send + 'static>(&mut self, writer: T) {
+        self.transcript = Some(Transcript::new(Box::new(writer)));
+    }
+    #[cfg(feature = "std")]
+    pub fn clear_transcript(&mut self) {
+        if let Some(mut transcript) = self.transcript.take() {
+            transcript.flush();
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    fn record_io_event(&mut self, _tag: &str, _value: W) {}
+    #[cfg(feature = "std")]
+    fn record_io_event(&mut self, tag: &str, value: W) {
+        if let Some(recorder) = &mut self.recorder {
+            // a failing recorder must not stop the program, the gap shows up on replay
+            let _ = writeln!(recorder, "{} {}", tag, value);
+        }
+        if let Some(transcript) = &mut self.transcript {
+            match tag {
+                "in" => transcript.input(value),
+                _ => transcript.output(value),
+            }
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    fn flush_io_logs(&mut self) {}
+    #[cfg(feature = "std")]
+    fn flush_io_logs(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            let _ = recorder.flush();
+        }
+        if let Some(transcript) = &mut self.transcript {
+            transcript.flush();
+        }
+    }
+    pub fn enable_profiling(&mut self) {
+        self.profiling = true;
+        self.profile = Profile::new(self.memory.len(), self.program.len());
+    }
+    pub fn disable_profiling(&mut self) {
+        self.profiling = false;
+    }
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+    pub fn coverage(&self) -> Coverage {
+        self.profile.coverage()
+    }
+    pub fn set_history_len(&mut self, len: usize) {
+        self.history_len = len;
+        while self.history.len() > len {
+            self.history.pop_front();
+        }
+    }
+    pub fn context(&self) -> Context<W> {
+        Context {
+            pc: self.pc,
+            rb: self.rb,
+            state: self.state,
+            history: self.history.iter().copied().collect(),
+        }
+    }
+
+    pub fn set_strict_decoding(&mut self, strict: bool) {
+        self.strict_decoding = strict;
+        // cached instructions were decoded under the old rules
+        self.decode_cache.clear();
+    }
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_caching = enabled;
+        self.decode_cache.clear();
+    }
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+    pub fn memory_len(&self) -> usize {
+        self.memory.len()
+    }
+    pub fn dump_memory(&self, range: Range<i64>) -> Vec<W> {
+        range.filter_map(|addr| usize::try_from(addr).ok()).map(|idx| self.memory.get(idx)).collect()
+    }
+    pub fn diff_memory(&self, other: &Vm<W>) -> Vec<(i64, W, W)> {
+        let cells: BTreeSet<usize> = self.memory.nonzero().chain(other.memory.nonzero()).map(|(idx, _)| idx).collect();
+        cells.into_iter()
+            .map(|idx| (idx as i64, self.memory.get(idx), other.memory.get(idx)))
+            .filter(|(_, mine, theirs)| mine != theirs)
+            .collect()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state != VmState::Stopped
+    }
+
+    pub fn get_outputs(&mut self) -> impl Iterator<Item=W> + '_ {
+        self.outputs.drain(..)
+    }
+
+    pub fn add_inputs(&mut self, inputs: &[W]) {
+        for i in inputs {
+            self.inputs.push_back(*i);
+        }
+    }
+    pub fn pending_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+    pub fn clear_inputs(&mut self) {
+        self.inputs.clear();
+    }
+    pub fn pending_outputs(&self) -> usize {
+        self.outputs.len()
+    }
+    pub fn peek_output(&self) -> Option<W> {
+        self.outputs.front().copied()
+    }
+    pub fn is_idle(&self) -> bool {
+        self.state == VmState::WaitingForInput && self.inputs.is_empty()
+    }
+
+    pub fn add_ascii_input(&mut self, s: &str) -> Result<()> {
+        if let Some(c) = s.chars().find(|c| !c.is_ascii()) {
+            return Err(VMError::NonAscii { value: c as i64 });
+        }
+        self.inputs.extend(s.bytes().map(|b| W::from_i64(i64::from(b))));
+        if !s.ends_with('\n') {
+            self.inputs.push_back(W::from_i64(i64::from(b'\n')));
+        }
+        Ok(())
+    }
+    pub fn add_ascii_script(&mut self, script: &str) -> Result<()> {
+        if let Some(c) = script.chars().find(|c| !c.is_ascii()) {
+            return Err(VMError::NonAscii { value: c as i64 });
+        }
+        // a transcript replays the commands that were typed, a plain script every non-empty line
+        let transcript = script.lines().any(|line| line.starts_with("> "));
+        for line in script.lines().map(|line| line.trim_end_matches('\r')) {
+            let command = match (transcript, line.strip_prefix("> ")) {
+                (true, Some(command)) => command,
+                (false, _) if !line.trim().is_empty() => line,
+                _ => continue,
+            };
+            self.add_ascii_input(command)?;
+        }
+        Ok(())
+    }
+    pub fn drain_ascii_output(&mut self) -> String {
+        let mut text = String::new();
+        let mut others = VecDeque::new();
+        for o in self.outputs.drain(..) {
+            match ascii_char(o) {
+                Some(c) => text.push(c),
+                None => others.push_back(o),
+            }
+        }
+        self.outputs = others;
+        text
+    }
+
+    pub fn set_input_source<S: InputSource<W> + Send + 'static>(&mut self, source: S) {
+        self.input_source = Some(Box::new(source));
+    }
+    pub fn clear_input_source(&mut self) {
+        self.input_source = None;
+    }
+
+    pub fn set_output_sink<S: OutputSink<W> + Send + 'static>(&mut self, sink: S) {
+        self.output_sink = Some(Box::new(sink));
+    }
+    pub fn clear_output_sink(&mut self) {
+        self.output_sink = None;
+    }
+
+    fn emit_output(&mut self, value: W) {
+        match &mut self.output_sink {
+            Some(sink) => sink.push_output(value),
+            None => {
+                self.outputs.push_back(value);
+                if let Some(entry) = self.journal.back_mut() {
+                    entry.queued_output = true;
+                }
             },
-            9  => {
-                let mode1 = Self::decode_mode(i, 0).ok_or_else(|| VMError::InvalidOpcode { opcode: i, addr: self.pc-1 })?;
-                Opcode::RelativeBaseOffset(mode1)
+        }
+    }
+    fn next_input(&mut self) -> Option<W> {
+        if let Some(i) = self.input_source.as_mut().and_then(|s| s.next_input()) {
+            return Some(i);
+        }
+        self.inputs.pop_front()
+    }
+
+    fn index(&self, addr: i64) -> Result<usize> {
+        usize::try_from(addr).map_err(|_| VMError::InvalidAddress{addr, pc: self.op_pc, rb: self.rb})
+    }
+    fn load(&self, addr: i64) -> Result<W> {
+        debug!("[{}] reading [{}]", self.pc, addr);
+        Ok(self.memory.get(self.index(addr)?))
+    }
+    fn access(&mut self, addr: i64) -> Result<&mut W> {
+        debug!("[{}] accessing [{}]", self.pc, addr);
+        let idx = self.index(addr)?;
+        // the limit is on allocated cells, so a few far apart writes stay cheap
+        if let Some(limit) = self.memory_limit {
+            if self.memory.len() + self.memory.growth(idx) > limit {
+                return Err(VMError::MemoryLimitExceeded { addr, limit, pc: self.op_pc });
+            }
+        }
+        Ok(self.memory.get_mut(idx))
+    }
+    fn store(&mut self, addr: i64, new: W, pc: i64) -> Result<W> {
+        let cell = self.access(addr)?;
+        let old = core::mem::replace(cell, new);
+        // decoded instructions are cached per address, a write invalidates the one it overwrites
+        if let Some(op) = self.decode_cache.get_mut(addr as usize) {
+            *op = None;
+        }
+        if !self.watchpoints.is_empty() && self.watchpoints.contains(&addr) {
+            self.watch_hit = Some(BreakReason::Watchpoint { addr, old, new, pc });
+        }
+        Ok(old)
+    }
+    fn read_opcode(&mut self) -> Result<Opcode> {
+        debug!("[{}] reading opcode",self.pc);
+        let cached = usize::try_from(self.pc).ok().and_then(|pc| self.decode_cache.get(pc).copied().flatten());
+        let op = match cached {
+            Some(op) => op,
+            None => {
+                let i = self.load(self.pc)?;
+                let op = Self::decode(i, self.pc, self.rb, self.strict_decoding)?;
+                let cached_len = self.cached_len();
+                let cache = Self::sized_decode_cache(&mut self.decode_cache, cached_len);
+                if let Some(slot) = usize::try_from(self.pc).ok().and_then(|pc| cache.get_mut(pc)) {
+                    *slot = Some(op);
+                }
+                op
             },
-            99 => Opcode::End,
-            o  => return Err(VMError::InvalidOpcode{opcode: o, addr: self.pc-1}),
+        };
+        self.pc += 1;
+        Ok(op)
+    }
+    // only the program image is cached, and nothing when caching is off
+    fn cached_len(&self) -> usize {
+        if self.decode_caching { self.program.len() } else { 0 }
+    }
+    // the cache starts empty so that creating, cloning and resetting a VM stay cheap
+    fn sized_decode_cache(cache: &mut Vec<Option<Opcode>>, len: usize) -> &mut Vec<Option<Opcode>> {
+        if cache.len() < len {
+            cache.resize(len, None);
+        }
+        cache
+    }
+    fn decode(word: W, pc: i64, rb: i64, strict: bool) -> Result<Opcode> {
+        match word.to_i64() {
+            Some(word) => Opcode::decode_at(word, pc, rb, strict),
+            None => Err(VMError::InvalidOpcode { opcode: word.saturating_i64(), pc, rb }),
+        }
+    }
+    fn checked(&self, op: &'static str, lhs: W, rhs: W, f: fn(W, W) -> Option<W>) -> Result<W> {
+        f(lhs, rhs).ok_or(VMError::ArithmeticOverflow {
+            pc: self.op_pc,
+            lhs: lhs.saturating_i64(),
+            rhs: rhs.saturating_i64(),
+            op,
         })
     }
-    fn fetch_param(&mut self, mode: ParameterMode) -> Result<&mut i64> {
+    fn jump_target(&self, target: W) -> Result<i64> {
+        target.to_i64().ok_or(VMError::InvalidAddress { addr: target.saturating_i64(), pc: self.op_pc, rb: self.rb })
+    }
+    fn param_addr(&mut self, mode: ParameterMode) -> Result<i64> {
         debug!("[{}] fetching {:?}", self.pc, mode);
         let pc = self.pc;
         self.pc += 1;
-        let r = match mode {
+        let addr = match mode {
             ParameterMode::Immediate => {
-                self.access(pc)?
+                pc
             },
             ParameterMode::Position => {
-                let pos = *self.access(pc)?;
-                self.access(pos)?
+                let addr = self.load(pc)?;
+                let addr = addr.to_i64().ok_or(VMError::InvalidAddress {
+                    addr: addr.saturating_i64(),
+                    pc: self.op_pc,
+                    rb: self.rb,
+                })?;
+                if addr < 0 {
+                    return Err(VMError::NegativePositionAddress { addr, pc: self.op_pc });
+                }
+                addr
             },
             ParameterMode::Relative => {
-                let pos = *self.access(pc)?;
-                self.access(pos + self.rb)?
+                let offset = self.load(pc)?;
+                let addr = self.checked("relative address", offset, W::from_i64(self.rb), W::checked_add)?;
+                if addr < W::default() {
+                    return Err(VMError::NegativeRelativeAddress {
+                        offset: offset.saturating_i64(),
+                        relative_base: self.rb,
+                        pc: self.op_pc,
+                    });
+                }
+                addr.to_i64().ok_or(VMError::InvalidAddress {
+                    addr: addr.saturating_i64(),
+                    pc: self.op_pc,
+                    rb: self.rb,
+                })?
+            },
+        };
+        Ok(addr)
+    }
+    fn fetch_param(&mut self, mode: ParameterMode) -> Result<W> {
+        let addr = self.param_addr(mode)?;
+        self.load(addr)
+    }
+}
+
+pub struct Outputs<'a, W: IntcodeWord = i64> {
+    vm: &'a mut Vm<W>,
+    done: bool,
+}
+
+impl<'a, W: IntcodeWord> Iterator for Outputs<'a, W> {
+    type Item = Result<W>;
+    fn next(&mut self) -> Option<Result<W>> {
+        if self.done {
+            return None;
+        }
+        let next = self.vm.run_until_output().transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
+
+pub struct OutputChunks<'a, W: IntcodeWord = i64> {
+    vm: &'a mut Vm<W>,
+    size: usize,
+    done: bool,
+}
+
+impl<'a, W: IntcodeWord> Iterator for OutputChunks<'a, W> {
+    type Item = Result<Vec<W>>;
+    fn next(&mut self) -> Option<Result<Vec<W>>> {
+        if self.done {
+            return None;
+        }
+        let mut chunk = vec![W::default(); self.size];
+        match self.vm.fill_outputs(&mut chunk, Vm::run_until_output) {
+            Ok(true) => Some(Ok(chunk)),
+            Ok(false) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
             },
+        }
+    }
+}
+
+impl<W: IntcodeWord> Clone for Vm<W> {
+    fn clone(&self) -> Vm<W> {
+        // input sources, output sinks, trace hooks, recorders and transcripts are not cloneable, the clone uses the internal queues
+        Vm {
+            program: self.program.clone(),
+            memory: self.memory.clone(),
+            decode_cache: Vec::new(),
+            decode_caching: self.decode_caching,
+            strict_decoding: self.strict_decoding,
+            memory_limit: self.memory_limit,
+            pc: self.pc,
+            op_pc: self.op_pc,
+            rb: self.rb,
+            state: self.state,
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            history: self.history.clone(),
+            history_len: self.history_len,
+            journal: self.journal.clone(),
+            journal_capacity: self.journal_capacity,
+            steps: self.steps,
+            inputs_consumed: self.inputs_consumed,
+            outputs_produced: self.outputs_produced,
+            step_limit: self.step_limit,
+            timeout_check_interval: self.timeout_check_interval,
+            breakpoints: self.breakpoints.clone(),
+            break_pc: self.break_pc,
+            watchpoints: self.watchpoints.clone(),
+            watch_hit: self.watch_hit,
+            input_source: None,
+            output_sink: None,
+            trace_hook: None,
+            #[cfg(feature = "std")]
+            recorder: None,
+            #[cfg(feature = "std")]
+            transcript: None,
+            profiling: self.profiling,
+            profile: self.profile.clone(),
+        }
+    }
+}
+
+impl<W: IntcodeWord> PartialEq for Vm<W> {
+    fn eq(&self, other: &Vm<W>) -> bool {
+        self.pc == other.pc
+            && self.rb == other.rb
+            && self.state == other.state
+            && self.memory == other.memory
+    }
+}
+
+impl<W: IntcodeWord> From<Snapshot<W>> for Vm<W> {
+    fn from(snapshot: Snapshot<W>) -> Vm<W> {
+        Vm::from_snapshot(snapshot)
+    }
+}
+
+impl<W: IntcodeWord> From<Vm<W>> for Snapshot<W> {
+    fn from(vm: Vm<W>) -> Snapshot<W> {
+        vm.snapshot()
+    }
+}
+
+impl<W: IntcodeWord> FromStr for Vm<W> {
+    type Err = VMError;
+    fn from_str(s: &str) -> Result<Vm<W>> {
+        Vm::from_source(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::borrow::ToOwned;
+    use alloc::format;
+    use proptest::prelude::*;
+
+    // reads a value and outputs it doubled
+    const DOUBLER: &str = "3,9,1002,9,2,10,4,10,99,0,0";
+
+    #[test]
+    fn input_closure_is_called_only_when_needed() {
+        let mut vm: Vm = Vm::from_source("104,7,3,13,4,13,3,13,4,13,104,8,99,0").unwrap();
+        let mut calls = 0;
+        let mut next = 0;
+        let mut input = || {
+            calls += 1;
+            next += 10;
+            next
         };
-        Ok(r)
+        assert_eq!(vm.run_until_output_with_input(&mut input).unwrap(), Some(7));
+        assert_eq!(vm.run_until_output_with_input(&mut input).unwrap(), Some(10));
+        assert_eq!(vm.run_until_output_with_input(&mut input).unwrap(), Some(20));
+        assert_eq!(vm.run_until_output_with_input(&mut input).unwrap(), Some(8));
+        assert_eq!(vm.run_until_output_with_input(&mut input).unwrap(), None);
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn queued_inputs_are_used_before_the_closure() {
+        let mut vm: Vm = Vm::from_source(DOUBLER).unwrap();
+        vm.add_inputs(&[4]);
+        let mut calls = 0;
+        let output = vm.run_until_output_with_input(|| {
+            calls += 1;
+            0
+        });
+        assert_eq!(output.unwrap(), Some(8));
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn parses_programs_with_trailing_whitespace() {
+        assert_eq!(parse_program("1,0,0,3,99\n").unwrap(), vec![1, 0, 0, 3, 99]);
+        assert_eq!(parse_program(" 1, 2 ,3,,\n\n").unwrap(), vec![1, 2, 3]);
+        let vm: Vm = "1,9,10,3,2,3,11,0,99,30,40,50".parse().unwrap();
+        assert_eq!(vm.peek_at(11).unwrap(), 50);
+    }
+
+    #[test]
+    fn parses_negative_numbers() {
+        assert_eq!(parse_program("1101,100,-1,4,0").unwrap(), vec![1101, 100, -1, 4, 0]);
+        assert_eq!(parse_program("-9223372036854775808").unwrap(), vec![i64::MIN]);
+    }
+
+    #[test]
+    fn reports_the_index_of_bad_tokens() {
+        let bad = |source| match parse_program(source) {
+            Err(VMError::ParseError { index, token }) => (index, token),
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+        assert_eq!(bad("1,2,x,4"), (2, "x".to_owned()));
+        assert_eq!(bad("1,,3"), (1, String::new()));
+        assert_eq!(bad("1,2,3.5\n"), (2, "3.5".to_owned()));
+        assert!(matches!(Vm::<i64>::from_source("99,9999999999999999999"), Err(VMError::ParseError { index: 1, .. })));
+    }
+
+    #[test]
+    fn peeking_does_not_grow_memory() {
+        let mut vm: Vm = Vm::from_source("1,0,0,0,99").unwrap();
+        let len = vm.memory_len();
+        assert_eq!(vm.peek_at(1_000_000).unwrap(), 0);
+        assert_eq!(vm.dump_memory(100..200), vec![0; 100]);
+        assert_eq!(vm.memory_len(), len);
+        vm.write_at(100, 42).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.peek_at(100).unwrap(), 42);
+        assert_eq!(vm.peek_at(0).unwrap(), 2);
+        assert!(matches!(vm.peek_at(-1), Err(VMError::InvalidAddress { addr: -1, .. })));
+    }
+
+    #[test]
+    fn pc_advances_by_the_instruction_width() {
+        let mut vm: Vm = Vm::from_source("109,5,1101,1,2,0,1006,0,99,99").unwrap();
+        assert_eq!((vm.pc(), vm.relative_base()), (0, 0));
+        vm.step().unwrap();
+        assert_eq!((vm.pc(), vm.relative_base()), (2, 5));
+        vm.step().unwrap();
+        assert_eq!(vm.pc(), 6);
+        // [0] is 3 now, so the jump falls through
+        assert_eq!(vm.step().unwrap(), VmState::Running);
+        assert_eq!(vm.pc(), 9);
+        assert_eq!(vm.step().unwrap(), VmState::Stopped);
+        assert_eq!(vm.state(), VmState::Stopped);
+        assert_eq!(vm.pc(), 10);
+    }
+
+    #[test]
+    fn jumps_fall_through_or_go_to_their_target() {
+        let mut vm: Vm = Vm::from_source("1105,0,9,1106,0,7,99,99").unwrap();
+        vm.step().unwrap();
+        assert_eq!(vm.pc(), 3);
+        vm.step().unwrap();
+        assert_eq!(vm.pc(), 7);
+        vm.set_relative_base(-4);
+        assert_eq!(vm.relative_base(), -4);
+    }
+
+    #[test]
+    fn errors_carry_pc_and_history() {
+        let mut vm: Vm = Vm::from_source("109,3,1101,2,3,20,1002,20,4,21,0").unwrap();
+        vm.set_history_len(16);
+        match vm.run() {
+            Err(VMError::InvalidOpcode { opcode: 0, pc: 10, rb: 3 }) => {},
+            other => panic!("expected an invalid opcode, got {:?}", other),
+        }
+        let context = vm.context().to_string();
+        assert!(context.starts_with("pc: 10, relative base: 3"), "{}", context);
+        assert!(context.contains("[2] Add(imm, imm, pos) 2 3 20"), "{}", context);
+        assert!(context.contains("[6] Mul(pos, imm, pos) 5 4 21"), "{}", context);
+        assert_eq!(vm.context().history.len(), 3);
+    }
+
+    #[test]
+    fn error_messages() {
+        let mut vm: Vm = Vm::from_source("3,0,99").unwrap();
+        let error = vm.run().unwrap_err();
+        assert_eq!(error.to_string(), "The VM is waiting for input at pc 0 (relative base 0), but none is available");
+        let error = VMError::Deadlock { stages: vec![0, 2] };
+        assert_eq!(error.to_string(), "The VMs [0, 2] are all waiting for input that will never arrive");
+        assert_eq!(VMError::NetworkHalted.to_string(), "Every machine in the network has halted");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_errors_keep_their_source() {
+        use std::error::Error;
+        let error = VMError::from(std::io::Error::new(std::io::ErrorKind::NotFound, "no such file"));
+        assert_eq!(error.to_string(), "I/O error: no such file");
+        assert_eq!(error.source().unwrap().to_string(), "no such file");
+    }
+
+    #[test]
+    fn history_keeps_the_most_recent_instructions() {
+        let mut vm: Vm = Vm::from_source("1101,0,0,7,1105,1,0").unwrap();
+        vm.set_history_len(4);
+        vm.set_step_limit(Some(100));
+        assert!(matches!(vm.run(), Err(VMError::StepLimitExceeded { steps: 100, .. })));
+        let history = vm.context().history;
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.last().unwrap().to_string(), "[4] JumpIfTrue(imm, imm) 1 0");
+    }
+
+    #[test]
+    fn run_with_inputs_collects_every_output() {
+        // outputs the sum of two inputs, then the product
+        let mut vm: Vm = Vm::from_source("3,17,3,18,1,17,18,19,4,19,2,17,18,19,4,19,99").unwrap();
+        assert_eq!(vm.run_with_inputs(&[6, 7]).unwrap(), vec![13, 42]);
+        let mut vm: Vm = Vm::from_source("104,1,104,2,104,3,99").unwrap();
+        assert_eq!(vm.run_collect().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn run_with_inputs_fails_when_inputs_run_out() {
+        let mut vm: Vm = Vm::from_source("3,17,3,18,1,17,18,19,4,19,2,17,18,19,4,19,99").unwrap();
+        assert!(matches!(vm.run_with_inputs(&[6]), Err(VMError::NoMoreInput { pc: 2, .. })));
+    }
+
+    #[test]
+    fn step_event_sequence() {
+        let mut vm: Vm = Vm::from_source("3,11,4,11,1001,11,1,11,4,11,99,0").unwrap();
+        assert_eq!(vm.step_event().unwrap(), Event::NeedsInput);
+        assert_eq!(vm.step_event().unwrap(), Event::NeedsInput);
+        vm.add_inputs(&[41]);
+        let mut events = Vec::new();
+        loop {
+            let event = vm.step_event().unwrap();
+            events.push(event);
+            if event == Event::Halted {
+                break;
+            }
+        }
+        assert_eq!(events, vec![
+            Event::Continue,
+            Event::Output(41),
+            Event::Continue,
+            Event::Output(42),
+            Event::Halted,
+        ]);
+        // step_event hands outputs to the caller instead of queueing them
+        assert_eq!(vm.pending_outputs(), 0);
+        assert!(matches!(vm.step_event(), Err(VMError::Stopped { pc: 11, .. })));
+    }
+
+    #[test]
+    fn input_from_a_deque() {
+        let mut vm: Vm = Vm::from_source(DOUBLER).unwrap();
+        vm.set_input_source(VecDeque::from(vec![21]));
+        assert_eq!(vm.run_collect().unwrap(), vec![42]);
+    }
+
+    #[test]
+    fn input_from_a_closure() {
+        let mut vm: Vm = Vm::from_source(DOUBLER).unwrap();
+        let mut values = vec![5];
+        vm.set_input_source(move || values.pop());
+        assert_eq!(vm.run_collect().unwrap(), vec![10]);
+    }
+
+    #[test]
+    fn input_from_an_iterator() {
+        let mut vm: Vm = Vm::from_source(DOUBLER).unwrap();
+        vm.set_input_source(IterInput(100..));
+        assert_eq!(vm.run_collect().unwrap(), vec![200]);
+    }
+
+    #[test]
+    fn an_exhausted_source_waits_for_input() {
+        let mut vm: Vm = Vm::from_source(DOUBLER).unwrap();
+        vm.set_input_source(IterInput(core::iter::empty()));
+        assert_eq!(vm.step().unwrap(), VmState::WaitingForInput);
+        assert_eq!(vm.pc(), 0);
+        // the internal queue is the fallback
+        vm.add_inputs(&[3]);
+        assert_eq!(vm.run_collect().unwrap(), vec![6]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn outputs_go_to_a_shared_vec_or_closure() {
+        let sink = Arc::new(Mutex::new(Vec::new()));
+        let mut vm: Vm = Vm::from_source("104,1,104,2,99").unwrap();
+        vm.set_output_sink(Arc::clone(&sink));
+        vm.run().unwrap();
+        assert_eq!(vm.pending_outputs(), 0);
+        assert_eq!(*sink.lock().unwrap(), vec![1, 2]);
+
+        let total = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&total);
+        vm.reset();
+        vm.set_output_sink(move |o| *counter.lock().unwrap() += o);
+        vm.run().unwrap();
+        assert_eq!(*total.lock().unwrap(), 3);
+        vm.clear_output_sink();
+        vm.reset();
+        assert_eq!(vm.run_collect().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn two_vms_connected_by_a_channel() {
+        // doubles every input forever, and adds one to every input forever
+        let mut doubler: Vm = Vm::from_source("3,11,1002,11,2,11,4,11,1105,1,0,0").unwrap();
+        let mut adder: Vm = Vm::from_source("3,11,1001,11,1,11,4,11,1105,1,0,0").unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        doubler.set_output_sink(tx);
+        adder.set_input_source(rx);
+        for i in 1..=3 {
+            doubler.add_inputs(&[i]);
+            assert_eq!(doubler.run_until_input_needed().unwrap(), (vec![], VmState::WaitingForInput));
+            let (outputs, _) = adder.run_until_input_needed().unwrap();
+            assert_eq!(outputs, vec![2 * i + 1]);
+        }
+    }
+
+    #[test]
+    fn reset_then_rerun_gives_identical_outputs() {
+        // day 9's quine
+        let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let mut vm: Vm = Vm::from_source(quine).unwrap();
+        let first = vm.run_collect().unwrap();
+        assert_eq!(first, parse_program(quine).unwrap());
+        let steps = vm.steps_executed();
+        vm.add_inputs(&[1]);
+        vm.reset();
+        assert_eq!((vm.pc(), vm.relative_base(), vm.state()), (0, 0, VmState::Running));
+        assert_eq!((vm.pending_inputs(), vm.steps_executed()), (0, 0));
+        assert_eq!(vm.run_collect().unwrap(), first);
+        assert_eq!(vm.steps_executed(), steps);
+    }
+
+    #[test]
+    fn reset_with_patches() {
+        let mut vm: Vm = Vm::from_source("1,0,0,0,99").unwrap();
+        vm.reset_with_patches(&[(1, 4), (2, 4)]).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.peek_at(0).unwrap(), 198);
+        vm.reset_with_patches(&[]).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.peek_at(0).unwrap(), 2);
+        assert!(matches!(vm.reset_with_patches(&[(-1, 0)]), Err(VMError::InvalidAddress { addr: -1, .. })));
+    }
+
+    #[test]
+    fn clones_run_independently() {
+        let mut vm: Vm = Vm::from_source("1101,1,1,20,1001,20,1,20,4,20,99").unwrap();
+        vm.step().unwrap();
+        let mut clone = vm.clone();
+        assert!(clone == vm);
+        vm.step().unwrap();
+        assert_eq!(vm.peek_at(20).unwrap(), 3);
+        assert_eq!(clone.peek_at(20).unwrap(), 2);
+        assert_eq!(clone.pc(), 4);
+        assert!(clone != vm);
+        clone.step().unwrap();
+        assert!(clone == vm);
+        assert_eq!(clone.run_collect().unwrap(), vec![3]);
+        assert_eq!(vm.run_collect().unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn a_waiting_clone_resumes_with_its_own_input() {
+        let mut vm: Vm = Vm::from_source(DOUBLER).unwrap();
+        assert_eq!(vm.step().unwrap(), VmState::WaitingForInput);
+        let mut clone = vm.clone();
+        clone.add_inputs(&[5]);
+        assert_eq!(clone.run_collect().unwrap(), vec![10]);
+        assert_eq!(vm.state(), VmState::WaitingForInput);
+        assert_eq!(vm.run_with_inputs(&[7]).unwrap(), vec![14]);
+    }
+
+    #[test]
+    fn equal_states_reached_by_different_paths() {
+        let mut a: Vm = Vm::from_source("1101,2,2,10,99").unwrap();
+        let mut b: Vm = Vm::from_source("1102,2,2,10,99").unwrap();
+        a.run().unwrap();
+        b.run().unwrap();
+        assert!(a != b);
+        b.write_at(0, 1101).unwrap();
+        assert!(a == b);
+        assert_eq!(a.diff_memory(&b), vec![]);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn json_round_trip_mid_run() {
+        let program = "3,30,1,30,30,31,4,31,3,32,2,31,32,33,4,33,1101,0,5000000,40,4,40,99";
+        let mut vm: Vm = Vm::from_source(program).unwrap();
+        vm.write_at(5_000_000, 7).unwrap();
+        vm.add_inputs(&[4]);
+        let (outputs, _) = vm.run_until_input_needed().unwrap();
+        assert_eq!(outputs, vec![8]);
+        let json = serde_json::to_string(&vm).unwrap();
+        let mut restored: Vm = serde_json::from_str(&json).unwrap();
+        assert!(restored == vm);
+        assert_eq!(restored.peek_at(5_000_000).unwrap(), 7);
+        assert_eq!(vm.run_with_inputs(&[3]).unwrap(), restored.run_with_inputs(&[3]).unwrap());
+        assert!(restored == vm);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn bincode_files_round_trip() {
+        let path = std::env::temp_dir().join(format!("intcode-save-{}.bin", std::process::id()));
+        let mut vm: Vm = Vm::from_source(DOUBLER).unwrap();
+        vm.step().unwrap();
+        vm.save_to(&path).unwrap();
+        let mut loaded: Vm = Vm::load_from(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.state(), VmState::WaitingForInput);
+        assert_eq!(loaded.run_with_inputs(&[9]).unwrap(), vec![18]);
+        assert!(matches!(Vm::<i64>::load_from(&path), Err(VMError::Persistence { .. })));
+    }
+
+    #[test]
+    fn mixed_ascii_and_numbers() {
+        let mut vm: Vm = Vm::from_source("104,104,104,105,104,10,104,1234,104,111,104,107,104,10,99").unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.drain_ascii_output(), "hi\nok\n");
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![1234]);
+    }
+
+    #[test]
+    fn ascii_lines() {
+        let mut vm: Vm = Vm::from_source("104,104,104,105,104,10,104,111,104,107,104,200,99").unwrap();
+        assert_eq!(vm.run_until_ascii_line().unwrap(), Some("hi".to_owned()));
+        // the partial line is kept for the caller that handles the error
+        assert!(matches!(vm.run_until_ascii_line(), Err(VMError::NonAscii { value: 200 })));
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![111, 107, 200]);
+        assert_eq!(vm.run_until_ascii_line().unwrap(), None);
+    }
+
+    #[test]
+    fn ascii_input() {
+        let mut vm: Vm = Vm::new(vec![]);
+        vm.add_ascii_input("go").unwrap();
+        vm.add_ascii_input("ok\n").unwrap();
+        assert_eq!(vm.inputs.iter().copied().collect::<Vec<_>>(), vec![103, 111, 10, 111, 107, 10]);
+        assert!(matches!(vm.add_ascii_input("né"), Err(VMError::NonAscii { value: 0xe9 })));
+        assert_eq!(vm.pending_inputs(), 6);
+    }
+
+    // echoes its input up to and including a `.`, then outputs 1000
+    #[cfg(feature = "std")]
+    const ECHO: &str = "3,100,4,100,1008,100,46,101,1006,101,0,104,1000,99";
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn interactive_echo() {
+        let mut vm: Vm = Vm::from_source(ECHO).unwrap();
+        let mut out = std::io::Cursor::new(Vec::new());
+        vm.run_interactive(std::io::Cursor::new(b"hi\nthere.\n".to_vec()), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out.into_inner()).unwrap(), "hi\nthere.\n1000\n");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn interactive_eof_is_an_error() {
+        let mut vm: Vm = Vm::from_source(ECHO).unwrap();
+        let mut out = Vec::new();
+        let result = vm.run_interactive(std::io::Cursor::new(b"ab".to_vec()), &mut out);
+        assert!(matches!(result, Err(VMError::NoMoreInput { pc: 0, .. })));
+        assert_eq!(out, b"ab\n");
+    }
+
+    #[test]
+    fn step_limit_stops_an_infinite_loop() {
+        let mut vm: Vm = Vm::from_source("1105,1,0").unwrap();
+        vm.set_step_limit(Some(1000));
+        assert!(matches!(vm.run(), Err(VMError::StepLimitExceeded { steps: 1000, pc: 0 })));
+        assert_eq!(vm.steps_executed(), 1000);
+        vm.reset();
+        assert_eq!(vm.steps_executed(), 0);
+    }
+
+    #[test]
+    fn steps_count_every_instruction_including_the_halt() {
+        let mut vm: Vm = Vm::from_source(DOUBLER).unwrap();
+        assert_eq!(vm.run_with_inputs(&[1]).unwrap(), vec![2]);
+        assert_eq!(vm.steps_executed(), 4);
+        vm.reset();
+        vm.set_step_limit(Some(4));
+        assert_eq!(vm.run_with_inputs(&[1]).unwrap(), vec![2]);
+        // a day 9 loop runs through the fast path, which must count the same
+        let mut vm: Vm = Vm::from_source("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99").unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.steps_executed(), 16 * 5 + 1);
+    }
+
+    #[test]
+    fn writing_far_away_allocates_one_cell() {
+        let mut vm: Vm = Vm::from_source("1101,0,0,3,1101,1,2,1000000000000000,99").unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.peek_at(1_000_000_000_000_000).unwrap(), 3);
+        assert_eq!(vm.memory_len(), 10);
+    }
+
+    #[test]
+    fn the_memory_limit_counts_allocated_cells() {
+        let mut vm: Vm = Vm::from_source("1101,0,0,200,99").unwrap();
+        vm.set_memory_limit(Some(100));
+        assert!(matches!(vm.run(), Err(VMError::MemoryLimitExceeded { addr: 200, limit: 100, pc: 0 })));
+        let far = "1101,1,1,1000000000,1101,1,1,2000000000,1101,1,1,3000000000,99";
+        let mut vm: Vm = Vm::from_source(far).unwrap();
+        vm.set_memory_limit(Some(15));
+        match vm.run() {
+            Err(VMError::MemoryLimitExceeded { addr, limit, pc }) => assert_eq!((addr, limit, pc), (3_000_000_000, 15, 8)),
+            other => panic!("expected the memory limit, got {:?}", other),
+        }
+        assert_eq!(vm.memory_len(), 15);
+        // overwriting an allocated cell needs no room
+        vm.write_at(2_000_000_000, 5).unwrap();
+    }
+
+    #[test]
+    fn reads_do_not_allocate() {
+        let mut vm: Vm = Vm::from_source("4,2000,4,1000000000000,99").unwrap();
+        vm.set_memory_limit(Some(5));
+        assert_eq!(vm.run_collect().unwrap(), vec![0, 0]);
+        assert_eq!(vm.memory_len(), 5);
+    }
+
+    #[test]
+    fn multiplication_overflow_is_an_error() {
+        let mut vm: Vm = Vm::from_source("1102,4611686018427387903,4,5,99,0").unwrap();
+        match vm.run() {
+            Err(VMError::ArithmeticOverflow { pc, lhs, rhs, op }) => {
+                assert_eq!((pc, lhs, rhs, op), (0, i64::MAX / 2, 4, "mul"));
+            },
+            other => panic!("expected an overflow, got {:?}", other),
+        }
+        let mut vm: Vm = Vm::from_source("1101,9223372036854775807,1,5,99,0").unwrap();
+        assert!(matches!(vm.run(), Err(VMError::ArithmeticOverflow { op: "add", .. })));
+        let mut vm: Vm = Vm::from_source("109,9223372036854775807,109,1,99").unwrap();
+        assert!(matches!(vm.run(), Err(VMError::ArithmeticOverflow { op: "relative base offset", pc: 2, .. })));
+    }
+
+    #[test]
+    fn big_numbers_still_work() {
+        let mut vm: Vm = Vm::from_source("1102,34915192,34915192,7,4,7,99,0").unwrap();
+        assert_eq!(vm.run_collect().unwrap(), vec![1219070632396864]);
+        let mut vm: Vm = Vm::from_source("104,1125899906842624,99").unwrap();
+        assert_eq!(vm.run_collect().unwrap(), vec![1125899906842624]);
+    }
+
+    #[test]
+    fn immediate_destinations_are_rejected() {
+        for program in &["11101,1,2,3,99", "11102,1,2,3,99", "103,0,99", "11107,1,2,3,99", "11108,1,2,3,99"] {
+            let mut vm: Vm = Vm::from_source(program).unwrap();
+            vm.add_inputs(&[1]);
+            let opcode = parse_program(program).unwrap()[0];
+            assert!(matches!(vm.run(), Err(VMError::InvalidWriteMode { opcode: o, pc: 0 }) if o == opcode), "{}", program);
+            assert_eq!(vm.peek_at(3).unwrap(), parse_program(program).unwrap().get(3).copied().unwrap_or(0));
+        }
+    }
+
+    #[test]
+    fn immediate_reads_are_fine() {
+        let mut vm: Vm = Vm::from_source("1101,1,2,7,104,5,99,0").unwrap();
+        assert_eq!(vm.run_collect().unwrap(), vec![5]);
+        assert_eq!(vm.peek_at(7).unwrap(), 3);
+        assert!(matches!(Opcode::decode(11101), Err(VMError::InvalidWriteMode { opcode: 11101, .. })));
+    }
+
+    #[test]
+    fn negative_relative_addresses() {
+        let mut vm: Vm = Vm::from_source("109,-5,204,0,99").unwrap();
+        match vm.run() {
+            Err(e @ VMError::NegativeRelativeAddress { .. }) => {
+                assert!(matches!(e, VMError::NegativeRelativeAddress { offset: 0, relative_base: -5, pc: 2 }));
+                let message = e.to_string();
+                assert!(message.contains("offset 0") && message.contains("relative base -5"), "{}", message);
+            },
+            other => panic!("expected a negative relative address, got {:?}", other),
+        }
+        // a negative base is fine as long as the sum is not
+        let mut vm: Vm = Vm::from_source("109,-5,204,9,99").unwrap();
+        assert_eq!(vm.run_collect().unwrap(), vec![99]);
+    }
+
+    #[test]
+    fn negative_position_addresses() {
+        let mut vm: Vm = Vm::from_source("4,-3,99").unwrap();
+        assert!(matches!(vm.run(), Err(VMError::NegativePositionAddress { addr: -3, pc: 0 })));
+    }
+
+    // outputs 1, then outputs every input doubled until it reads 0
+    const PROMPTER: &str = "104,1,3,20,1006,20,16,1002,20,2,21,4,21,1105,1,2,99";
+
+    #[test]
+    fn run_until_input_needed_interleaves_io() {
+        let mut vm: Vm = Vm::from_source(PROMPTER).unwrap();
+        assert_eq!(vm.run_until_input_needed().unwrap(), (vec![1], VmState::WaitingForInput));
+        assert_eq!(vm.run_until_input_needed().unwrap(), (vec![], VmState::WaitingForInput));
+        vm.add_inputs(&[5]);
+        assert_eq!(vm.run_until_input_needed().unwrap(), (vec![10], VmState::WaitingForInput));
+        vm.add_inputs(&[7, 8]);
+        assert_eq!(vm.run_until_input_needed().unwrap(), (vec![14, 16], VmState::WaitingForInput));
+        vm.add_inputs(&[0]);
+        assert_eq!(vm.run_until_input_needed().unwrap(), (vec![], VmState::Stopped));
+        assert_eq!(vm.run_until_input_needed().unwrap(), (vec![], VmState::Stopped));
+    }
+
+    #[test]
+    fn paused_vms_do_not_run() {
+        let mut vm: Vm = Vm::from_source(PROMPTER).unwrap();
+        vm.add_inputs(&[3]);
+        vm.step().unwrap();
+        vm.pause();
+        assert_eq!(vm.state(), VmState::Paused);
+        assert_eq!(vm.run_until_input_needed().unwrap(), (vec![1], VmState::Paused));
+        assert!(matches!(vm.step(), Err(VMError::Paused { pc: 2 })));
+        assert!(matches!(vm.run(), Err(VMError::Paused { pc: 2 })));
+        vm.resume();
+        assert_eq!(vm.run_until_input_needed().unwrap(), (vec![6], VmState::WaitingForInput));
+        vm.add_inputs(&[0]);
+        vm.run().unwrap();
+        // a stopped VM stays stopped
+        vm.pause();
+        assert_eq!(vm.state(), VmState::Stopped);
+    }
+
+    // day 13 style (x, y, tile) triples
+    const TILES: &str = "104,1,104,2,104,3,104,6,104,5,104,4,99";
+
+    #[test]
+    fn records_of_n_outputs() {
+        let mut vm: Vm = Vm::from_source(TILES).unwrap();
+        assert_eq!(vm.run_until_n_outputs::<3>().unwrap(), Some([1, 2, 3]));
+        assert_eq!(vm.run_until_n_outputs::<3>().unwrap(), Some([6, 5, 4]));
+        assert_eq!(vm.run_until_n_outputs::<3>().unwrap(), None);
+    }
+
+    #[test]
+    fn halting_mid_record_is_an_error() {
+        let mut vm: Vm = Vm::from_source(TILES).unwrap();
+        assert_eq!(vm.run_until_n_outputs::<4>().unwrap(), Some([1, 2, 3, 6]));
+        assert!(matches!(vm.run_until_n_outputs::<4>(), Err(VMError::TruncatedOutput { expected: 4, got: 2 })));
+        // the partial record is still there
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![5, 4]);
+    }
+
+    #[test]
+    fn records_with_input() {
+        let mut vm: Vm = Vm::from_source("3,20,4,20,104,0,3,20,4,20,99").unwrap();
+        let mut next = 0;
+        let record = vm.run_until_n_outputs_with_input::<3, _>(|| {
+            next += 7;
+            next
+        });
+        assert_eq!(record.unwrap(), Some([7, 0, 14]));
+        assert!(matches!(vm.run_until_n_outputs::<2>(), Ok(None)));
+    }
+
+    #[test]
+    fn iterating_over_outputs() {
+        let mut vm: Vm = Vm::from_source(TILES).unwrap();
+        let outputs: Vec<_> = vm.outputs_iter().collect::<Result<_>>().unwrap();
+        assert_eq!(outputs, vec![1, 2, 3, 6, 5, 4]);
+        assert!(matches!(vm.outputs_iter().next(), Some(Err(VMError::Stopped { .. }))));
+    }
+
+    #[test]
+    fn iterating_over_tiles() {
+        let mut vm: Vm = Vm::from_source(TILES).unwrap();
+        let mut tiles = Vec::new();
+        for chunk in vm.output_chunks(3) {
+            let chunk = chunk.unwrap();
+            tiles.push((chunk[0], chunk[1], chunk[2]));
+        }
+        assert_eq!(tiles, vec![(1, 2, 3), (6, 5, 4)]);
+        let mut vm: Vm = Vm::from_source(TILES).unwrap();
+        let chunks: Vec<_> = vm.output_chunks(4).collect();
+        assert_eq!(chunks.len(), 2);
+        assert!(matches!(chunks[1], Err(VMError::TruncatedOutput { expected: 4, got: 2 })));
+    }
+
+    #[test]
+    fn starving_iterators_yield_an_error_and_stop() {
+        let mut vm: Vm = Vm::from_source("104,1,3,0,104,2,99").unwrap();
+        let mut outputs = vm.outputs_iter();
+        assert_eq!(outputs.next().unwrap().unwrap(), 1);
+        assert!(matches!(outputs.next(), Some(Err(VMError::NoMoreInput { pc: 2, .. }))));
+        assert!(outputs.next().is_none());
+        let mut vm: Vm = Vm::from_source("3,0,99").unwrap();
+        let mut chunks = vm.output_chunks(3);
+        assert!(matches!(chunks.next(), Some(Err(VMError::NoMoreInput { .. }))));
+        assert!(chunks.next().is_none());
+    }
+
+    // counts its input down to zero, then outputs the zero
+    const COUNTDOWN: &str = "3,12,1001,12,-1,12,1005,12,2,4,12,99,0";
+
+    #[test]
+    fn breakpoints_fire_once_per_iteration() {
+        let mut vm: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        vm.add_inputs(&[3]);
+        vm.add_breakpoint(2);
+        let mut counters = Vec::new();
+        loop {
+            match vm.run_until_breakpoint().unwrap() {
+                BreakReason::Breakpoint(pc) => {
+                    assert_eq!(pc, 2);
+                    assert_eq!(vm.pc(), 2);
+                    // the instruction at the breakpoint has not run yet
+                    counters.push(vm.peek_at(12).unwrap());
+                },
+                BreakReason::Halted => break,
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(counters, vec![3, 2, 1]);
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    fn removed_breakpoints_and_input_waits() {
+        let mut vm: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        vm.add_breakpoint(0);
+        vm.add_breakpoint(9);
+        assert_eq!(vm.run_until_breakpoint().unwrap(), BreakReason::Breakpoint(0));
+        assert_eq!(vm.run_until_breakpoint().unwrap(), BreakReason::NeedsInput);
+        vm.add_inputs(&[2]);
+        vm.remove_breakpoint(9);
+        assert_eq!(vm.run_until_breakpoint().unwrap(), BreakReason::Halted);
+        vm.reset();
+        vm.clear_breakpoints();
+        vm.add_inputs(&[2]);
+        assert_eq!(vm.run_until_breakpoint().unwrap(), BreakReason::Halted);
+    }
+
+    #[test]
+    fn watchpoints_report_every_write() {
+        let mut vm: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        vm.add_inputs(&[3]);
+        vm.add_watchpoint(12);
+        let mut writes = Vec::new();
+        loop {
+            match vm.run_until_breakpoint().unwrap() {
+                BreakReason::Watchpoint { addr, old, new, pc } => writes.push((addr, old, new, pc)),
+                BreakReason::Halted => break,
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        assert_eq!(writes, vec![(12, 0, 3, 0), (12, 3, 2, 2), (12, 2, 1, 2), (12, 1, 0, 2)]);
+    }
+
+    #[test]
+    fn reads_do_not_trigger_watchpoints() {
+        let mut vm: Vm = Vm::from_source("4,7,1001,7,1,8,99,6,0").unwrap();
+        vm.add_watchpoint(7);
+        assert_eq!(vm.run_until_breakpoint().unwrap(), BreakReason::Halted);
+        vm.reset();
+        vm.write_at(7, 9).unwrap();
+        let hit = vm.run_until_breakpoint().unwrap();
+        assert_eq!(hit, BreakReason::Watchpoint { addr: 7, old: 6, new: 9, pc: 0 });
+        vm.remove_watchpoint(7);
+        vm.write_at(7, 10).unwrap();
+        vm.clear_watchpoints();
+        assert_eq!(vm.run_until_breakpoint().unwrap(), BreakReason::Halted);
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![10]);
+    }
+
+    #[cfg(feature = "std")]
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    #[cfg(feature = "std")]
+    impl SharedBuffer {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn trace_of_a_short_program() {
+        let mut vm: Vm = Vm::from_source("1101,2,3,13,1002,13,2,14,109,4,204,10,99").unwrap();
+        let buffer = SharedBuffer::default();
+        vm.set_trace_hook(trace_to_writer(buffer.clone()));
+        vm.run().unwrap();
+        assert_eq!(buffer.text(), "\
+[0] Add(imm, imm, pos) 2 3 -> [13] (was 0)
+[4] Mul(pos, imm, pos) 5 2 -> [14] (was 0)
+[8] RelativeBaseOffset(imm) 4
+[10] Output(rel) 10
+[12] End
+");
+    }
+
+    #[test]
+    fn trace_events_see_the_relative_base_before_the_instruction() {
+        let mut vm: Vm = Vm::from_source("109,4,21101,1,2,0,99").unwrap();
+        let events = Arc::new(core::sync::atomic::AtomicUsize::new(0));
+        let seen = Arc::clone(&events);
+        vm.set_trace_hook(Box::new(move |event| {
+            match seen.fetch_add(1, core::sync::atomic::Ordering::SeqCst) {
+                0 => assert_eq!((event.pc, event.rb, event.dst), (0, 0, None)),
+                1 => assert_eq!((event.pc, event.rb, event.dst, event.old), (2, 4, Some(4), Some(2))),
+                _ => {},
+            }
+        }));
+        vm.run().unwrap();
+        vm.clear_trace_hook();
+        assert_eq!(events.load(core::sync::atomic::Ordering::SeqCst), 3);
+        assert_eq!(vm.peek_at(4).unwrap(), 3);
+    }
+
+    // the first pass rewrites its opening add of 3 and 3 into a multiplication
+    const SELF_PATCHING: &str = "1101,3,3,22,1101,0,1102,0,1005,21,18,1101,0,1,21,1105,1,0,4,22,99,0,0";
+
+    #[test]
+    fn decode_cache_sees_self_patches() {
+        for &caching in &[true, false] {
+            let mut vm: Vm = Vm::from_source(SELF_PATCHING).unwrap();
+            vm.set_decode_cache(caching);
+            assert_eq!(vm.run_collect().unwrap(), vec![9]);
+            vm.reset();
+            let mut outputs = Vec::new();
+            loop {
+                match vm.step_event().unwrap() {
+                    Event::Continue => {},
+                    Event::Output(o) => outputs.push(o),
+                    _ => break,
+                }
+            }
+            assert_eq!(outputs, vec![9]);
+        }
+    }
+
+    #[test]
+    fn decode_cache_sees_external_patches() {
+        let mut vm: Vm = Vm::from_source("104,1,1105,1,0").unwrap();
+        assert_eq!(vm.run_until_output().unwrap(), Some(1));
+        assert_eq!(vm.run_until_output().unwrap(), Some(1));
+        // turn the cached output of an immediate into one of a position
+        vm.write_at(0, 4).unwrap();
+        vm.write_at(1, 2).unwrap();
+        assert_eq!(vm.run_until_output().unwrap(), Some(1105));
+        vm.write_at(0, 104).unwrap();
+        assert_eq!(vm.run_until_output().unwrap(), Some(2));
+    }
+
+    fn slow_run(vm: &mut Vm) -> Vec<i64> {
+        let mut outputs = Vec::new();
+        loop {
+            match vm.step_event().unwrap() {
+                Event::Continue => {},
+                Event::Output(o) => outputs.push(o),
+                Event::Halted | Event::NeedsInput => return outputs,
+            }
+        }
+    }
+
+    #[test]
+    fn fast_and_slow_paths_agree() {
+        let day5 = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+        let cases: &[(&str, &[i64])] = &[
+            ("1,9,10,3,2,3,11,0,99,30,40,50", &[]),
+            ("3,9,8,9,10,9,4,9,99,-1,8", &[8]),
+            (day5, &[7]),
+            (day5, &[8]),
+            (day5, &[9]),
+            ("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99", &[]),
+            ("1102,34915192,34915192,7,4,7,99,0", &[]),
+            ("104,1125899906842624,99", &[]),
+            (SELF_PATCHING, &[]),
+            (COUNTDOWN, &[50]),
+            (DOUBLER, &[21]),
+        ];
+        for (source, inputs) in cases {
+            let mut fast: Vm = Vm::from_source(source).unwrap();
+            let mut slow = fast.clone();
+            fast.add_inputs(inputs);
+            slow.add_inputs(inputs);
+            fast.run().unwrap();
+            let outputs = slow_run(&mut slow);
+            assert_eq!(fast.get_outputs().collect::<Vec<_>>(), outputs, "{}", source);
+            assert_eq!(fast.state(), slow.state(), "{}", source);
+            assert_eq!((fast.pc(), fast.relative_base(), fast.steps_executed()), (slow.pc(), slow.relative_base(), slow.steps_executed()), "{}", source);
+            assert_eq!(fast.diff_memory(&slow), vec![], "{}", source);
+            assert_eq!(fast.memory_len(), slow.memory_len(), "{}", source);
+        }
+    }
+
+    fn any_mode() -> impl Strategy<Value = ParameterMode> {
+        prop_oneof![Just(ParameterMode::Position), Just(ParameterMode::Immediate), Just(ParameterMode::Relative)]
+    }
+
+    fn write_mode() -> impl Strategy<Value = ParameterMode> {
+        prop_oneof![Just(ParameterMode::Position), Just(ParameterMode::Relative)]
+    }
+
+    fn any_opcode() -> impl Strategy<Value = Opcode> {
+        prop_oneof![
+            (any_mode(), any_mode(), write_mode()).prop_map(|(a, b, c)| Opcode::Add(a, b, c)),
+            (any_mode(), any_mode(), write_mode()).prop_map(|(a, b, c)| Opcode::Mul(a, b, c)),
+            write_mode().prop_map(Opcode::Input),
+            any_mode().prop_map(Opcode::Output),
+            (any_mode(), any_mode()).prop_map(|(a, b)| Opcode::JumpIfTrue(a, b)),
+            (any_mode(), any_mode()).prop_map(|(a, b)| Opcode::JumpIfFalse(a, b)),
+            (any_mode(), any_mode(), write_mode()).prop_map(|(a, b, c)| Opcode::LessThan(a, b, c)),
+            (any_mode(), any_mode(), write_mode()).prop_map(|(a, b, c)| Opcode::Equals(a, b, c)),
+            any_mode().prop_map(Opcode::RelativeBaseOffset),
+            Just(Opcode::End),
+        ]
+    }
+
+    fn check_decode(word: i64) -> core::result::Result<(), TestCaseError> {
+        match Opcode::decode(word) {
+            Ok((op, len)) => {
+                prop_assert_eq!(len, op.arity() + 1);
+                prop_assert_eq!(op.modes().len(), op.arity());
+                prop_assert_eq!(op.encode() % 100, word % 100);
+                if op.writes() {
+                    prop_assert_ne!(op.modes().last().copied(), Some(ParameterMode::Immediate));
+                }
+            },
+            Err(VMError::InvalidOpcode { .. }) | Err(VMError::InvalidWriteMode { .. }) => {},
+            Err(e) => prop_assert!(false, "unexpected error {:?} for {}", e, word),
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn decode_any_word(word in any::<i64>()) {
+            check_decode(word)?;
+        }
+
+        #[test]
+        fn decode_small_words(word in -1000i64..100_000) {
+            check_decode(word)?;
+        }
+
+        #[test]
+        fn encode_round_trips(op in any_opcode()) {
+            prop_assert_eq!(Opcode::decode(op.encode()).unwrap(), (op, op.arity() + 1));
+            prop_assert_eq!(Opcode::decode_at(op.encode(), 0, 0, true).unwrap(), op);
+        }
+
+        #[test]
+        fn extra_mode_digits(op in any_opcode(), extra in 1i64..9) {
+            // a digit past the last parameter is ignored, unless decoding is strict
+            let word = op.encode() + extra * 10i64.pow(op.arity() as u32 + 2);
+            prop_assert_eq!(Opcode::decode(word).unwrap().0, op);
+            let strict = Opcode::decode_at(word, 0, 0, true);
+            prop_assert!(matches!(strict, Err(VMError::InvalidOpcode { .. })), "{:?}", strict);
+        }
+    }
+
+    #[test]
+    fn outputs_before_an_error_are_kept() {
+        let mut vm: Vm = Vm::from_source("104,1,104,2,0").unwrap();
+        assert!(matches!(vm.run(), Err(VMError::InvalidOpcode { opcode: 0, pc: 4, .. })));
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![1, 2]);
+        vm.reset();
+        assert!(vm.run_collect().is_err());
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![1, 2]);
+        vm.reset();
+        assert!(vm.run_with_inputs(&[]).is_err());
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![1, 2]);
+        vm.reset();
+        assert!(vm.run_until_n_outputs::<3>().is_err());
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![1, 2]);
+        vm.reset();
+        assert_eq!(vm.run_until_output().unwrap(), Some(1));
+        assert_eq!(vm.run_until_output().unwrap(), Some(2));
+        assert!(vm.run_until_output().is_err());
+    }
+
+    // reads two values and outputs their sum
+    const ADDER: &str = "3,11,3,12,1,11,12,13,4,13,99,0,0,0";
+
+    #[test]
+    fn queue_inspection_and_idleness() {
+        let mut vm: Vm = Vm::from_source(ADDER).unwrap();
+        assert_eq!((vm.pending_inputs(), vm.pending_outputs(), vm.is_idle()), (0, 0, false));
+        assert_eq!(vm.run_until_input_needed().unwrap(), (vec![], VmState::WaitingForInput));
+        assert!(vm.is_idle());
+        vm.add_inputs(&[5]);
+        assert_eq!(vm.pending_inputs(), 1);
+        assert!(!vm.is_idle());
+        vm.run_until_input_needed().unwrap();
+        assert_eq!(vm.pending_inputs(), 0);
+        assert!(vm.is_idle());
+        vm.add_inputs(&[7, 8]);
+        assert_eq!(vm.pending_inputs(), 2);
+        vm.clear_inputs();
+        assert_eq!(vm.pending_inputs(), 0);
+        assert!(vm.is_idle());
+        vm.add_inputs(&[7]);
+        vm.run().unwrap();
+        assert!(!vm.is_idle());
+        assert_eq!((vm.pending_outputs(), vm.peek_output()), (1, Some(12)));
+        assert_eq!((vm.pending_outputs(), vm.peek_output()), (1, Some(12)));
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![12]);
+        assert_eq!((vm.pending_outputs(), vm.peek_output()), (0, None));
+    }
+
+    #[test]
+    fn rewinding_ten_steps_restores_a_pristine_vm() {
+        let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let pristine: Vm = Vm::from_source(quine).unwrap();
+        let mut vm = pristine.clone();
+        vm.enable_rewind(10);
+        for _ in 0..10 {
+            vm.step().unwrap();
+        }
+        assert!(vm != pristine);
+        assert_eq!(vm.pending_outputs(), 2);
+        for _ in 0..10 {
+            vm.step_back().unwrap();
+        }
+        assert!(vm == pristine);
+        assert_eq!(vm.diff_memory(&pristine), vec![]);
+        assert_eq!((vm.steps_executed(), vm.outputs_produced(), vm.pending_outputs()), (0, 0, 0));
+        assert!(matches!(vm.step_back(), Err(VMError::NothingToRewind { pc: 0 })));
+        // and it runs the same from there
+        assert_eq!(vm.run_collect().unwrap(), pristine.clone().run_collect().unwrap());
+    }
+
+    #[test]
+    fn rewinding_io_restores_the_queues() {
+        let mut vm: Vm = Vm::from_source(ADDER).unwrap();
+        vm.enable_rewind(100);
+        vm.add_inputs(&[2, 3]);
+        vm.run().unwrap();
+        assert_eq!((vm.pending_inputs(), vm.peek_output()), (0, Some(5)));
+        // the halt, then the output
+        vm.step_back().unwrap();
+        vm.step_back().unwrap();
+        assert_eq!((vm.pending_outputs(), vm.outputs_produced(), vm.state()), (0, 0, VmState::Running));
+        // the add, then the second input
+        vm.step_back().unwrap();
+        vm.step_back().unwrap();
+        assert_eq!((vm.pending_inputs(), vm.inputs_consumed(), vm.pc()), (1, 1, 2));
+        vm.step_back().unwrap();
+        assert_eq!((vm.pending_inputs(), vm.inputs_consumed(), vm.pc()), (2, 0, 0));
+        vm.clear_inputs();
+        vm.add_inputs(&[10, 20]);
+        assert_eq!(vm.run_collect().unwrap(), vec![30]);
+    }
+
+    #[test]
+    fn the_journal_keeps_only_its_capacity() {
+        let mut vm: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        vm.enable_rewind(3);
+        vm.add_inputs(&[10]);
+        for _ in 0..5 {
+            vm.step().unwrap();
+        }
+        for _ in 0..3 {
+            vm.step_back().unwrap();
+        }
+        assert_eq!(vm.steps_executed(), 2);
+        assert!(matches!(vm.step_back(), Err(VMError::NothingToRewind { .. })));
+    }
+
+    #[test]
+    fn sources_round_trip() {
+        let day2 = include_str!("../../day2/input");
+        let program = parse_program(day2).unwrap();
+        assert_eq!(to_source(&program), day2.trim());
+        assert_eq!(parse_program(&to_source(&program)).unwrap(), program);
+        for source in &["1,-2,0,0", "-9223372036854775808,9223372036854775807", "99"] {
+            assert_eq!(to_source(&parse_program(source).unwrap()), *source);
+        }
+        assert_eq!(to_source::<i64>(&[]), "");
+    }
+
+    #[test]
+    fn dumping_and_diffing_memory() {
+        let before: Vm = Vm::from_source("1,9,10,3,2,3,11,0,99,30,40,50").unwrap();
+        let mut after = before.clone();
+        after.run().unwrap();
+        assert_eq!(after.dump_memory(0..4), vec![3500, 9, 10, 70]);
+        assert_eq!(after.dump_memory(10..14), vec![40, 50, 0, 0]);
+        assert_eq!(after.dump_memory(-2..1), vec![3500]);
+        assert_eq!(after.diff_memory(&before), vec![(0, 3500, 1), (3, 70, 3)]);
+        assert_eq!(before.diff_memory(&after), vec![(0, 1, 3500), (3, 3, 70)]);
+        // a cell written far away shows up against the implicit zero
+        after.write_at(1000, 5).unwrap();
+        assert_eq!(after.diff_memory(&before).last(), Some(&(1000, 5, 0)));
+    }
+
+    #[test]
+    fn running_until_a_matching_output() {
+        let mut vm: Vm = Vm::from_source("104,1,104,2,104,3,104,-1,104,5,99").unwrap();
+        assert_eq!(vm.run_until_output_matching(|o| o < 0).unwrap(), Some(-1));
+        assert_eq!(vm.pending_outputs(), 3);
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(vm.run_until_output_matching(|o| o > 100).unwrap(), None);
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![5]);
+        assert!(!vm.is_running());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn infinite_loops_time_out_resumably() {
+        // counts iterations in [20] forever
+        let mut vm: Vm = Vm::from_source("1001,20,1,20,1105,1,0").unwrap();
+        let steps = match vm.run_with_timeout(Duration::from_millis(50)) {
+            Err(VMError::Timeout { elapsed, steps }) => {
+                assert!(elapsed >= Duration::from_millis(50));
+                steps
+            },
+            other => panic!("expected a timeout, got {:?}", other),
+        };
+        assert_eq!(steps, vm.steps_executed());
+        let iterations = vm.peek_at(20).unwrap() as u64;
+        assert!(iterations > 0);
+        assert_eq!((vm.pc(), steps), if steps % 2 == 0 { (0, 2 * iterations) } else { (4, 2 * iterations - 1) });
+        assert_eq!(vm.state(), VmState::Running);
+        vm.set_timeout_check_interval(1);
+        assert!(matches!(vm.run_with_timeout(Duration::from_millis(5)), Err(VMError::Timeout { .. })));
+        assert!(vm.peek_at(20).unwrap() as u64 > iterations);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn programs_within_the_timeout_are_unaffected() {
+        let mut vm: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        vm.add_inputs(&[1000]);
+        vm.run_with_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(vm.get_outputs().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(vm.steps_executed(), 2003);
+        assert!(!vm.is_running());
+    }
+
+    fn word() -> impl Strategy<Value = i64> {
+        prop_oneof![
+            any::<i64>(),
+            // opcodes with every mode combination
+            (0i64..3, 0i64..3, 0i64..3, prop::sample::select(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 99]))
+                .prop_map(|(a, b, c, op)| ((c * 10 + b) * 10 + a) * 100 + op),
+            // addresses inside and around the program
+            -70i64..70,
+            Just(i64::MAX),
+            Just(i64::MIN),
+        ]
+    }
+
+    fn run_to_any_end(program: Vec<i64>) {
+        let _ = verify::verify(&program);
+        let _ = disasm::disassemble(&program);
+        let mut vm: Vm = Vm::new(program);
+        vm.set_step_limit(Some(5_000));
+        vm.set_memory_limit(Some(1 << 16));
+        vm.add_inputs(&[0, 1, -1, i64::MAX, i64::MIN]);
+        let mut stepped = vm.clone();
+        let _ = vm.clone().run();
+        // stepping and rewinding go through the slow path
+        vm.enable_rewind(64);
+        let _ = vm.run();
+        while vm.step_back().is_ok() {}
+        while let Ok(Event::Continue) | Ok(Event::Output(_)) = stepped.step_event() {}
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_programs_never_panic(program in prop::collection::vec(word(), 0..=64)) {
+            run_to_any_end(program);
+        }
+    }
+
+    #[test]
+    fn absurd_programs_fail_cleanly() {
+        // each program with the variant of the error it stops with
+        let cases = [
+            ("9223372036854775807", "InvalidOpcode"),
+            ("-9223372036854775808", "InvalidOpcode"),
+            ("301,0,0,0,99", "InvalidOpcode"),
+            ("109,-9223372036854775808,109,-1", "ArithmeticOverflow"),
+            ("204,-9223372036854775808", "NegativeRelativeAddress"),
+            ("4,-1", "NegativePositionAddress"),
+            ("1105,1,-5", "InvalidAddress"),
+            ("109,9223372036854775807,21101,1,1,1,99", "ArithmeticOverflow"),
+            ("1102,-9223372036854775808,-1,0,99", "ArithmeticOverflow"),
+            // parameters past the end read as zeros, then the next opcode is a zero
+            ("1", "InvalidOpcode"),
+        ];
+        for &(source, variant) in &cases {
+            let mut vm: Vm = Vm::from_source(source).unwrap();
+            vm.set_step_limit(Some(100));
+            match vm.run() {
+                Err(e) => assert!(format!("{:?}", e).starts_with(variant), "{}: {:?}", source, e),
+                Ok(()) => panic!("{} ran to completion", source),
+            }
+            run_to_any_end(parse_program(source).unwrap());
+        }
+        // a write to the last address allocates a single cell
+        let mut vm: Vm = Vm::from_source("1101,1,1,9223372036854775807,99").unwrap();
+        vm.run().unwrap();
+        assert_eq!((vm.peek_at(i64::MAX).unwrap(), vm.memory_len()), (2, 6));
+    }
+
+    #[test]
+    fn clones_diverge_after_writes() {
+        let mut original: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        let mut clone = original.clone();
+        clone.write_at(12, 5).unwrap();
+        clone.write_at(5_000_000, 6).unwrap();
+        assert_eq!((original.peek_at(12).unwrap(), clone.peek_at(12).unwrap()), (0, 5));
+        assert_eq!(original.diff_memory(&clone), vec![(12, 0, 5), (5_000_000, 0, 6)]);
+        // a clone of the clone sees its writes, and snapshots capture them
+        let second = clone.clone();
+        assert_eq!(second.peek_at(5_000_000).unwrap(), 6);
+        let snapshot = clone.snapshot();
+        assert_eq!((snapshot.memory[12], snapshot.sparse_memory.clone()), (5, vec![(5_000_000, 6)]));
+        assert!(Vm::from_snapshot(snapshot) == clone);
+        // running either leaves the other alone
+        assert_eq!(original.run_with_inputs(&[3]).unwrap(), vec![0]);
+        assert_eq!(clone.peek_at(12).unwrap(), 5);
+        clone.reset();
+        assert_eq!(clone.diff_memory(&Vm::from_source(COUNTDOWN).unwrap()), vec![]);
+        assert_eq!(second.peek_at(12).unwrap(), 5);
+    }
+
+    #[test]
+    fn read_only_runs_keep_the_image_shared() {
+        // moves the relative base back and forth, outputs its first cell and halts
+        let mut vm: Vm = Vm::from_source("109,1,109,-1,4,0,1105,1,10,0,99").unwrap();
+        assert!(vm.memory.shares(&vm.program));
+        assert_eq!(vm.run_collect().unwrap(), vec![109]);
+        assert!(vm.memory.shares(&vm.program));
+        // the first write copies the image, and only for the VM that writes
+        let mut original: Vm = Vm::from_source("1105,1,3,1101,2,3,0,99").unwrap();
+        let mut clone = original.clone();
+        clone.run().unwrap();
+        assert!(original.memory.shares(&original.program));
+        assert!(!clone.memory.shares(&clone.program));
+        assert_eq!((original.peek_at(0).unwrap(), clone.peek_at(0).unwrap()), (1105, 5));
+        original.run().unwrap();
+        assert_eq!(original.peek_at(0).unwrap(), 5);
+    }
+
+    #[test]
+    fn day2_run_summary() {
+        let mut vm: Vm = Vm::from_source("1,9,10,3,2,3,11,0,99,30,40,50").unwrap();
+        let summary = vm.run_with_summary().unwrap();
+        assert_eq!(summary, RunSummary { steps: 3, inputs_consumed: 0, outputs_produced: 0, peak_memory_cells: 12, halted: true });
+        assert_eq!(vm.summary(), summary);
+        vm.reset();
+        assert_eq!(vm.summary(), RunSummary { steps: 0, inputs_consumed: 0, outputs_produced: 0, peak_memory_cells: 12, halted: false });
+    }
+
+    #[test]
+    fn run_summary_tracks_growth_and_io() {
+        let mut vm: Vm = Vm::from_source("1101,1,1,20,99").unwrap();
+        assert_eq!(vm.run_with_summary().unwrap().peak_memory_cells, 21);
+        let mut vm: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        // polled while it waits for input
+        let summary = vm.run_with_summary().unwrap();
+        assert_eq!((summary.steps, summary.inputs_consumed, summary.halted), (0, 0, false));
+        vm.add_inputs(&[2]);
+        let summary = vm.run_with_summary().unwrap();
+        assert_eq!(summary, RunSummary { steps: 7, inputs_consumed: 1, outputs_produced: 1, peak_memory_cells: 13, halted: true });
+        assert_eq!((vm.steps_executed(), vm.inputs_consumed(), vm.outputs_produced()), (7, 1, 1));
+    }
+
+    #[test]
+    fn a_bad_input_destination_keeps_the_input() {
+        let mut vm: Vm = Vm::from_source("3,-1,99").unwrap();
+        vm.add_inputs(&[5]);
+        assert!(matches!(vm.run(), Err(VMError::NegativePositionAddress { addr: -1, pc: 0 })));
+        assert_eq!((vm.pending_inputs(), vm.inputs_consumed()), (1, 0));
+        let mut vm: Vm = Vm::from_source("3,-1,99").unwrap();
+        let calls = Arc::new(core::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        vm.set_input_source(move || {
+            counter.fetch_add(1, core::sync::atomic::Ordering::SeqCst);
+            Some(5)
+        });
+        assert!(vm.run().is_err());
+        assert_eq!(calls.load(core::sync::atomic::Ordering::SeqCst), 0);
+        // waiting for input leaves the pc on the instruction
+        let mut vm: Vm = Vm::from_source("3,5,4,5,99,0").unwrap();
+        assert!(matches!(vm.step_event().unwrap(), Event::NeedsInput));
+        assert_eq!(vm.pc(), 0);
+        vm.add_inputs(&[8]);
+        assert_eq!(vm.run_collect().unwrap(), vec![8]);
+    }
+
+    #[test]
+    fn lenient_decoding_ignores_extra_mode_digits() {
+        let mut vm: Vm = Vm::from_source("11104,7,10099").unwrap();
+        assert_eq!(vm.run_collect().unwrap(), vec![7]);
+        let mut vm: Vm = Vm::from_source("1001101,1,1,5,99,0").unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.peek_at(5).unwrap(), 2);
+    }
+
+    #[test]
+    fn strict_decoding_rejects_extra_mode_digits() {
+        for &(source, opcode) in &[("11104,7,99", 11104), ("104,7,10099", 10099), ("1001101,1,1,5,99,0", 1001101)] {
+            let mut vm: Vm = Vm::from_source(source).unwrap();
+            vm.set_strict_decoding(true);
+            match vm.run() {
+                Err(VMError::InvalidOpcode { opcode: word, .. }) => assert_eq!(word, opcode),
+                other => panic!("{}: expected an invalid opcode, got {:?}", source, other),
+            }
+        }
+        // words with exactly their modes are fine
+        let mut vm: Vm = Vm::from_source("21101,2,3,7,4,7,99,0").unwrap();
+        vm.set_strict_decoding(true);
+        assert_eq!(vm.run_collect().unwrap(), vec![5]);
+    }
+
+    #[test]
+    fn switching_to_strict_decoding_mid_run() {
+        let mut vm: Vm = Vm::from_source("11104,7,1105,1,0").unwrap();
+        assert_eq!(vm.run_until_output().unwrap(), Some(7));
+        assert_eq!(vm.run_until_output().unwrap(), Some(7));
+        // the lenient decoding of the output is cached by now
+        vm.set_strict_decoding(true);
+        assert!(matches!(vm.run_until_output(), Err(VMError::InvalidOpcode { opcode: 11104, pc: 0, .. })));
     }
 }
@@ -0,0 +1,142 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::max;
+
+use crate::IntcodeWord;
+
+const DENSE_CELLS: usize = 1 << 16;
+
+#[derive(Clone, Debug)]
+pub(crate) struct Memory<W> {
+    dense: Arc<Vec<W>>,
+    dense_limit: usize,
+    sparse: BTreeMap<usize, W>,
+}
+
+impl<W: IntcodeWord> Memory<W> {
+    pub(crate) fn new(image: Arc<Vec<W>>) -> Memory<W> {
+        Memory::from_parts(image, Vec::new())
+    }
+    pub(crate) fn from_parts(dense: Arc<Vec<W>>, sparse: Vec<(usize, W)>) -> Memory<W> {
+        let mut memory = Memory {
+            dense_limit: max(dense.len(), DENSE_CELLS),
+            dense,
+            sparse: BTreeMap::new(),
+        };
+        for (idx, val) in sparse {
+            *memory.get_mut(idx) = val;
+        }
+        memory
+    }
+    pub(crate) fn load(&mut self, image: &Arc<Vec<W>>) {
+        self.dense = Arc::clone(image);
+        self.dense_limit = max(image.len(), DENSE_CELLS);
+        self.sparse.clear();
+    }
+    pub(crate) fn get(&self, idx: usize) -> W {
+        if idx < self.dense_limit {
+            self.dense.get(idx).copied().unwrap_or_default()
+        } else {
+            self.sparse.get(&idx).copied().unwrap_or_default()
+        }
+    }
+    pub(crate) fn get_mut(&mut self, idx: usize) -> &mut W {
+        if idx < self.dense_limit {
+            // the first write copies a dense part shared with other VMs
+            let dense = Arc::make_mut(&mut self.dense);
+            if dense.len() <= idx {
+                dense.resize(idx+1, W::default());
+            }
+            &mut dense[idx]
+        } else {
+            self.sparse.entry(idx).or_default()
+        }
+    }
+    // The number of cells a write to `idx` would allocate.
+    pub(crate) fn growth(&self, idx: usize) -> usize {
+        if idx < self.dense_limit {
+            (idx + 1).saturating_sub(self.dense.len())
+        } else {
+            !self.sparse.contains_key(&idx) as usize
+        }
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.dense.len() + self.sparse.len()
+    }
+    pub(crate) fn dense(&self) -> &[W] {
+        &self.dense
+    }
+    #[cfg(test)]
+    pub(crate) fn shares(&self, image: &Arc<Vec<W>>) -> bool {
+        Arc::ptr_eq(&self.dense, image)
+    }
+    pub(crate) fn dense_mut(&mut self) -> &mut [W] {
+        Arc::make_mut(&mut self.dense).as_mut_slice()
+    }
+    pub(crate) fn sparse(&self) -> Vec<(usize, W)> {
+        self.sparse.iter().map(|(&a, &v)| (a, v)).collect()
+    }
+    pub(crate) fn nonzero(&self) -> impl Iterator<Item=(usize, W)> + '_ {
+        self.dense.iter().copied().enumerate()
+            .chain(self.sparse())
+            .filter(|&(_, v)| v != W::default())
+    }
+}
+
+impl<W: IntcodeWord> PartialEq for Memory<W> {
+    fn eq(&self, other: &Memory<W>) -> bool {
+        self.nonzero().eq(other.nonzero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn clones_share_the_image_until_they_write() {
+        let image = Arc::new(vec![1i64, 2, 3]);
+        let mut original = Memory::new(image.clone());
+        let mut clone = original.clone();
+        assert!(Arc::ptr_eq(&original.dense, &image) && Arc::ptr_eq(&clone.dense, &image));
+        *clone.get_mut(1) = 20;
+        assert!(Arc::ptr_eq(&original.dense, &image));
+        assert!(!Arc::ptr_eq(&clone.dense, &image));
+        assert_eq!((original.get(1), clone.get(1)), (2, 20));
+        *original.get_mut(2) = 30;
+        assert_eq!((original.dense(), clone.dense()), (&[1, 2, 30][..], &[1, 20, 3][..]));
+        assert_eq!(*image, vec![1, 2, 3]);
+        // loading the image again shares it again
+        clone.load(&image);
+        assert!(Arc::ptr_eq(&clone.dense, &image));
+        assert_eq!(clone.get(1), 2);
+    }
+
+    #[test]
+    fn sparse_writes_diverge() {
+        let mut original = Memory::new(Arc::new(vec![1i64, 2, 3]));
+        let far = DENSE_CELLS * 4;
+        *original.get_mut(far) = 7;
+        let mut clone = original.clone();
+        *clone.get_mut(far) = 8;
+        *clone.get_mut(far + 1) = 9;
+        assert_eq!((original.get(far), clone.get(far)), (7, 8));
+        assert_eq!((original.get(far + 1), clone.get(far + 1)), (0, 9));
+        assert_eq!((original.len(), clone.len()), (4, 5));
+        assert!(original != clone);
+        *clone.get_mut(far) = 7;
+        *clone.get_mut(far + 1) = 0;
+        // an allocated zero equals an unallocated one
+        assert!(original == clone);
+    }
+
+    #[test]
+    fn growth_counts_new_cells() {
+        let memory = Memory::new(Arc::new(vec![1i64, 2, 3]));
+        assert_eq!((memory.growth(2), memory.growth(3), memory.growth(9)), (0, 1, 7));
+        assert_eq!(memory.growth(DENSE_CELLS * 2), 1);
+    }
+}
@@ -0,0 +1,117 @@
+use alloc::vec::Vec;
+
+use crate::{Result, VMError, Vm, VmState};
+
+pub struct Pipeline {
+    stages: Vec<Vm>,
+    circular: bool,
+}
+
+impl Pipeline {
+    pub fn new(stages: Vec<Vm>) -> Pipeline {
+        Pipeline {
+            stages,
+            circular: false,
+        }
+    }
+    pub fn circular(stages: Vec<Vm>) -> Pipeline {
+        Pipeline {
+            stages,
+            circular: true,
+        }
+    }
+    pub fn stage(&self, i: usize) -> &Vm {
+        &self.stages[i]
+    }
+    pub fn stage_mut(&mut self, i: usize) -> &mut Vm {
+        &mut self.stages[i]
+    }
+    pub fn into_stages(self) -> Vec<Vm> {
+        self.stages
+    }
+    pub fn run_to_completion(&mut self) -> Result<Vec<i64>> {
+        let n = self.stages.len();
+        let mut results = Vec::new();
+        loop {
+            let mut progress = false;
+            for i in 0..n {
+                let vm = &mut self.stages[i];
+                if !vm.is_running() {
+                    continue;
+                }
+                let before = vm.steps_executed();
+                let (outputs, _) = vm.run_until_input_needed()?;
+                progress |= vm.steps_executed() != before;
+                if i + 1 < n {
+                    self.stages[i + 1].add_inputs(&outputs);
+                } else {
+                    if self.circular {
+                        self.stages[0].add_inputs(&outputs);
+                    }
+                    results.extend(outputs);
+                }
+            }
+            if self.stages.iter().all(|vm| vm.state() == VmState::Stopped) {
+                return Ok(results);
+            }
+            if !progress {
+                let stages = self.stages.iter()
+                    .enumerate()
+                    .filter(|(_, vm)| vm.is_running())
+                    .map(|(i, _)| i)
+                    .collect();
+                return Err(VMError::Deadlock { stages });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    const SERIES: &str = "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0";
+    const FEEDBACK: &str = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+    const FEEDBACK_2: &str = "3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,\
+        -5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,\
+        53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10";
+
+    fn amplifiers(program: &str, phases: &[i64]) -> Vec<Vm> {
+        phases.iter().map(|&phase| {
+            let mut vm: Vm = Vm::from_source(program).unwrap();
+            vm.add_inputs(&[phase]);
+            vm
+        }).collect()
+    }
+
+    #[test]
+    fn series_example() {
+        let mut stages = amplifiers(SERIES, &[4, 3, 2, 1, 0]);
+        stages[0].add_inputs(&[0]);
+        assert_eq!(Pipeline::new(stages).run_to_completion().unwrap(), vec![43210]);
+    }
+
+    #[test]
+    fn feedback_examples() {
+        let mut stages = amplifiers(FEEDBACK, &[9, 8, 7, 6, 5]);
+        stages[0].add_inputs(&[0]);
+        let outputs = Pipeline::circular(stages).run_to_completion().unwrap();
+        assert_eq!(outputs.last(), Some(&139629729));
+        let mut stages = amplifiers(FEEDBACK_2, &[9, 7, 8, 5, 6]);
+        stages[0].add_inputs(&[0]);
+        let outputs = Pipeline::circular(stages).run_to_completion().unwrap();
+        assert_eq!(outputs.last(), Some(&18216));
+    }
+
+    #[test]
+    fn deadlocks_are_reported() {
+        // without the initial 0 every amplifier waits for the previous one
+        let stages = amplifiers(FEEDBACK, &[9, 8, 7, 6, 5]);
+        match Pipeline::circular(stages).run_to_completion() {
+            Err(VMError::Deadlock { stages }) => assert_eq!(stages, vec![0, 1, 2, 3, 4]),
+            other => panic!("expected a deadlock, got {:?}", other),
+        }
+    }
+}
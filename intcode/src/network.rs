@@ -0,0 +1,250 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Result, VMError, Vm};
+
+pub const NAT_ADDRESS: i64 = 255;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NetEvent {
+    Packet {
+        from: usize,
+        to: i64,
+        x: i64,
+        y: i64,
+    },
+    NatDelivery {
+        x: i64,
+        y: i64,
+    },
+}
+
+pub struct Network {
+    vms: Vec<Vm>,
+    pending: Vec<Vec<i64>>,
+    nat: Option<(i64, i64)>,
+    on_nat_packet: Option<Box<dyn FnMut(i64, i64)>>,
+    idle_rounds: usize,
+    quiet: usize,
+}
+
+impl Network {
+    pub fn new(program: &[i64], count: usize) -> Network {
+        let vms = (0..count).map(|addr| {
+            let mut vm = Vm::new(program.to_vec());
+            vm.add_inputs(&[addr as i64]);
+            vm
+        }).collect();
+        Network {
+            vms,
+            pending: vec![Vec::new(); count],
+            nat: None,
+            on_nat_packet: None,
+            idle_rounds: 2,
+            quiet: 0,
+        }
+    }
+    pub fn on_nat_packet<F: FnMut(i64, i64) + 'static>(&mut self, f: F) {
+        self.on_nat_packet = Some(Box::new(f));
+    }
+    pub fn set_idle_rounds(&mut self, rounds: usize) {
+        self.idle_rounds = rounds.max(1);
+    }
+    pub fn vm(&self, addr: usize) -> &Vm {
+        &self.vms[addr]
+    }
+    pub fn run_until<F: FnMut(&NetEvent) -> bool>(&mut self, mut stop: F) -> Result<NetEvent> {
+        loop {
+            if !self.vms.iter().any(Vm::is_running) {
+                return Err(VMError::NetworkHalted);
+            }
+            let mut idle = true;
+            for addr in 0..self.vms.len() {
+                let vm = &mut self.vms[addr];
+                if !vm.is_running() {
+                    continue;
+                }
+                if vm.pending_inputs() == 0 {
+                    vm.add_inputs(&[-1]);
+                } else {
+                    idle = false;
+                }
+                let (outputs, _) = vm.run_until_input_needed()?;
+                self.pending[addr].extend(outputs);
+                let packets: Vec<i64> = self.pending[addr].drain(..).collect();
+                let mut chunks = packets.chunks_exact(3);
+                for packet in &mut chunks {
+                    idle = false;
+                    let event = NetEvent::Packet { from: addr, to: packet[0], x: packet[1], y: packet[2] };
+                    self.route(addr, packet[0], packet[1], packet[2])?;
+                    if stop(&event) {
+                        return Ok(event);
+                    }
+                }
+                self.pending[addr].extend_from_slice(chunks.remainder());
+            }
+            self.quiet = if idle { self.quiet + 1 } else { 0 };
+            if self.quiet >= self.idle_rounds {
+                self.quiet = 0;
+                // nothing but the NAT can wake an idle network up
+                let (x, y) = match self.nat {
+                    Some(packet) => packet,
+                    None => {
+                        let stages = self.vms.iter()
+                            .enumerate()
+                            .filter(|(_, vm)| vm.is_running())
+                            .map(|(i, _)| i)
+                            .collect();
+                        return Err(VMError::Deadlock { stages });
+                    },
+                };
+                self.vms[0].add_inputs(&[x, y]);
+                let event = NetEvent::NatDelivery { x, y };
+                if stop(&event) {
+                    return Ok(event);
+                }
+            }
+        }
+    }
+    fn route(&mut self, from: usize, to: i64, x: i64, y: i64) -> Result<()> {
+        if to == NAT_ADDRESS {
+            self.nat = Some((x, y));
+            if let Some(f) = &mut self.on_nat_packet {
+                f(x, y);
+            }
+            return Ok(());
+        }
+        match self.vms.get_mut(to as usize) {
+            Some(vm) if to >= 0 => {
+                vm.add_inputs(&[x, y]);
+                Ok(())
+            },
+            _ => Err(VMError::UnroutablePacket { from, to }),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    // Machine 0 starts by sending (7, 8) to machine 1. From then on, machine 0
+    // forwards every packet to 1, and the others send it to the NAT with y + 1.
+    const RELAY: &str = "
+            in [addr]
+            eq [addr], #0, [first]
+            jz [first], #loop
+            out #1
+            out #7
+            out #8
+        loop:
+            in [x]
+            eq [x], #-1, [idle]
+            jnz [idle], #loop
+            in [y]
+            jz [first], #other
+            out #1
+            out [x]
+            out [y]
+            jz #0, #loop
+        other:
+            add [y], #1, [y]
+            out #255
+            out [x]
+            out [y]
+            jz #0, #loop
+        addr: .data 0
+        first: .data 0
+        x: .data 0
+        y: .data 0
+        idle: .data 0
+    ";
+
+    fn relay() -> Vec<i64> {
+        crate::asm::assemble(RELAY).unwrap()
+    }
+
+    #[test]
+    fn routes_packets_to_the_nat() {
+        let mut network = Network::new(&relay(), 2);
+        let first = network.run_until(|e| matches!(e, NetEvent::Packet { to: NAT_ADDRESS, .. })).unwrap();
+        assert_eq!(first, NetEvent::Packet { from: 1, to: NAT_ADDRESS, x: 7, y: 9 });
+    }
+
+    #[test]
+    fn the_nat_wakes_an_idle_network() {
+        let mut network = Network::new(&relay(), 3);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let hook = Rc::clone(&seen);
+        network.on_nat_packet(move |x, y| hook.borrow_mut().push((x, y)));
+        let mut events = Vec::new();
+        let last = network.run_until(|&e| {
+            events.push(e);
+            e == NetEvent::NatDelivery { x: 7, y: 11 }
+        }).unwrap();
+        assert_eq!(last, NetEvent::NatDelivery { x: 7, y: 11 });
+        assert_eq!(events, vec![
+            NetEvent::Packet { from: 0, to: 1, x: 7, y: 8 },
+            NetEvent::Packet { from: 1, to: NAT_ADDRESS, x: 7, y: 9 },
+            NetEvent::NatDelivery { x: 7, y: 9 },
+            NetEvent::Packet { from: 0, to: 1, x: 7, y: 9 },
+            NetEvent::Packet { from: 1, to: NAT_ADDRESS, x: 7, y: 10 },
+            NetEvent::NatDelivery { x: 7, y: 10 },
+            NetEvent::Packet { from: 0, to: 1, x: 7, y: 10 },
+            NetEvent::Packet { from: 1, to: NAT_ADDRESS, x: 7, y: 11 },
+            NetEvent::NatDelivery { x: 7, y: 11 },
+        ]);
+        assert_eq!(*seen.borrow(), vec![(7, 9), (7, 10), (7, 11)]);
+        // machine 2 only ever got -1
+        assert!(network.vm(2).is_idle());
+    }
+
+    #[test]
+    fn unknown_addresses_are_an_error() {
+        let mut network = Network::new(&relay(), 1);
+        assert!(matches!(network.run_until(|_| false), Err(VMError::UnroutablePacket { from: 0, to: 1 })));
+    }
+
+    #[test]
+    fn halted_networks_are_an_error() {
+        let mut network = Network::new(&[99], 2);
+        assert!(matches!(network.run_until(|_| false), Err(VMError::NetworkHalted)));
+    }
+
+    #[test]
+    fn idle_networks_without_a_nat_packet_are_a_deadlock() {
+        // every machine reads forever without ever sending anything
+        let mut network = Network::new(&[3, 5, 1105, 1, 0, 0], 3);
+        match network.run_until(|_| false) {
+            Err(VMError::Deadlock { stages }) => assert_eq!(stages, vec![0, 1, 2]),
+            other => panic!("expected a deadlock, got {:?}", other),
+        }
+        // machine 1 halts once it has sent (1, 2) to machine 0, which never answers
+        let mut network = Network::new(&crate::asm::assemble("
+                in [addr]
+                jz [addr], #read
+                out #0
+                out #1
+                out #2
+                halt
+            read:
+                in [addr]
+                jz #0, #read
+            addr: .data 0
+        ").unwrap(), 2);
+        let mut events = Vec::new();
+        let result = network.run_until(|&e| {
+            events.push(e);
+            false
+        });
+        assert_eq!(events, vec![NetEvent::Packet { from: 1, to: 0, x: 1, y: 2 }]);
+        match result {
+            Err(VMError::Deadlock { stages }) => assert_eq!(stages, vec![0]),
+            other => panic!("expected a deadlock, got {:?}", other),
+        }
+    }
+}
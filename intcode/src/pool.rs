@@ -0,0 +1,62 @@
+use rayon::prelude::*;
+
+use crate::Vm;
+
+// `f` can fail with any error type, so jobs can report more than VM errors.
+pub fn run_batch<I, J, R, E, F>(program: &[i64], jobs: I, f: F) -> std::result::Result<Vec<R>, E>
+where
+    I: IntoIterator<Item=J>,
+    J: Send,
+    R: Send,
+    E: Send,
+    F: Fn(&mut Vm, J) -> std::result::Result<R, E> + Sync,
+{
+    let jobs: Vec<J> = jobs.into_iter().collect();
+    jobs.into_par_iter()
+        .map_init(|| Vm::new(program.to_vec()), |vm, job| {
+            vm.reset();
+            f(vm, job)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Result;
+
+    const DAY2: &str = "1,0,0,3,1,1,2,3,1,3,4,3,1,5,0,3,2,1,10,19,1,19,5,23,99";
+
+    #[test]
+    fn results_match_the_serial_path() {
+        let program = crate::parse_program(DAY2).unwrap();
+        let jobs: Vec<(i64, i64)> = (0..20).flat_map(|noun| (0..20).map(move |verb| (noun, verb))).collect();
+        let run = |vm: &mut Vm, (noun, verb): (i64, i64)| {
+            vm.write_at(1, noun)?;
+            vm.write_at(2, verb)?;
+            vm.run()?;
+            vm.peek_at(0)
+        };
+        let parallel = run_batch(&program, jobs.clone(), run).unwrap();
+        let mut vm = Vm::new(program);
+        let serial: Vec<i64> = jobs.into_iter().map(|job| {
+            vm.reset();
+            run(&mut vm, job)
+        }).collect::<Result<_>>().unwrap();
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn the_first_error_is_returned() {
+        let program = crate::parse_program("3,0,4,0,99").unwrap();
+        let result = run_batch(&program, 0..100, |vm, i| {
+            if i != 50 {
+                vm.add_inputs(&[i]);
+            }
+            vm.run_collect()
+        });
+        assert!(matches!(result, Err(crate::VMError::NoMoreInput { .. })));
+        let outputs = run_batch(&program, 0..100, |vm, i| vm.run_with_inputs(&[i])).unwrap();
+        assert_eq!(outputs, (0..100).map(|i| vec![i]).collect::<Vec<_>>());
+    }
+}
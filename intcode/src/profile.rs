@@ -0,0 +1,269 @@
+use alloc::format;
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+use core::str::FromStr;
+
+use crate::{Opcode, Result, VMError, DEFAULT_MEMORY_LIMIT};
+
+const KINDS: [&str; 10] = [
+    "Add",
+    "Mul",
+    "Input",
+    "Output",
+    "JumpIfTrue",
+    "JumpIfFalse",
+    "LessThan",
+    "Equals",
+    "RelativeBaseOffset",
+    "End",
+];
+
+fn kind(op: Opcode) -> usize {
+    match op {
+        Opcode::Add(..) => 0,
+        Opcode::Mul(..) => 1,
+        Opcode::Input(..) => 2,
+        Opcode::Output(..) => 3,
+        Opcode::JumpIfTrue(..) => 4,
+        Opcode::JumpIfFalse(..) => 5,
+        Opcode::LessThan(..) => 6,
+        Opcode::Equals(..) => 7,
+        Opcode::RelativeBaseOffset(..) => 8,
+        Opcode::End => 9,
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Profile {
+    counts: [u64; 10],
+    pub steps: u64,
+    pub memory_growths: u64,
+    pub peak_memory: usize,
+    pub inputs: u64,
+    pub outputs: u64,
+    hits: Vec<u64>,
+    covered: Vec<bool>,
+}
+
+impl Profile {
+    pub(crate) fn new(memory_len: usize, program_len: usize) -> Profile {
+        Profile {
+            peak_memory: memory_len,
+            hits: vec![0; program_len],
+            covered: vec![false; program_len],
+            ..Profile::default()
+        }
+    }
+    pub(crate) fn record(&mut self, pc: i64, op: Opcode, memory_len: usize) {
+        let pc = pc as usize;
+        if let Some(hits) = self.hits.get_mut(pc) {
+            *hits += 1;
+            let end = (pc + 1 + op.arity()).min(self.covered.len());
+            for c in &mut self.covered[pc..end] {
+                *c = true;
+            }
+        }
+        self.counts[kind(op)] += 1;
+        self.steps += 1;
+        match op {
+            Opcode::Input(..) => self.inputs += 1,
+            Opcode::Output(..) => self.outputs += 1,
+            _ => {},
+        }
+        if memory_len > self.peak_memory {
+            self.memory_growths += 1;
+            self.peak_memory = memory_len;
+        }
+    }
+    pub fn count(&self, kind: &str) -> u64 {
+        KINDS.iter().position(|&k| k == kind).map_or(0, |i| self.counts[i])
+    }
+    pub fn coverage(&self) -> Coverage {
+        Coverage {
+            hits: self.hits.clone(),
+            covered: self.covered.clone(),
+        }
+    }
+    pub fn counts(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<_> = KINDS.iter().copied().zip(self.counts.iter().copied()).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<20}{:>12}", "opcode", "count")?;
+        for (kind, count) in self.counts() {
+            writeln!(f, "{:<20}{:>12}", kind, count)?;
+        }
+        writeln!(f, "steps: {}", self.steps)?;
+        writeln!(f, "inputs: {}, outputs: {}", self.inputs, self.outputs)?;
+        write!(f, "memory growths: {}, peak memory: {} cells", self.memory_growths, self.peak_memory)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Coverage {
+    hits: Vec<u64>,
+    covered: Vec<bool>,
+}
+
+impl Coverage {
+    #[cfg(feature = "std")]
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Coverage> {
+        std::fs::read_to_string(path)?.parse()
+    }
+    #[cfg(feature = "std")]
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        Ok(std::fs::write(path, self.to_string())?)
+    }
+    pub fn hits(&self, addr: usize) -> u64 {
+        self.hits.get(addr).copied().unwrap_or(0)
+    }
+    pub fn hottest(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut hot: Vec<_> = self.hits.iter().copied().enumerate().filter(|&(_, h)| h > 0).collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot.truncate(n);
+        hot
+    }
+    pub fn unexecuted(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for (addr, _) in self.covered.iter().enumerate().filter(|(_, &c)| !c) {
+            match ranges.last_mut() {
+                Some(r) if r.end == addr => r.end += 1,
+                _ => ranges.push(addr..addr+1),
+            }
+        }
+        ranges
+    }
+    pub fn annotate(&self, disasm: &str) -> String {
+        disasm.lines()
+            .map(|line| {
+                let addr = line.split(':').next().and_then(|addr| addr.trim().parse().ok());
+                // label lines have no address and get no marker
+                match addr.map(|addr| self.hits(addr)) {
+                    Some(0) => format!("{:>10}  {}\n", ".", line),
+                    Some(h) => format!("{:>10}  {}\n", h, line),
+                    None => format!("{:>10}  {}\n", "", line),
+                }
+            })
+            .collect()
+    }
+}
+
+// One `addr hits` line per covered word; parameter words have 0 hits.
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, hits) in self.hits.iter().enumerate().filter(|&(addr, _)| self.covered[addr]) {
+            writeln!(f, "{} {}", addr, hits)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Coverage {
+    type Err = VMError;
+    fn from_str(s: &str) -> Result<Coverage> {
+        let mut coverage = Coverage { hits: Vec::new(), covered: Vec::new() };
+        for (idx, text) in s.lines().enumerate() {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let mut parts = text.split_whitespace();
+            let (addr, hits): (usize, u64) = match (parts.next().map(str::parse), parts.next().map(str::parse), parts.next()) {
+                // a program can't run code past the memory a VM allows by default
+                (Some(Ok(addr)), Some(Ok(hits)), None) if addr < DEFAULT_MEMORY_LIMIT => (addr, hits),
+                _ => return Err(VMError::InvalidCoverage { line: idx + 1, text: text.into() }),
+            };
+            if coverage.hits.len() <= addr {
+                coverage.hits.resize(addr + 1, 0);
+                coverage.covered.resize(addr + 1, false);
+            }
+            coverage.hits[addr] = hits;
+            coverage.covered[addr] = true;
+        }
+        Ok(coverage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::Vm;
+
+    // counts its input down to zero, then outputs the zero
+    const COUNTDOWN: &str = "3,12,1001,12,-1,12,1005,12,2,4,12,99,0";
+
+    #[test]
+    fn exact_opcode_counts() {
+        let mut vm: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        vm.enable_profiling();
+        vm.run_with_inputs(&[5]).unwrap();
+        let profile = vm.profile();
+        assert_eq!(profile.count("Input"), 1);
+        assert_eq!(profile.count("Add"), 5);
+        assert_eq!(profile.count("JumpIfTrue"), 5);
+        assert_eq!(profile.count("Output"), 1);
+        assert_eq!(profile.count("End"), 1);
+        assert_eq!(profile.count("Mul"), 0);
+        assert_eq!(profile.count("Bogus"), 0);
+        assert_eq!((profile.steps, profile.inputs, profile.outputs), (13, 1, 1));
+        assert_eq!(profile.counts()[..3], [("Add", 5), ("JumpIfTrue", 5), ("End", 1)]);
+    }
+
+    #[test]
+    fn memory_growth() {
+        let mut vm: Vm = Vm::from_source("1101,1,1,15,1101,1,1,20,1101,1,1,5,99").unwrap();
+        vm.enable_profiling();
+        vm.run().unwrap();
+        assert_eq!((vm.profile().memory_growths, vm.profile().peak_memory), (2, 21));
+        let table = vm.profile().to_string();
+        assert!(table.starts_with("opcode                     count\nAdd                            3\n"), "{}", table);
+        assert!(table.ends_with("steps: 4\ninputs: 0, outputs: 0\nmemory growths: 2, peak memory: 21 cells"), "{}", table);
+    }
+
+    #[test]
+    fn disabled_profiling_records_nothing() {
+        let mut vm: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        vm.run_with_inputs(&[5]).unwrap();
+        assert_eq!(vm.profile().steps, 0);
+        vm.enable_profiling();
+        vm.disable_profiling();
+        vm.reset();
+        vm.run_with_inputs(&[5]).unwrap();
+        assert_eq!(vm.profile().count("Add"), 0);
+    }
+
+    // decrements [14] from 100 to 0 with a no-op multiplication in between
+    const LOOP: &str = "1001,14,-1,14,1002,15,1,15,1005,14,0,99,7,7,100,0";
+
+    #[test]
+    fn loop_coverage() {
+        let mut vm: Vm = Vm::from_source(LOOP).unwrap();
+        vm.enable_profiling();
+        vm.run().unwrap();
+        let coverage = vm.coverage();
+        assert_eq!(coverage.hottest(3), [(0, 100), (4, 100), (8, 100)]);
+        assert_eq!(coverage.hottest(10).len(), 4);
+        assert_eq!((coverage.hits(11), coverage.hits(1), coverage.hits(1000)), (1, 0, 0));
+        assert_eq!(coverage.unexecuted(), vec![12..16]);
+        let program: Vec<i64> = LOOP.split(',').map(|w| w.parse().unwrap()).collect();
+        let annotated = coverage.annotate(&crate::disasm::disassemble(&program));
+        assert_eq!(annotated, concat!(
+            "       100  0000: ADD  [14], #-1 -> [14]\n",
+            "       100  0004: MUL  [15], #1 -> [15]\n",
+            "       100  0008: JNZ  [14], #0\n",
+            "         1  0011: HALT\n",
+            "         .  0012: LT   [7], [100] -> [0]\n",
+        ));
+    }
+}
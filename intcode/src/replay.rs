@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+use crate::{Event, Result, VMError, Vm};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IoEvent {
+    Input(i64),
+    Output(i64),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Recording {
+    pub events: Vec<IoEvent>,
+}
+
+impl Recording {
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Recording> {
+        std::fs::read_to_string(path)?.parse()
+    }
+    pub fn inputs(&self) -> Vec<i64> {
+        self.events.iter().filter_map(|e| match e {
+            IoEvent::Input(i) => Some(*i),
+            IoEvent::Output(_) => None,
+        }).collect()
+    }
+    pub fn outputs(&self) -> Vec<i64> {
+        self.events.iter().filter_map(|e| match e {
+            IoEvent::Output(o) => Some(*o),
+            IoEvent::Input(_) => None,
+        }).collect()
+    }
+}
+
+impl FromStr for Recording {
+    type Err = VMError;
+    fn from_str(s: &str) -> Result<Recording> {
+        let events = s.lines()
+            .enumerate()
+            .filter(|(_, text)| !text.trim().is_empty())
+            .map(|(idx, text)| {
+                let invalid = || VMError::InvalidRecording { line: idx + 1, text: text.to_owned() };
+                let mut parts = text.split_whitespace();
+                let (tag, value) = (parts.next(), parts.next().and_then(|v| v.parse().ok()));
+                match (tag, value, parts.next()) {
+                    (Some("in"), Some(v), None) => Ok(IoEvent::Input(v)),
+                    (Some("out"), Some(v), None) => Ok(IoEvent::Output(v)),
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(Recording { events })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Divergence {
+    pub index: usize,
+    pub expected: Option<i64>,
+    pub got: Option<i64>,
+    pub pc: i64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ReplayReport {
+    pub matched: usize,
+    pub divergence: Option<Divergence>,
+}
+
+impl ReplayReport {
+    pub fn is_clean(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+pub fn replay(program: Vec<i64>, recording: &Recording) -> Result<ReplayReport> {
+    let expected = recording.outputs();
+    let mut vm = Vm::new(program);
+    vm.add_inputs(&recording.inputs());
+    let mut index = 0;
+    loop {
+        let pc = vm.pc();
+        match vm.step_event()? {
+            Event::Continue => {},
+            Event::Output(o) => {
+                if expected.get(index) != Some(&o) {
+                    let divergence = Divergence { index, expected: expected.get(index).copied(), got: Some(o), pc };
+                    return Ok(ReplayReport { matched: index, divergence: Some(divergence) });
+                }
+                index += 1;
+            },
+            Event::Halted | Event::NeedsInput => {
+                let divergence = expected.get(index).map(|&e| Divergence { index, expected: Some(e), got: None, pc: vm.pc() });
+                return Ok(ReplayReport { matched: index, divergence });
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // outputs the running sum of its inputs
+    const SUMMER: &str = "3,100,1,100,101,101,4,101,1105,1,0";
+
+    fn record(name: &str, inputs: &[i64]) -> Recording {
+        let path = std::env::temp_dir().join(format!("intcode-{}-{}.txt", name, std::process::id()));
+        let mut vm: Vm = Vm::from_source(SUMMER).unwrap();
+        vm.record_io(&path).unwrap();
+        vm.add_inputs(inputs);
+        vm.run_until_input_needed().unwrap();
+        vm.stop_recording().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let recording = Recording::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text, "in 1\nout 1\nin 2\nout 3\nin 3\nout 6\n");
+        recording
+    }
+
+    fn program() -> Vec<i64> {
+        SUMMER.split(',').map(|w| w.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn clean_replay() {
+        let recording = record("clean", &[1, 2, 3]);
+        assert_eq!(recording.inputs(), vec![1, 2, 3]);
+        assert_eq!(recording.outputs(), vec![1, 3, 6]);
+        let report = replay(program(), &recording).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.matched, 3);
+    }
+
+    #[test]
+    fn patched_program_diverges() {
+        let recording = record("patched", &[1, 2, 3]);
+        let mut patched = program();
+        // output the last input instead of the sum
+        patched[7] = 100;
+        let report = replay(patched, &recording).unwrap();
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.divergence, Some(Divergence { index: 1, expected: Some(3), got: Some(2), pc: 6 }));
+    }
+
+    #[test]
+    fn missing_outputs_diverge() {
+        let mut recording = record("missing", &[1, 2, 3]);
+        recording.events.push(IoEvent::Output(10));
+        let report = replay(program(), &recording).unwrap();
+        assert_eq!(report.matched, 3);
+        assert_eq!(report.divergence, Some(Divergence { index: 3, expected: Some(10), got: None, pc: 0 }));
+    }
+
+    #[test]
+    fn invalid_recordings() {
+        assert_eq!("in 1\n\nout -2\n".parse::<Recording>().unwrap().events, vec![IoEvent::Input(1), IoEvent::Output(-2)]);
+        for text in &["in", "in x", "out 1 2", "put 3"] {
+            assert!(matches!(text.parse::<Recording>(), Err(VMError::InvalidRecording { line: 1, .. })), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::str::FromStr;
+
+use crate::{Result, VMError};
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SymbolTable {
+    names: BTreeMap<i64, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+    #[cfg(feature = "std")]
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<SymbolTable> {
+        std::fs::read_to_string(path)?.parse()
+    }
+    pub fn insert(&mut self, addr: i64, name: &str) {
+        self.names.insert(addr, name.into());
+    }
+    pub fn name(&self, addr: i64) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+    pub fn addr_or_name(&self, addr: i64) -> String {
+        match self.name(addr) {
+            Some(name) => name.into(),
+            None => addr.to_string(),
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item=(i64, &str)> + '_ {
+        self.names.iter().map(|(&addr, name)| (addr, name.as_str()))
+    }
+}
+
+impl FromStr for SymbolTable {
+    type Err = VMError;
+    fn from_str(s: &str) -> Result<SymbolTable> {
+        let mut table = SymbolTable::new();
+        for (idx, text) in s.lines().enumerate() {
+            let entry = text.split(';').next().unwrap_or("").trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let mut parts = entry.split_whitespace();
+            match (parts.next().and_then(|a| a.parse().ok()), parts.next(), parts.next()) {
+                (Some(addr), Some(name), None) => table.insert(addr, name),
+                _ => return Err(VMError::InvalidSymbol { line: idx + 1, text: text.into() }),
+            }
+        }
+        Ok(table)
+    }
+}
+
+pub struct Symbolized<'a, T> {
+    pub(crate) item: &'a T,
+    pub(crate) symbols: &'a SymbolTable,
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::disasm::{disassemble, disassemble_with_symbols};
+    use crate::{parse_program, Vm};
+
+    // counts [10] down from 2, then outputs it
+    const COUNTDOWN: &str = "1001,10,-1,10,1005,10,0,4,10,99,2";
+    const SYMBOLS: &str = "; the countdown\n0 main_loop\n10 counter   ; starts at 2\n\n";
+
+    fn load(name: &str) -> SymbolTable {
+        let path = std::env::temp_dir().join(format!("intcode-{}-{}.txt", name, std::process::id()));
+        std::fs::write(&path, SYMBOLS).unwrap();
+        let symbols = SymbolTable::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        symbols
+    }
+
+    #[test]
+    fn loading_a_symbol_file() {
+        let symbols = load("symbols");
+        assert_eq!(symbols.iter().collect::<Vec<_>>(), vec![(0, "main_loop"), (10, "counter")]);
+        assert_eq!((symbols.addr_or_name(10), symbols.addr_or_name(11)), ("counter".to_string(), "11".to_string()));
+        assert!(matches!("0 a b".parse::<SymbolTable>(), Err(VMError::InvalidSymbol { line: 1, .. })));
+        assert!(matches!("\nmain 0".parse::<SymbolTable>(), Err(VMError::InvalidSymbol { line: 2, .. })));
+    }
+
+    #[test]
+    fn symbolized_listing() {
+        let program = parse_program(COUNTDOWN).unwrap();
+        assert_eq!(disassemble(&program), "\
+0000: ADD  [10], #-1 -> [10]
+0004: JNZ  [10], #0
+0007: OUT  [10]
+0009: HALT
+0010: DATA 2
+");
+        assert_eq!(disassemble_with_symbols(&program, &load("listing")), "\
+main_loop:
+0000: ADD  [counter], #-1 -> [counter]
+0004: JNZ  [counter], main_loop
+0007: OUT  [counter]
+0009: HALT
+counter:
+0010: DATA 2
+");
+    }
+
+    #[test]
+    fn symbolized_trace() {
+        let symbols = Arc::new(load("trace"));
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut vm: Vm = Vm::from_source(COUNTDOWN).unwrap();
+        let (hook_symbols, hook_lines) = (symbols.clone(), lines.clone());
+        vm.set_trace_hook(Box::new(move |event| {
+            hook_lines.lock().unwrap().push(event.with_symbols(&hook_symbols).to_string());
+        }));
+        vm.run().unwrap();
+        assert_eq!(*lines.lock().unwrap(), vec![
+            "[main_loop] Add(pos, imm, pos) 2 -1 -> [counter] (was 2)",
+            "[4] JumpIfTrue(pos, imm) 1 0",
+            "[main_loop] Add(pos, imm, pos) 1 -1 -> [counter] (was 1)",
+            "[4] JumpIfTrue(pos, imm) 0 0",
+            "[7] Output(pos) 0",
+            "[9] End",
+        ]);
+    }
+}
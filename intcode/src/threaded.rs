@@ -0,0 +1,78 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::{Event, Result, VMError, Vm};
+
+pub fn spawn(mut vm: Vm) -> (Sender<i64>, Receiver<i64>, JoinHandle<Result<Vm>>) {
+    let (input_tx, input_rx) = channel();
+    let (output_tx, output_rx) = channel();
+    let handle = thread::spawn(move || {
+        loop {
+            match vm.step_event()? {
+                Event::Continue => {},
+                Event::Output(o) => {
+                    // a vanished downstream is not an error for this VM
+                    let _ = output_tx.send(o);
+                },
+                Event::NeedsInput => match input_rx.recv() {
+                    Ok(i) => vm.add_inputs(&[i]),
+                    Err(_) => {
+                        return Err(VMError::NoMoreInput { pc: vm.pc(), rb: vm.relative_base() });
+                    },
+                },
+                Event::Halted => {return Ok(vm);},
+            }
+        }
+    });
+    (input_tx, output_rx, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEEDBACK: &str = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+
+    #[test]
+    fn threaded_feedback_ring() {
+        let amps: Vec<_> = [9, 8, 7, 6, 5].iter().map(|&phase| {
+            let mut vm = Vm::from_source(FEEDBACK).unwrap();
+            vm.add_inputs(&[phase]);
+            spawn(vm)
+        }).collect();
+        let inputs: Vec<Sender<i64>> = amps.iter().map(|(tx, _, _)| tx.clone()).collect();
+        inputs[0].send(0).unwrap();
+        let mut forwarders = Vec::new();
+        let mut handles = Vec::new();
+        for (i, (tx, rx, handle)) in amps.into_iter().enumerate() {
+            drop(tx);
+            handles.push(handle);
+            let next = inputs[(i + 1) % inputs.len()].clone();
+            forwarders.push(thread::spawn(move || {
+                let mut last = None;
+                // ends when the amplifier halts and drops its sender
+                for o in rx {
+                    last = Some(o);
+                    let _ = next.send(o);
+                }
+                last
+            }));
+        }
+        drop(inputs);
+        let last: Vec<_> = forwarders.into_iter().map(|f| f.join().unwrap()).collect();
+        assert_eq!(last[4], Some(139629729));
+        for handle in handles {
+            assert!(!handle.join().unwrap().unwrap().is_running());
+        }
+    }
+
+    #[test]
+    fn errors_come_back_through_the_handle() {
+        let (tx, rx, handle) = spawn(Vm::from_source("3,0,4,0,3,0,99").unwrap());
+        tx.send(5).unwrap();
+        assert_eq!(rx.recv().unwrap(), 5);
+        drop(tx);
+        assert!(matches!(handle.join().unwrap(), Err(VMError::NoMoreInput { pc: 4, .. })));
+        assert!(rx.recv().is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::Write;
+
+use crate::{ascii_char, IntcodeWord};
+
+pub(crate) struct Transcript {
+    writer: Box<dyn Write + Send>,
+    line: String,
+    // whether the program's output ended its last line
+    at_line_start: bool,
+}
+
+impl Transcript {
+    pub(crate) fn new(writer: Box<dyn Write + Send>) -> Transcript {
+        Transcript { writer, line: String::new(), at_line_start: true }
+    }
+    // like the recorder, a failing transcript must not stop the program
+    pub(crate) fn output<W: IntcodeWord>(&mut self, value: W) {
+        let _ = match ascii_char(value) {
+            Some(c) => {
+                self.at_line_start = c == '\n';
+                write!(self.writer, "{}", c)
+            },
+            None => {
+                self.at_line_start = true;
+                writeln!(self.writer, "{}", value)
+            },
+        };
+    }
+    pub(crate) fn input<W: IntcodeWord>(&mut self, value: W) {
+        match ascii_char(value) {
+            Some('\n') => {
+                let line = core::mem::take(&mut self.line);
+                self.command(&line);
+            },
+            Some(c) => self.line.push(c),
+            None => self.command(&value.to_string()),
+        }
+    }
+    // commands go on their own lines, or a replayed transcript would miss them
+    fn command(&mut self, command: &str) {
+        if !self.at_line_start {
+            let _ = writeln!(self.writer);
+            self.at_line_start = true;
+        }
+        let _ = writeln!(self.writer, "> {}", command);
+    }
+    pub(crate) fn flush(&mut self) {
+        // a program may stop before reading the rest of a command
+        if !self.line.is_empty() {
+            let line = core::mem::take(&mut self.line);
+            self.command(&line);
+        }
+        let _ = self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{VMError, Vm};
+
+    // echoes its input up to and including a `.`, then outputs 1000
+    const ECHO: &str = "3,100,4,100,1008,100,46,101,1006,101,0,104,1000,99";
+
+    fn transcript_of(name: &str, source: &str, script: &str) -> (Vm, Result<(), VMError>, String) {
+        let path = std::env::temp_dir().join(format!("intcode-{}-{}.txt", name, std::process::id()));
+        let mut vm: Vm = Vm::from_source(source).unwrap();
+        vm.set_transcript(std::fs::File::create(&path).unwrap());
+        vm.add_ascii_script(script).unwrap();
+        let result = vm.run();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        (vm, result, text)
+    }
+
+    #[test]
+    fn echo_transcript() {
+        let (mut vm, result, text) = transcript_of("echo", ECHO, "hi\n\nthere.\n");
+        result.unwrap();
+        // commands start on a line of their own, the last one is flushed on halt without its unread newline
+        assert_eq!(text, "hi\n> hi\n\nthere.1000\n> there.\n");
+        let first = vm.drain_ascii_output();
+        // the transcript replays the typed commands
+        let mut replayed: Vm = Vm::from_source(ECHO).unwrap();
+        replayed.add_ascii_script(&text).unwrap();
+        replayed.run().unwrap();
+        assert_eq!(replayed.drain_ascii_output(), first);
+    }
+
+    #[test]
+    fn transcripts_are_flushed_on_errors() {
+        // echoes one character and fails on opcode 0
+        let (_, result, text) = transcript_of("error", "3,100,4,100,0", "go\n");
+        assert!(matches!(result, Err(VMError::InvalidOpcode { opcode: 0, pc: 4, .. })));
+        assert_eq!(text, "g\n> g\n");
+    }
+}
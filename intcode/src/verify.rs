@@ -0,0 +1,290 @@
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+use crate::{Opcode, ParameterMode, VMError};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Issue {
+    InvalidOpcode(i64),
+    ImmediateWrite(i64),
+    TruncatedInstruction,
+    FallsOffEnd,
+    JumpOutOfProgram(i64),
+    DynamicJump,
+    NoHalt,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    pub addr: usize,
+    pub severity: Severity,
+    pub issue: Issue,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::InvalidOpcode(word) => write!(f, "`{}` is not a valid opcode", word),
+            Issue::ImmediateWrite(word) => write!(f, "`{}` writes through an immediate mode parameter", word),
+            Issue::TruncatedInstruction => write!(f, "the instruction runs past the end of the program"),
+            Issue::FallsOffEnd => write!(f, "execution falls off the end of the program"),
+            Issue::JumpOutOfProgram(target) => write!(f, "jump to {} outside the program", target),
+            Issue::DynamicJump => write!(f, "the jump target is only known at runtime"),
+            Issue::NoHalt => write!(f, "no path from here reaches a halt"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}: {}: {}", self.addr, self.severity, self.issue)
+    }
+}
+
+struct Walk {
+    diagnostics: Vec<Diagnostic>,
+    successors: Vec<Option<Vec<usize>>>,
+    halts: Vec<bool>,
+    patched: Vec<bool>,
+}
+
+// Follows every reachable instruction, treating position mode conditions that read
+// words outside `patched` as the constants they are in the program image.
+fn walk(program: &[i64], patched: &[bool]) -> Walk {
+    let len = program.len();
+    let mut diagnostics = Vec::new();
+    let mut report = |addr, severity, issue| diagnostics.push(Diagnostic { addr, severity, issue });
+    let mut successors: Vec<Option<Vec<usize>>> = vec![None; len];
+    // dead ends that already have an error or leave the analysis (dynamic jumps)
+    // count as halting so that they aren't reported twice
+    let mut halts = vec![false; len];
+    let mut writes = vec![false; len];
+    let mut undecodable = Vec::new();
+    let mut pending = vec![0];
+    while let Some(addr) = pending.pop() {
+        if addr >= len || successors[addr].is_some() {
+            continue;
+        }
+        let (op, size) = match Opcode::decode(program[addr]) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                match e {
+                    VMError::InvalidWriteMode { .. } => report(addr, Severity::Error, Issue::ImmediateWrite(program[addr])),
+                    _ => undecodable.push(addr),
+                }
+                successors[addr] = Some(Vec::new());
+                halts[addr] = true;
+                continue;
+            },
+        };
+        if addr + size > len {
+            report(addr, Severity::Error, Issue::TruncatedInstruction);
+            successors[addr] = Some(Vec::new());
+            halts[addr] = true;
+            continue;
+        }
+        let params = &program[addr+1..addr+size];
+        if op.writes() && op.modes().last() == Some(&ParameterMode::Position) {
+            if let Some(dst) = params.last().and_then(|&dst| usize::try_from(dst).ok()).filter(|&dst| dst < len) {
+                writes[dst] = true;
+            }
+        }
+        let mut next = Vec::new();
+        let mut falls_through = true;
+        match op {
+            Opcode::End => {
+                halts[addr] = true;
+                falls_through = false;
+            },
+            Opcode::JumpIfTrue(cond, target) | Opcode::JumpIfFalse(cond, target) => {
+                let jumps_when = matches!(op, Opcode::JumpIfTrue(..));
+                let constant = match cond {
+                    ParameterMode::Immediate => Some(params[0]),
+                    ParameterMode::Position => usize::try_from(params[0]).ok()
+                        .filter(|&at| at < len && !patched[at])
+                        .map(|at| program[at]),
+                    ParameterMode::Relative => None,
+                };
+                // a constant condition makes the jump either unconditional or dead
+                let always = constant.map(|c| (c != 0) == jumps_when);
+                falls_through = always != Some(true);
+                if always != Some(false) {
+                    match (target, usize::try_from(params[1])) {
+                        (ParameterMode::Immediate, Ok(t)) if t < len => next.push(t),
+                        (ParameterMode::Immediate, _) => {
+                            report(addr, Severity::Error, Issue::JumpOutOfProgram(params[1]));
+                            halts[addr] = true;
+                        },
+                        _ => {
+                            report(addr, Severity::Warning, Issue::DynamicJump);
+                            halts[addr] = true;
+                        },
+                    }
+                }
+            },
+            _ => {},
+        }
+        if falls_through {
+            if addr + size < len {
+                next.push(addr + size);
+            } else {
+                report(addr, Severity::Error, Issue::FallsOffEnd);
+                halts[addr] = true;
+            }
+        }
+        pending.extend(next.iter().copied());
+        successors[addr] = Some(next);
+    }
+    for addr in undecodable {
+        // a word the program overwrites before reaching it may be fine at runtime
+        let severity = if writes[addr] { Severity::Warning } else { Severity::Error };
+        report(addr, severity, Issue::InvalidOpcode(program[addr]));
+    }
+    Walk { diagnostics, successors, halts, patched: writes }
+}
+
+pub fn verify(program: &[i64]) -> Vec<Diagnostic> {
+    let len = program.len();
+    // assuming fewer patched words can only hide branches, so iterate until the
+    // words the reachable code writes are exactly the ones assumed
+    let mut patched = vec![false; len];
+    let Walk { mut diagnostics, successors, mut halts, .. } = loop {
+        let walk = walk(program, &patched);
+        if walk.patched.iter().zip(&patched).all(|(&found, &assumed)| assumed || !found) {
+            break walk;
+        }
+        for (assumed, found) in patched.iter_mut().zip(&walk.patched) {
+            *assumed |= found;
+        }
+    };
+
+    let mut predecessors = vec![Vec::new(); len];
+    for (addr, next) in successors.iter().enumerate() {
+        for &n in next.iter().flatten() {
+            predecessors[n].push(addr);
+        }
+    }
+    let mut queue: VecDeque<usize> = (0..len).filter(|&addr| halts[addr]).collect();
+    while let Some(addr) = queue.pop_front() {
+        for &p in &predecessors[addr] {
+            if !halts[p] {
+                halts[p] = true;
+                queue.push_back(p);
+            }
+        }
+    }
+    // report only where a path leaves the part of the program that can still halt
+    let mut entered = vec![false; len];
+    if len > 0 && !halts[0] {
+        entered[0] = true;
+    }
+    for (addr, next) in successors.iter().enumerate() {
+        if halts[addr] {
+            for &n in next.iter().flatten() {
+                entered[n] |= !halts[n];
+            }
+        }
+    }
+    for addr in (0..len).filter(|&addr| entered[addr]) {
+        diagnostics.push(Diagnostic { addr, severity: Severity::Warning, issue: Issue::NoHalt });
+    }
+    diagnostics.sort_by_key(|d| d.addr);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::parse_program;
+
+    fn check(source: &str) -> Vec<(usize, Severity, Issue)> {
+        verify(&parse_program(source).unwrap()).into_iter().map(|d| (d.addr, d.severity, d.issue)).collect()
+    }
+
+    #[test]
+    fn clean_program() {
+        assert_eq!(check("1,9,10,3,2,3,11,0,99,30,40,50"), vec![]);
+    }
+
+    #[test]
+    fn invalid_opcodes() {
+        assert_eq!(check("1,0,0,0,42"), vec![(4, Severity::Error, Issue::InvalidOpcode(42))]);
+        // the add turns the 42 into a halt before it runs
+        assert_eq!(check("1101,99,0,4,42"), vec![(4, Severity::Warning, Issue::InvalidOpcode(42))]);
+    }
+
+    #[test]
+    fn immediate_writes() {
+        assert_eq!(check("11101,1,1,0,99"), vec![(0, Severity::Error, Issue::ImmediateWrite(11101))]);
+    }
+
+    #[test]
+    fn truncated_instructions() {
+        assert_eq!(check("1,0,0"), vec![(0, Severity::Error, Issue::TruncatedInstruction)]);
+    }
+
+    #[test]
+    fn falling_off_the_end() {
+        assert_eq!(check("104,1"), vec![(0, Severity::Error, Issue::FallsOffEnd)]);
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(check("1105,1,50,99"), vec![(0, Severity::Error, Issue::JumpOutOfProgram(50))]);
+        assert_eq!(check("1105,1,-1,99"), vec![(0, Severity::Error, Issue::JumpOutOfProgram(-1))]);
+        assert_eq!(check("105,1,4,99,3"), vec![(0, Severity::Warning, Issue::DynamicJump)]);
+        // a constant false condition never jumps
+        assert_eq!(check("1106,1,50,99"), vec![]);
+    }
+
+    #[test]
+    fn loops_without_a_halt() {
+        assert_eq!(check("1105,1,0"), vec![(0, Severity::Warning, Issue::NoHalt)]);
+        // only the branch that spins forever is reported
+        assert_eq!(check("3,10,1005,10,6,99,1105,1,6,0,0"), vec![(6, Severity::Warning, Issue::NoHalt)]);
+    }
+
+    #[test]
+    fn display() {
+        let diagnostic = verify(&[1, 0, 0, 0, 42])[0];
+        assert_eq!(diagnostic.to_string(), "0004: error: `42` is not a valid opcode");
+    }
+
+    #[test]
+    fn real_inputs_only_warn() {
+        let inputs = [
+            include_str!("../../day2/input"),
+            include_str!("../../day5/input"),
+            include_str!("../../day7/input"),
+            include_str!("../../day9/input"),
+            include_str!("../../day11/input"),
+            include_str!("../../day13/input"),
+        ];
+        for input in &inputs {
+            let program = parse_program(input).unwrap();
+            for diagnostic in verify(&program) {
+                assert_eq!(diagnostic.severity, Severity::Warning, "{}", diagnostic);
+            }
+        }
+    }
+}
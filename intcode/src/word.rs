@@ -0,0 +1,121 @@
+use core::convert::TryFrom;
+use core::fmt;
+use core::hash::Hash;
+use core::str::FromStr;
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for i64 {}
+    impl Sealed for i128 {}
+}
+
+#[cfg(feature = "serde")]
+pub trait SerdeWord: serde::Serialize + serde::de::DeserializeOwned {}
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> SerdeWord for T {}
+#[cfg(not(feature = "serde"))]
+pub trait SerdeWord {}
+#[cfg(not(feature = "serde"))]
+impl<T> SerdeWord for T {}
+
+pub trait IntcodeWord:
+    sealed::Sealed + SerdeWord + Copy + Default + Ord + Hash + fmt::Debug + fmt::Display + FromStr + Send + Sync + 'static
+{
+    fn from_i64(value: i64) -> Self;
+    fn to_i64(self) -> Option<i64>;
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+
+    fn to_usize(self) -> Option<usize> {
+        self.to_i64().and_then(|v| usize::try_from(v).ok())
+    }
+    // used for error reports, which always carry i64 values
+    fn saturating_i64(self) -> i64 {
+        self.to_i64().unwrap_or(if self < Self::default() { i64::MIN } else { i64::MAX })
+    }
+}
+
+impl IntcodeWord for i64 {
+    fn from_i64(value: i64) -> i64 {
+        value
+    }
+    fn to_i64(self) -> Option<i64> {
+        Some(self)
+    }
+    fn checked_add(self, rhs: i64) -> Option<i64> {
+        i64::checked_add(self, rhs)
+    }
+    fn checked_mul(self, rhs: i64) -> Option<i64> {
+        i64::checked_mul(self, rhs)
+    }
+}
+
+impl IntcodeWord for i128 {
+    fn from_i64(value: i64) -> i128 {
+        i128::from(value)
+    }
+    fn to_i64(self) -> Option<i64> {
+        i64::try_from(self).ok()
+    }
+    fn checked_add(self, rhs: i128) -> Option<i128> {
+        i128::checked_add(self, rhs)
+    }
+    fn checked_mul(self, rhs: i128) -> Option<i128> {
+        i128::checked_mul(self, rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::{VMError, Vm};
+
+    const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+    const COMPARE_TO_8: &str = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+
+    #[test]
+    fn i64_and_i128_agree() {
+        for &(source, input) in &[(QUINE, None), (COMPARE_TO_8, Some(7)), (COMPARE_TO_8, Some(8)), (COMPARE_TO_8, Some(9))] {
+            let mut narrow: Vm<i64> = Vm::from_source(source).unwrap();
+            let mut wide: Vm<i128> = Vm::from_source(source).unwrap();
+            if let Some(input) = input {
+                narrow.add_inputs(&[input]);
+                wide.add_inputs(&[i128::from(input)]);
+            }
+            let narrow: Vec<i128> = narrow.run_collect().unwrap().into_iter().map(i128::from).collect();
+            assert_eq!(narrow, wide.run_collect().unwrap(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn i128_survives_an_i64_overflow() {
+        // (2^62 - 1) * 4, then plus 3
+        let source = "1102,4611686018427387903,4,11,1001,11,3,11,4,11,99,0";
+        let mut narrow: Vm<i64> = Vm::from_source(source).unwrap();
+        assert!(matches!(narrow.run(), Err(VMError::ArithmeticOverflow { op: "mul", pc: 0, .. })));
+        let mut wide: Vm<i128> = Vm::from_source(source).unwrap();
+        assert_eq!(wide.run_collect().unwrap(), vec![18_446_744_073_709_551_615]);
+    }
+
+    #[test]
+    fn wide_literals() {
+        let mut wide: Vm<i128> = Vm::from_source("104,170141183460469231731687303715884105727,99").unwrap();
+        assert_eq!(wide.run_collect().unwrap(), vec![i128::MAX]);
+        assert!(Vm::<i64>::from_source("104,170141183460469231731687303715884105727,99").is_err());
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(i128::from_i64(-5), -5);
+        assert_eq!((1i128 << 70).to_i64(), None);
+        assert_eq!((-1i64).to_usize(), None);
+        assert_eq!(7i128.to_usize(), Some(7));
+        assert_eq!((1i128 << 70).saturating_i64(), i64::MAX);
+        assert_eq!((-1i128 << 70).saturating_i64(), i64::MIN);
+        assert_eq!(i64::MAX.checked_add(1), None);
+        assert_eq!(IntcodeWord::checked_mul(1i128 << 62, 4), Some(1i128 << 64));
+    }
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "std")]
+
+use assert_cmd::Command;
+
+fn session(script: &str) -> String {
+    let output = Command::cargo_bin("icdb").unwrap()
+        .arg("tests/data/countdown.ic")
+        .write_stdin(script)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn scripted_session() {
+    let script = "\
+break 6
+in 2
+run
+regs
+print 10..13
+step
+print 12
+run
+run
+out
+dis 0 3
+restart
+regs
+quit
+";
+    assert_eq!(session(script), "\
+(icdb) breakpoint at 6
+(icdb) (icdb) breakpoint at 6
+0006: JNZ  [12], #2
+(icdb) pc: 6, rb: 0, state: Running
+(icdb) 0010: 12
+0011: 99
+0012: 1
+(icdb) 0002: ADD  [12], #-1 -> [12]
+(icdb) 0012: 1
+(icdb) breakpoint at 6
+0006: JNZ  [12], #2
+(icdb) halted
+(icdb) 0
+(icdb) 0000: IN   -> [12]
+0002: ADD  [12], #-1 -> [12]
+0006: JNZ  [12], #2
+(icdb) restarted
+(icdb) pc: 0, rb: 0, state: Running
+(icdb) ");
+}
+
+#[test]
+fn bad_addresses_and_ranges() {
+    let script = "\
+print -1
+break -5
+print 0..5000
+print 7..3
+print 9223372036854775807
+dis 9223372036854775807 2
+dis 0 0
+dis 0 99999999999
+step
+";
+    assert_eq!(session(script), "\
+(icdb) error: invalid address `-1`
+(icdb) error: invalid address `-5`
+(icdb) error: print shows at most 1000 cells
+(icdb) (icdb) 9223372036854775807: 0
+(icdb) 9223372036854775807: DATA 0
+(icdb) error: dis shows between 1 and 1000 instructions
+(icdb) error: dis shows between 1 and 1000 instructions
+(icdb) waiting for input
+(icdb) ");
+}
+
+#[test]
+fn unknown_commands_print_help() {
+    let transcript = session("bogus\nregs\n");
+    assert!(transcript.starts_with("(icdb) commands:\n"));
+    assert!(transcript.ends_with("(icdb) pc: 0, rb: 0, state: Running\n(icdb) "));
+}
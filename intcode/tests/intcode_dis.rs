@@ -0,0 +1,55 @@
+#![cfg(feature = "std")]
+
+use assert_cmd::Command;
+
+// Runs intcode-dis and compares its listing with a golden file in tests/data.
+fn golden(args: &[&str], expected: &str) {
+    let output = Command::cargo_bin("intcode-dis").unwrap().args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = std::fs::read_to_string(format!("tests/data/{}", expected)).unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn day2_example() {
+    golden(&["tests/data/day2.ic"], "day2.dis");
+}
+
+#[test]
+fn data_between_code() {
+    golden(&["tests/data/mixed.ic"], "mixed.dis");
+}
+
+#[test]
+fn symbols_and_coverage() {
+    // mixed.cov comes from `intcode-run tests/data/mixed.ic --coverage tests/data/mixed.cov`
+    golden(&["tests/data/mixed.ic", "--symbols", "tests/data/mixed.sym", "--coverage", "tests/data/mixed.cov"], "mixed.annotated.dis");
+}
+
+#[test]
+fn a_window_of_the_listing() {
+    Command::cargo_bin("intcode-dis").unwrap()
+        .args(["tests/data/mixed.ic", "--start", "5", "--count", "2"])
+        .assert()
+        .success()
+        .stdout("0005: ADD  [3], #1 -> [3]\n0009: OUT  [3]\n");
+}
+
+#[test]
+fn garbage_is_listed_as_data() {
+    // the 99999 is a halt with ignored mode digits, the add at the end is cut short
+    Command::cargo_bin("intcode-dis").unwrap()
+        .arg("tests/data/garbage.ic")
+        .assert()
+        .success()
+        .stdout("0000: DATA 0\n0001: DATA -1\n0002: DATA 123456789\n0003: HALT\n0004: DATA 1101\n0005: DATA 1\n");
+}
+
+#[test]
+fn unparsable_files_fail() {
+    Command::cargo_bin("intcode-dis").unwrap()
+        .arg("tests/data/mixed.dis")
+        .assert()
+        .code(1)
+        .stdout("");
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "std")]
+
+use assert_cmd::Command;
+use predicates::str::contains;
+
+fn intcode_run() -> Command {
+    Command::cargo_bin("intcode-run").unwrap()
+}
+
+#[test]
+fn numeric_inputs_and_outputs() {
+    intcode_run()
+        .args(["tests/data/countdown.ic", "--input", "3"])
+        .assert()
+        .success()
+        .stdout("0\n")
+        .stderr("");
+    intcode_run()
+        .args(["tests/data/adder.ic", "--input", "6,7"])
+        .assert()
+        .success()
+        .stdout("13\n");
+}
+
+#[test]
+fn patches_apply_before_the_run() {
+    // the add becomes a multiplication
+    intcode_run()
+        .args(["tests/data/adder.ic", "--input", "6,7", "--patch", "4=2"])
+        .assert()
+        .success()
+        .stdout("42\n");
+}
+
+#[test]
+fn ascii_mode_reads_stdin() {
+    intcode_run()
+        .args(["tests/data/echo.ic", "--ascii"])
+        .write_stdin("hi\nthere.\n")
+        .assert()
+        .success()
+        .stdout("hi\nthere.\n1000\n");
+}
+
+#[test]
+fn trace_and_profile_go_to_stderr() {
+    let output = intcode_run()
+        .args(["tests/data/adder.ic", "--input", "6,7", "--trace", "--profile"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "13\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("\
+[0] Input(pos) -> [11] (was 0)
+[2] Input(pos) -> [12] (was 0)
+[4] Add(pos, pos, pos) 6 7 -> [13] (was 0)
+[8] Output(pos) 13
+[10] End
+opcode                     count
+Input                          2
+"), "{}", stderr);
+    assert!(stderr.ends_with("steps: 5\ninputs: 2, outputs: 1\nmemory growths: 0, peak memory: 14 cells\n"), "{}", stderr);
+}
+
+#[test]
+fn vm_errors_fail_with_the_message() {
+    intcode_run()
+        .arg("tests/data/countdown.ic")
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr("error: The VM is waiting for input at pc 0 (relative base 0), but none is available\n");
+    intcode_run()
+        .args(["tests/data/countdown.ic", "--input", "3", "--steps-limit", "5"])
+        .assert()
+        .code(1)
+        .stderr("error: Step limit exceeded after 5 steps at pc 2\n");
+}
+
+#[test]
+fn bad_arguments() {
+    intcode_run().arg("--bogus").assert().code(1).stderr(contains("unknown option `--bogus`\nusage: intcode-run <program>"));
+    intcode_run().args(["tests/data/adder.ic", "--patch", "4"]).assert().code(1).stderr("error: invalid patch `4`, expected addr=value\n");
+    intcode_run().args(["tests/data/adder.ic", "--input"]).assert().code(1).stderr("error: --input needs a value\n");
+    intcode_run().arg("tests/data/missing.ic").assert().code(1).stderr(contains("error: cannot read tests/data/missing.ic"));
+}
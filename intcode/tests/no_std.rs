@@ -0,0 +1,16 @@
+use std::process::Command;
+
+// Without `std` the crate only needs `core` and `alloc`, so it must build with no dependencies at all.
+#[test]
+fn builds_without_std() {
+    let target_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/target/no-std-check");
+    let output = Command::new(env!("CARGO"))
+        .args(["check", "--offline", "--lib", "--no-default-features", "--manifest-path"])
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .env("CARGO_TARGET_DIR", target_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("warning"), "{}", stderr);
+}
@@ -0,0 +1,35 @@
+#![cfg(feature = "trace-log")]
+
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use intcode::Vm;
+
+struct Capture(Mutex<Vec<String>>);
+
+impl Log for Capture {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Debug
+    }
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+    fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+// what RUST_LOG=debug turns on with env_logger
+#[test]
+fn steps_are_logged_at_debug_level() {
+    log::set_logger(&CAPTURE).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+    let mut vm: Vm = Vm::from_source("1101,2,3,5,99,0").unwrap();
+    vm.step().unwrap();
+    let lines = CAPTURE.0.lock().unwrap().clone();
+    assert_eq!(lines.first().map(String::as_str), Some("[0] stepping"));
+    assert!(lines.iter().any(|l| l.starts_with("[1] executing Add(")), "{:?}", lines);
+}
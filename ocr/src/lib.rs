@@ -0,0 +1,164 @@
+// Reads the capital letters the 2019 puzzles draw: 6 pixels tall, 4 wide (Y is 5),
+// with a blank column between letters, so every letter fits a 5 pixel wide cell.
+pub const HEIGHT: usize = 6;
+pub const CELL_WIDTH: usize = 5;
+
+const FONT: [(char, [&str; HEIGHT]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+pub struct Reading {
+    // unrecognized letters read as '?'
+    pub text: String,
+    // the art of each unrecognized letter, in order
+    pub unknown: Vec<String>,
+}
+
+fn cell_art(rows: &[Vec<bool>], left: isize) -> String {
+    let lit = |row: &Vec<bool>, x: isize| x >= 0 && row.get(x as usize) == Some(&true);
+    let mut art = String::new();
+    for row in rows {
+        art.extend((left..left + CELL_WIDTH as isize).map(|x| if lit(row, x) { '#' } else { '.' }));
+        art.push('\n');
+    }
+    art
+}
+
+fn letter(art: &str) -> Option<char> {
+    FONT.iter()
+        .find(|(_, glyph)| {
+            art.lines().zip(glyph.iter()).all(|(row, pattern)| {
+                row.chars().zip(pattern.chars().chain(std::iter::repeat('.'))).all(|(a, b)| a == b)
+            })
+        })
+        .map(|&(c, _)| c)
+}
+
+fn read_cells(rows: &[Vec<bool>], first: isize, right: isize) -> Reading {
+    let mut reading = Reading { text: String::new(), unknown: Vec::new() };
+    let mut cell = first;
+    while cell < right {
+        let art = cell_art(rows, cell);
+        match letter(&art).filter(|_| rows.len() == HEIGHT) {
+            Some(c) => reading.text.push(c),
+            None => {
+                reading.text.push('?');
+                reading.unknown.push(art);
+            },
+        }
+        cell += CELL_WIDTH as isize;
+    }
+    reading
+}
+
+// Reads the letters in `rows`, a bitmap with true for lit pixels. Blank rows around the
+// text are skipped, and so are blank columns before it, although a letter like I starts
+// with one: the alignment of the cells that recognizes the most letters wins.
+pub fn read(rows: &[Vec<bool>]) -> Reading {
+    let lit = |row: &Vec<bool>| row.iter().any(|&p| p);
+    let top = rows.iter().position(lit).unwrap_or(rows.len());
+    let bottom = rows.iter().rposition(lit).map_or(top, |b| b + 1);
+    let rows = &rows[top..bottom];
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let lit_column = |x: usize| rows.iter().any(|row| row.get(x) == Some(&true));
+    let left = (0..width).find(|&x| lit_column(x)).unwrap_or(width) as isize;
+    let right = (0..width).rev().find(|&x| lit_column(x)).map_or(left, |r| r as isize + 1);
+    (0..CELL_WIDTH as isize)
+        .map(|shift| read_cells(rows, left - shift, right))
+        .min_by_key(|reading| reading.unknown.len())
+        .expect("CELL_WIDTH is not 0")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap(rows: &[&str]) -> Vec<Vec<bool>> {
+        rows.iter().map(|row| row.chars().map(|c| c == '#').collect()).collect()
+    }
+
+    #[test]
+    fn every_glyph_reads_back() {
+        for &(c, glyph) in &FONT {
+            let reading = read(&bitmap(&glyph));
+            assert_eq!(reading.text, c.to_string());
+            assert!(reading.unknown.is_empty());
+        }
+    }
+
+    #[test]
+    fn a_line_of_letters() {
+        let rows = bitmap(&[
+            ".##..###..#..#.####.",
+            "#..#.#..#.#.#.....#.",
+            "#..#.###..##.....#..",
+            "####.#..#.#.#...#...",
+            "#..#.#..#.#.#..#....",
+            "#..#.###..#..#.####.",
+        ]);
+        assert_eq!(read(&rows).text, "ABKZ");
+    }
+
+    #[test]
+    fn blank_margins_are_skipped() {
+        // I starts with a blank column, and Y is a pixel wider than the other letters
+        let rows = bitmap(&[
+            "..............",
+            "...###.#...#..",
+            "....#..#...#..",
+            "....#...#.#...",
+            "....#....#....",
+            "....#....#....",
+            "...###...#....",
+            "..............",
+        ]);
+        assert_eq!(read(&rows).text, "IY");
+    }
+
+    #[test]
+    fn unknown_letters() {
+        let rows = bitmap(&[
+            "#..#.#...#",
+            "#..#.##.##",
+            "####.#.#.#",
+            "#..#.#...#",
+            "#..#.#...#",
+            "#..#.#...#",
+        ]);
+        let reading = read(&rows);
+        assert_eq!(reading.text, "H?");
+        assert_eq!(reading.unknown, ["#...#\n##.##\n#.#.#\n#...#\n#...#\n#...#\n"]);
+    }
+
+    #[test]
+    fn letters_need_six_rows() {
+        let reading = read(&bitmap(&["####", "#...", "###.", "#...", "####"]));
+        assert_eq!(reading.text, "?");
+        assert_eq!(reading.unknown.len(), 1);
+    }
+
+    #[test]
+    fn nothing_to_read() {
+        let reading = read(&bitmap(&["....", "...."]));
+        assert_eq!(reading.text, "");
+        assert!(reading.unknown.is_empty());
+        assert_eq!(read(&[]).text, "");
+    }
+}